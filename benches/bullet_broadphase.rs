@@ -0,0 +1,229 @@
+#![allow(dead_code)]
+
+// Compares the bullet-vs-enemy broad phases as the enemy count grows: the old per-group
+// bounding boxes that loop over every enemy, the spatial hash asked once per bullet, and the
+// spatial hash asked once per run of bullets, which is what the bullets use now.
+//
+// cargo bench --bench bullet_broadphase
+
+#[path = "../src/aabb.rs"]
+mod aabb;
+#[path = "../src/geom.rs"]
+mod geom;
+#[path = "../src/spatial_hash.rs"]
+mod spatial_hash;
+
+use aabb::Aabb;
+use geom::distance_between_line_segments;
+use glam::{vec3, Vec3};
+use spatial_hash::SpatialHash;
+use std::time::{Duration, Instant};
+
+const ENEMY_COUNTS: [usize; 8] = [20, 50, 100, 200, 500, 1000, 5000, 10000];
+const ARENA_HALF_SIZE: f32 = 40.0;
+const SPREAD_AMOUNT: usize = 20;
+const BULLETS_PER_SHOT: usize = SPREAD_AMOUNT * SPREAD_AMOUNT;
+const SHOTS: usize = 10; // live bullet groups, one second of firing
+const SUB_GROUPS: usize = 9;
+const ITERATIONS: u32 = 200;
+
+// same sizes as the game
+const BULLET_HALF_HEIGHT: f32 = 0.15;
+const BULLET_RADIUS: f32 = 0.03;
+const ENEMY_HALF_HEIGHT: f32 = 0.2;
+const ENEMY_RADIUS: f32 = 0.08;
+const MAX_COLLISION_DIST: f32 = BULLET_HALF_HEIGHT + BULLET_RADIUS + ENEMY_HALF_HEIGHT + ENEMY_RADIUS;
+const GRID_CELL_SIZE: f32 = 2.0;
+const RUN_LEN: usize = 16; // divides a shot, so no run straddles two
+
+struct Enemy {
+    position: Vec3,
+    dir: Vec3,
+}
+
+struct Bullets {
+    positions: Vec<Vec3>,
+    directions: Vec<Vec3>,
+}
+
+// xorshift, so runs are repeatable without a dependency
+struct Random(u32);
+
+impl Random {
+    fn next(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        self.0 as f32 / u32::MAX as f32
+    }
+
+    fn in_arena(&mut self) -> f32 {
+        (self.next() * 2.0 - 1.0) * ARENA_HALF_SIZE
+    }
+}
+
+fn make_enemies(count: usize, random: &mut Random) -> Vec<Enemy> {
+    (0..count)
+        .map(|_| {
+            let theta = random.next() * std::f32::consts::TAU;
+            Enemy {
+                position: vec3(random.in_arena(), 0.5, random.in_arena()),
+                dir: vec3(theta.sin(), 0.0, theta.cos()),
+            }
+        })
+        .collect()
+}
+
+// Shots fanned out from points around the arena, each bullet a little further along than the last shot's.
+fn make_bullets(random: &mut Random) -> Bullets {
+    let mut positions = vec![];
+    let mut directions = vec![];
+
+    for shot in 0..SHOTS {
+        let origin = vec3(random.in_arena() * 0.5, 0.5, random.in_arena() * 0.5);
+        let aim = random.next() * std::f32::consts::TAU;
+        let travelled = shot as f32 * 1.5;
+
+        for i in 0..BULLETS_PER_SHOT {
+            let spread = (i as f32 / BULLETS_PER_SHOT as f32 - 0.5) * 0.5;
+            let direction = vec3((aim + spread).sin(), 0.0, (aim + spread).cos());
+            positions.push(origin + direction * travelled);
+            directions.push(direction);
+        }
+    }
+
+    Bullets { positions, directions }
+}
+
+fn collides(bullets: &Bullets, bullet_index: usize, enemy: &Enemy) -> bool {
+    let position = bullets.positions[bullet_index];
+    if position.distance(enemy.position) > MAX_COLLISION_DIST {
+        return false;
+    }
+    let direction = bullets.directions[bullet_index];
+    let a0 = position - direction * BULLET_HALF_HEIGHT;
+    let a1 = position + direction * BULLET_HALF_HEIGHT;
+    let b0 = enemy.position - enemy.dir * ENEMY_HALF_HEIGHT;
+    let b1 = enemy.position + enemy.dir * ENEMY_HALF_HEIGHT;
+    distance_between_line_segments(&a0, &a1, &b0, &b1) <= BULLET_RADIUS + ENEMY_RADIUS
+}
+
+// The previous approach: each shot split into sub groups, a box around each, and every enemy checked against every box.
+fn aabb_sub_groups(bullets: &Bullets, enemies: &[Enemy], hits: &mut Vec<(usize, usize)>) {
+    hits.clear();
+
+    for shot in 0..SHOTS {
+        let shot_start = shot * BULLETS_PER_SHOT;
+        let sub_group_size = BULLETS_PER_SHOT / SUB_GROUPS;
+
+        for sub_group in 0..SUB_GROUPS {
+            let start = shot_start + sub_group * sub_group_size;
+            let end = if sub_group == SUB_GROUPS - 1 { shot_start + BULLETS_PER_SHOT } else { start + sub_group_size };
+
+            let mut bounds = Aabb::new();
+            for position in &bullets.positions[start..end] {
+                bounds.expand_to_include(*position);
+            }
+            bounds.expand_by(MAX_COLLISION_DIST);
+
+            for (enemy_index, enemy) in enemies.iter().enumerate() {
+                if !bounds.contains_point(enemy.position) {
+                    continue;
+                }
+                for bullet_index in start..end {
+                    if collides(bullets, bullet_index, enemy) {
+                        hits.push((bullet_index, enemy_index));
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn spatial_hash(bullets: &Bullets, enemies: &[Enemy], grid: &mut SpatialHash, hits: &mut Vec<(usize, usize)>) {
+    hits.clear();
+    grid.rebuild(enemies.iter().map(|e| e.position));
+
+    let mut nearby = vec![];
+    for bullet_index in 0..bullets.positions.len() {
+        grid.query(bullets.positions[bullet_index], MAX_COLLISION_DIST, &mut nearby);
+        for &enemy_index in nearby.iter() {
+            if collides(bullets, bullet_index, &enemies[enemy_index]) {
+                hits.push((bullet_index, enemy_index));
+            }
+        }
+    }
+}
+
+// The spatial hash asked once per run of bullets, with a box around the run, as the game does. A run of a shot's
+// bullets is close together, so one query stands in for many.
+fn spatial_hash_runs(bullets: &Bullets, enemies: &[Enemy], grid: &mut SpatialHash, run_len: usize, hits: &mut Vec<(usize, usize)>) {
+    hits.clear();
+    grid.rebuild(enemies.iter().map(|e| e.position));
+
+    let mut nearby = vec![];
+    for run_start in (0..bullets.positions.len()).step_by(run_len) {
+        let run = run_start..(run_start + run_len).min(bullets.positions.len());
+        let positions = &bullets.positions[run.clone()];
+        let min = positions.iter().fold(Vec3::MAX, |min, p| min.min(*p)) - MAX_COLLISION_DIST;
+        let max = positions.iter().fold(Vec3::MIN, |max, p| max.max(*p)) + MAX_COLLISION_DIST;
+
+        grid.query_box(min, max, &mut nearby);
+        if nearby.is_empty() {
+            continue;
+        }
+        for bullet_index in run {
+            for &enemy_index in nearby.iter() {
+                if collides(bullets, bullet_index, &enemies[enemy_index]) {
+                    hits.push((bullet_index, enemy_index));
+                }
+            }
+        }
+    }
+}
+
+fn time(mut f: impl FnMut()) -> Duration {
+    f();
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    start.elapsed() / ITERATIONS
+}
+
+fn main() {
+    let mut random = Random(0x2545_f491);
+    let bullets = make_bullets(&mut random);
+    let mut grid = SpatialHash::new(GRID_CELL_SIZE);
+
+    println!("{} bullets, average time per tick over {} runs", bullets.positions.len(), ITERATIONS);
+    println!("{:>8} {:>14} {:>14} {:>14} {:>8} {:>6}", "enemies", "aabb groups", "per bullet", "per run", "speedup", "hits");
+
+    for count in ENEMY_COUNTS {
+        let enemies = make_enemies(count, &mut random);
+
+        let mut aabb_hits = vec![];
+        let mut bullet_hits = vec![];
+        let mut run_hits = vec![];
+        let aabb_time = time(|| aabb_sub_groups(&bullets, &enemies, &mut aabb_hits));
+        let bullet_time = time(|| spatial_hash(&bullets, &enemies, &mut grid, &mut bullet_hits));
+        let run_time = time(|| spatial_hash_runs(&bullets, &enemies, &mut grid, RUN_LEN, &mut run_hits));
+
+        // all must find the same pairs, only the order differs
+        aabb_hits.sort_unstable();
+        bullet_hits.sort_unstable();
+        run_hits.sort_unstable();
+        assert_eq!(aabb_hits, bullet_hits, "broad phases disagree with {} enemies", count);
+        assert_eq!(aabb_hits, run_hits, "broad phases disagree with {} enemies", count);
+
+        println!(
+            "{:>8} {:>14.3?} {:>14.3?} {:>14.3?} {:>7.1}x {:>6}",
+            count,
+            aabb_time,
+            bullet_time,
+            run_time,
+            aabb_time.as_secs_f64() / run_time.as_secs_f64(),
+            run_hits.len()
+        );
+    }
+}
@@ -0,0 +1,235 @@
+#![allow(dead_code)]
+
+// Measures what it costs the thread pool to hand work over, and what one item of each parallel
+// update costs, to set the minimum items per worker for each. A chunk is only worth handing to a
+// worker when the work in it takes longer than handing it over.
+//
+// cargo bench --bench thread_pool
+
+#[path = "../src/enemy_ai.rs"]
+mod enemy_ai;
+#[path = "../src/geom.rs"]
+mod geom;
+#[path = "../src/rng.rs"]
+mod rng;
+#[path = "../src/spatial_hash.rs"]
+mod spatial_hash;
+#[path = "../src/thread_pool.rs"]
+mod thread_pool;
+
+use enemy_ai::{AiBehaviour, AttackStyle, ChaseStyle, EnemyAi};
+use geom::distance_between_line_segments;
+use glam::{vec3, Quat, Vec3};
+use rng::SimRng;
+use spatial_hash::SpatialHash;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+use thread_pool::ThreadPool;
+
+const PARALLELISM: usize = 4; // as main.rs
+const ITERATIONS: u32 = 2000;
+const ITEMS: usize = 4096;
+const ARENA_HALF_SIZE: f32 = 40.0;
+
+// same sizes as the game
+const BULLET_HALF_HEIGHT: f32 = 0.15;
+const BULLET_RADIUS: f32 = 0.03;
+const ENEMY_HALF_HEIGHT: f32 = 0.2;
+const ENEMY_RADIUS: f32 = 0.08;
+const MAX_COLLISION_DIST: f32 = BULLET_HALF_HEIGHT + BULLET_RADIUS + ENEMY_HALF_HEIGHT + ENEMY_RADIUS;
+const GRID_CELL_SIZE: f32 = 2.0;
+const RUN_LEN: usize = 16;
+const ENEMY_COUNT: usize = 1000;
+const SPREAD_AMOUNT: i32 = 20;
+
+// the melee enemy's settings from enemy.rs
+const BEHAVIOUR: AiBehaviour = AiBehaviour {
+    spawn_time: 0.5,
+    sight_range: 15.0,
+    wander_speed: 0.75,
+    chase_speed: 1.5,
+    chase: ChaseStyle::Direct,
+    attack_range: 1.2,
+    wind_up_time: 0.35,
+    attack: AttackStyle::Lunge { speed: 3.0 },
+    attack_time: 0.3,
+    attack_cool_down: 1.5,
+    retreat_time: 0.0,
+    retreat_speed: 0.0,
+    stun_time: 0.25,
+    dying_time: 0.3,
+};
+
+fn time(mut f: impl FnMut()) -> Duration {
+    f();
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    start.elapsed() / ITERATIONS
+}
+
+fn nanoseconds_per_item(elapsed: Duration) -> f64 {
+    elapsed.as_secs_f64() * 1e9 / ITEMS as f64
+}
+
+// A call that splits a few items across every worker, less the same call kept on one thread, leaves what handing
+// the chunks over and waiting for them costs.
+fn dispatch_cost() -> Duration {
+    let pool = ThreadPool::new(PARALLELISM);
+    let single = ThreadPool::new(1);
+    let spread = time(|| {
+        black_box(pool.map_chunks(PARALLELISM, 1, |range| range.collect::<Vec<_>>()));
+    });
+    let kept = time(|| {
+        black_box(single.map_chunks(PARALLELISM, 1, |range| range.collect::<Vec<_>>()));
+    });
+    spread.saturating_sub(kept)
+}
+
+// One row of a spread, each bullet turned off the row's heading, as create_bullets does it.
+fn spread_row_cost() -> f64 {
+    let mut directions = vec![Vec3::ZERO; ITEMS];
+    let row_len = SPREAD_AMOUNT as usize;
+
+    let elapsed = time(|| {
+        for (row, directions) in directions.chunks_mut(row_len).enumerate() {
+            let y_quat = Quat::from_axis_angle(Vec3::Y, 0.01 * row as f32);
+            for (j, direction) in directions.iter_mut().enumerate() {
+                let rot_quat = y_quat * Quat::from_axis_angle(Vec3::X, 0.01 * j as f32);
+                *direction = rot_quat.mul_vec3(Vec3::NEG_Z);
+            }
+        }
+        black_box(&directions);
+    });
+    nanoseconds_per_item(elapsed) * row_len as f64
+}
+
+// The bullet move and floor bounce, as BulletChunk::integrate does it.
+fn integration_cost(random: &mut SimRng) -> f64 {
+    let mut positions: Vec<Vec3> = (0..ITEMS)
+        .map(|_| vec3(random.random_clamped() * ARENA_HALF_SIZE, 0.5, random.random_clamped() * ARENA_HALF_SIZE))
+        .collect();
+    let mut previous_positions = positions.clone();
+    let mut directions: Vec<Vec3> = (0..ITEMS)
+        .map(|_| vec3(random.random_clamped(), -0.1, random.random_clamped()).normalize())
+        .collect();
+
+    let elapsed = time(|| {
+        for i in 0..ITEMS {
+            previous_positions[i] = positions[i];
+            positions[i] += directions[i] * 0.1;
+            if positions[i].y < 0.0 && directions[i].y < 0.0 {
+                directions[i].y = -directions[i].y;
+                positions[i].y = -positions[i].y;
+            }
+            if positions[i].x.abs() > ARENA_HALF_SIZE || positions[i].z.abs() > ARENA_HALF_SIZE {
+                positions[i] = Vec3::ZERO;
+            }
+        }
+        black_box(&positions);
+    });
+    nanoseconds_per_item(elapsed)
+}
+
+// A run's grid query shared by its bullets, then each bullet's checks against what it found, as the game does.
+fn collision_cost(random: &mut SimRng) -> f64 {
+    let enemies: Vec<(Vec3, Vec3)> = (0..ENEMY_COUNT)
+        .map(|_| {
+            let theta = random.rand_float() * std::f32::consts::TAU;
+            (
+                vec3(random.random_clamped() * ARENA_HALF_SIZE, 0.5, random.random_clamped() * ARENA_HALF_SIZE),
+                vec3(theta.sin(), 0.0, theta.cos()),
+            )
+        })
+        .collect();
+    let mut grid = SpatialHash::new(GRID_CELL_SIZE);
+    grid.rebuild(enemies.iter().map(|e| e.0));
+
+    // shots of 16 bullets, fanned out from around the arena
+    let mut positions = vec![];
+    let mut directions = vec![];
+    for _ in 0..ITEMS / RUN_LEN {
+        let origin = vec3(random.random_clamped() * ARENA_HALF_SIZE, 0.5, random.random_clamped() * ARENA_HALF_SIZE);
+        let aim = random.rand_float() * std::f32::consts::TAU;
+        for i in 0..RUN_LEN {
+            let direction = Vec3::new((aim + i as f32 * 0.01).sin(), 0.0, (aim + i as f32 * 0.01).cos());
+            positions.push(origin + direction * 0.1 * i as f32);
+            directions.push(direction);
+        }
+    }
+
+    let mut nearby = vec![];
+    let elapsed = time(|| {
+        let mut hits = 0;
+        for run_start in (0..ITEMS).step_by(RUN_LEN) {
+            let run = run_start..run_start + RUN_LEN;
+            let min = positions[run.clone()].iter().fold(Vec3::MAX, |min, p| min.min(*p));
+            let max = positions[run.clone()].iter().fold(Vec3::MIN, |max, p| max.max(*p));
+            grid.query_box(min - MAX_COLLISION_DIST, max + MAX_COLLISION_DIST, &mut nearby);
+            for i in run {
+                for &enemy_index in nearby.iter() {
+                    let (position, dir) = enemies[enemy_index];
+                    if positions[i].distance(position) > MAX_COLLISION_DIST {
+                        continue;
+                    }
+                    let a0 = positions[i] - directions[i] * BULLET_HALF_HEIGHT;
+                    let a1 = positions[i] + directions[i] * BULLET_HALF_HEIGHT;
+                    let b0 = position - dir * ENEMY_HALF_HEIGHT;
+                    let b1 = position + dir * ENEMY_HALF_HEIGHT;
+                    if distance_between_line_segments(&a0, &a1, &b0, &b1) <= BULLET_RADIUS + ENEMY_RADIUS {
+                        hits += 1;
+                    }
+                }
+            }
+        }
+        black_box(hits);
+    });
+    nanoseconds_per_item(elapsed)
+}
+
+// One enemy's state machine tick and move, as steer_enemies does it.
+fn steering_cost(random: &mut SimRng) -> f64 {
+    let mut enemies: Vec<(EnemyAi, Vec3, Vec3)> = (0..ITEMS)
+        .map(|_| {
+            (
+                EnemyAi::new(),
+                vec3(random.random_clamped() * 20.0, 0.5, random.random_clamped() * 20.0),
+                Vec3::ZERO,
+            )
+        })
+        .collect();
+    let target = Some(vec3(0.0, 0.5, 0.0));
+    let mut tick = 0;
+
+    let elapsed = time(|| {
+        tick += 1;
+        for (index, (ai, position, velocity)) in enemies.iter_mut().enumerate() {
+            let mut rng = SimRng::stream(tick, index as u64);
+            let action = ai.update(&BEHAVIOUR, *position, target, 1.0 / 60.0, &mut rng);
+            *velocity = velocity.lerp(action.steering_velocity, 0.1);
+            *position += *velocity / 60.0;
+        }
+        black_box(&enemies);
+    });
+    nanoseconds_per_item(elapsed)
+}
+
+fn main() {
+    let mut random = SimRng::new(0x2545_f491);
+
+    let dispatch = dispatch_cost();
+    println!("handing work to {} workers costs {:.3?} per call", PARALLELISM, dispatch);
+    println!("{:>12} {:>12} {:>22}", "update", "per item", "min items per worker");
+
+    for (name, per_item) in [
+        ("spread rows", spread_row_cost()),
+        ("integration", integration_cost(&mut random)),
+        ("collision", collision_cost(&mut random)),
+        ("steering", steering_cost(&mut random)),
+    ] {
+        // the fewest items whose work outweighs handing them over, round it to set the constant
+        let break_even = (dispatch.as_secs_f64() * 1e9 / per_item).ceil() as usize;
+        println!("{:>12} {:>10.1}ns {:>22}", name, per_item, break_even);
+    }
+}
@@ -8,6 +8,12 @@ layout(location = 4) in vec3 bitangent;
 layout(location = 5) in ivec4 boneIds;
 layout(location = 6) in vec4 weights;
 
+const int MAX_BONES = 100;
+const int MAX_BONE_INFLUENCE = 4;
+
+uniform mat4 finalBonesMatrices[MAX_BONES];
+uniform mat4 nodeTransform;
+
 out vec2 TexCoords;
 out vec3 Norm;
 out vec4 FragPosLightSpace;
@@ -22,18 +28,54 @@ uniform vec3 nosePos;
 uniform float time;
 
 uniform bool depth_mode;
+// the sine wiggle, for when the model has no clips to pose it with
+uniform bool useWiggle;
+// eases with each enemy's animation state, 0 stills it
+uniform float wiggleScale;
 
 const float wiggleMagnitude = 3.0;
 const float wiggleDistModifier = 0.12;
 const float wiggleTimeModifier = 9.4;
 
+vec4 get_animated_position() {
+  vec4 totalPosition = vec4(0.0f);
+
+  for(int i = 0 ; i < MAX_BONE_INFLUENCE ; i++)
+  {
+    if(boneIds[i] == -1) {
+      continue;
+    }
+
+    if(boneIds[i] >= MAX_BONES) {
+      totalPosition = vec4(pos, 1.0f);
+      break;
+    }
+
+    vec4 localPosition = finalBonesMatrices[boneIds[i]] * vec4(pos, 1.0f);
+    totalPosition += localPosition * weights[i];
+  }
+
+  if (totalPosition == vec4(0.0f)) {
+    totalPosition = nodeTransform * vec4(pos, 1.0f);
+  }
+
+  return totalPosition;
+}
+
 void main() {
-  float xOffset = sin(wiggleTimeModifier * time + wiggleDistModifier * distance(nosePos, pos)) * wiggleMagnitude;
+  vec4 final_position;
+
+  if (useWiggle) {
+    float xOffset = sin(wiggleTimeModifier * time + wiggleDistModifier * distance(nosePos, pos)) * wiggleMagnitude * wiggleScale;
+    final_position = vec4(pos.x + xOffset, pos.y, pos.z, 1.0);
+  } else {
+    final_position = get_animated_position();
+  }
 
   if (depth_mode) {
-    gl_Position = lightSpaceMatrix * model * vec4(pos.x + xOffset, pos.y, pos.z, 1.0);
+    gl_Position = lightSpaceMatrix * model * final_position;
   } else {
-    gl_Position = projectionView * model * vec4(pos.x + xOffset, pos.y, pos.z, 1.0);
+    gl_Position = projectionView * model * final_position;
   }
 
   TexCoords = tex;
@@ -45,4 +87,3 @@ void main() {
 
   FragWorldPos = vec3(model * vec4(pos, 1.0));
 }
-
@@ -44,6 +44,61 @@ impl Aabb {
         }
     }
 
+    pub fn from_min_max(min: Vec3, max: Vec3) -> Self {
+        let mut aabb = Self::new();
+        aabb.expand_to_include(min);
+        aabb.expand_to_include(max);
+        aabb
+    }
+
+    pub fn min(&self) -> Vec3 {
+        vec3(self.x_min, self.y_min, self.z_min)
+    }
+
+    pub fn max(&self) -> Vec3 {
+        vec3(self.x_max, self.y_max, self.z_max)
+    }
+
+    /// The point in the box nearest to `point`, which is `point` itself when it is inside.
+    pub fn closest_point(&self, point: Vec3) -> Vec3 {
+        point.max(self.min()).min(self.max())
+    }
+
+    /// Distance along the ray to where it enters the box, or `None` if it misses. A ray starting inside hits at 0.
+    /// The direction doesn't need to be normalized, the distance is in multiples of it.
+    pub fn ray_intersection(&self, origin: Vec3, direction: Vec3) -> Option<f32> {
+        if !self.is_initialize {
+            return None;
+        }
+
+        let mut t_enter = 0.0f32;
+        let mut t_exit = f32::MAX;
+
+        for axis in 0..3 {
+            let (min, max) = (self.min()[axis], self.max()[axis]);
+            let (o, d) = (origin[axis], direction[axis]);
+
+            // parallel to the slab, so either always inside it or never
+            if d == 0.0 {
+                if o < min || o > max {
+                    return None;
+                }
+                continue;
+            }
+
+            let t0 = (min - o) / d;
+            let t1 = (max - o) / d;
+            t_enter = t_enter.max(t0.min(t1));
+            t_exit = t_exit.min(t0.max(t1));
+
+            if t_enter > t_exit {
+                return None;
+            }
+        }
+
+        Some(t_enter)
+    }
+
     #[rustfmt::skip]
     pub fn contains_point(&self, point: Vec3) -> bool {
         point.x >= self.x_min
@@ -55,14 +110,52 @@ impl Aabb {
     }
 }
 
+/// Whether the boxes overlap on every axis. Boxes that only touch count as intersecting and empty boxes never do.
 #[rustfmt::skip]
 pub fn aabbs_intersect(a: &Aabb, b: &Aabb) -> bool {
-    a.contains_point(vec3(b.x_min, b.y_min, b.z_min))
-        || a.contains_point(vec3(b.x_min, b.y_min, b.z_max))
-        || a.contains_point(vec3(b.x_min, b.y_max, b.z_min))
-        || a.contains_point(vec3(b.x_min, b.y_max, b.z_max))
-        || a.contains_point(vec3(b.x_max, b.y_min, b.z_min))
-        || a.contains_point(vec3(b.x_max, b.y_min, b.z_max))
-        || a.contains_point(vec3(b.x_max, b.y_max, b.z_min))
-        || a.contains_point(vec3(b.x_max, b.y_max, b.z_max))
+    a.x_min <= b.x_max && b.x_min <= a.x_max
+        && a.y_min <= b.y_max && b.y_min <= a.y_max
+        && a.z_min <= b.z_max && b.z_min <= a.z_max
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_crossing_boxes_intersect() {
+        use super::{aabbs_intersect, Aabb};
+        use glam::vec3;
+
+        // a plus sign, neither box holds a corner of the other
+        let wide = Aabb::from_min_max(vec3(-2.0, -1.0, -1.0), vec3(2.0, 1.0, 1.0));
+        let tall = Aabb::from_min_max(vec3(-1.0, -2.0, -1.0), vec3(1.0, 2.0, 1.0));
+        assert!(aabbs_intersect(&wide, &tall));
+        assert!(aabbs_intersect(&tall, &wide));
+
+        let touching = Aabb::from_min_max(vec3(2.0, -1.0, -1.0), vec3(3.0, 1.0, 1.0));
+        assert!(aabbs_intersect(&wide, &touching));
+
+        let apart = Aabb::from_min_max(vec3(2.1, -1.0, -1.0), vec3(3.0, 1.0, 1.0));
+        assert!(!aabbs_intersect(&wide, &apart));
+
+        let point = Aabb::from_min_max(vec3(0.0, 0.0, 0.0), vec3(0.0, 0.0, 0.0));
+        assert!(aabbs_intersect(&wide, &point));
+        assert!(!aabbs_intersect(&wide, &Aabb::new()));
+        assert!(!aabbs_intersect(&Aabb::new(), &Aabb::new()));
+    }
+
+    #[test]
+    fn test_ray_intersection() {
+        use super::Aabb;
+        use glam::vec3;
+
+        let unit = Aabb::from_min_max(vec3(-1.0, -1.0, -1.0), vec3(1.0, 1.0, 1.0));
+
+        assert_eq!(unit.ray_intersection(vec3(-5.0, 0.0, 0.0), vec3(1.0, 0.0, 0.0)), Some(4.0));
+        assert_eq!(unit.ray_intersection(vec3(0.5, 0.0, 0.0), vec3(1.0, 0.0, 0.0)), Some(0.0));
+        assert_eq!(unit.ray_intersection(vec3(-5.0, 0.0, 0.0), vec3(-1.0, 0.0, 0.0)), None);
+        // along a face, with zero direction on the other axes
+        assert_eq!(unit.ray_intersection(vec3(-5.0, 1.0, 1.0), vec3(1.0, 0.0, 0.0)), Some(4.0));
+        assert_eq!(unit.ray_intersection(vec3(-5.0, 1.5, 0.0), vec3(1.0, 0.0, 0.0)), None);
+        assert_eq!(Aabb::new().ray_intersection(vec3(0.0, 0.0, 0.0), vec3(1.0, 0.0, 0.0)), None);
+    }
 }
@@ -0,0 +1,322 @@
+use small_gl_core::animator::{AnimationClip, AnimationRepeat, WeightedAnimation};
+use small_gl_core::hash_map::HashMap;
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+
+//
+// The named clips of a model, read from the model's animation definition file so clips can be added
+// or retimed without code changes. Each line is
+//
+//     name = start_frame end_frame repeat [blend_offset]
+//
+// where repeat is `forever` or `once`, and the blend offset shifts the clip's phase, in frames, when it is
+// blended with the others. Events are named frames of a clip that gameplay hears about as the clip plays past
+// them, one per line as
+//
+//     event name = clip frame
+//
+
+/// The rate the models' clips were authored at, which the animator plays them at.
+pub const FRAMES_PER_SECOND: f32 = 30.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClipRepeat {
+    Forever,
+    Once,
+}
+
+impl ClipRepeat {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "forever" => Some(ClipRepeat::Forever),
+            "once" => Some(ClipRepeat::Once),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClipEvent {
+    pub frame: f32,
+    pub name: Rc<str>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClipDefinition {
+    pub start_frame: f32,
+    pub end_frame: f32,
+    pub repeat: ClipRepeat,
+    pub blend_offset: f32,
+    pub events: Vec<ClipEvent>,
+}
+
+impl ClipDefinition {
+    pub fn to_clip(&self) -> AnimationClip {
+        let repeat = match self.repeat {
+            ClipRepeat::Forever => AnimationRepeat::Forever,
+            ClipRepeat::Once => AnimationRepeat::Once,
+        };
+        AnimationClip::new(self.start_frame, self.end_frame, repeat)
+    }
+
+    /// The clip as one layer of a blend. `start_time` is when a `once` clip began playing.
+    pub fn weighted(&self, weight: f32, start_time: f32) -> WeightedAnimation {
+        WeightedAnimation::new(weight, self.start_frame, self.end_frame, self.blend_offset, start_time)
+    }
+
+    /// A looping clip as one layer of a blend, shifted on by `phase` of a cycle so models sharing it don't move in step.
+    pub fn weighted_at_phase(&self, weight: f32, phase: f32) -> WeightedAnimation {
+        let offset = self.blend_offset + phase * (self.end_frame - self.start_frame);
+        WeightedAnimation::new(weight, self.start_frame, self.end_frame, offset, 0.0)
+    }
+
+    /// The events the clip plays past going from `from` to `to` seconds in, in the order it reaches them. Looping clips
+    /// are timed from the frame time, as the animator plays them, and `once` clips from when they started. A looping
+    /// clip reports at most one time round, however long the gap, so a hitch doesn't set off a burst of footsteps.
+    pub fn events_between(&self, from: f32, to: f32) -> Vec<Rc<str>> {
+        let length = self.end_frame - self.start_frame;
+        if to <= from || length <= 0.0 {
+            return vec![];
+        }
+
+        let mut passed: Vec<(f32, &Rc<str>)> = vec![];
+        match self.repeat {
+            ClipRepeat::Forever => {
+                let (from, to) = (from * FRAMES_PER_SECOND + self.blend_offset, to * FRAMES_PER_SECOND + self.blend_offset);
+                let from = from.max(to - length);
+                for event in self.events.iter() {
+                    let frame = event.frame - self.start_frame;
+                    // every time round the loop that lands in the range
+                    let first_cycle = ((from - frame) / length).floor() + 1.0;
+                    let last_cycle = ((to - frame) / length).floor();
+                    let mut cycle = first_cycle;
+                    while cycle <= last_cycle {
+                        passed.push((frame + cycle * length, &event.name));
+                        cycle += 1.0;
+                    }
+                }
+            }
+            ClipRepeat::Once => {
+                let (from, to) = (from * FRAMES_PER_SECOND, to * FRAMES_PER_SECOND);
+                for event in self.events.iter() {
+                    let frame = event.frame - self.start_frame;
+                    if from < frame && frame <= to {
+                        passed.push((frame, &event.name));
+                    }
+                }
+            }
+        }
+
+        passed.sort_by(|a, b| a.0.total_cmp(&b.0));
+        passed.into_iter().map(|(_, name)| name.clone()).collect()
+    }
+}
+
+/// Reads `name = start_frame end_frame repeat [blend_offset]` lines, in file order, with the `event name = clip frame`
+/// lines added to their clips. Bad lines are skipped with a warning.
+pub fn parse_clip_definitions(contents: &str) -> Vec<(Rc<str>, ClipDefinition)> {
+    let mut definitions: Vec<(Rc<str>, ClipDefinition)> = vec![];
+    let mut events = vec![];
+
+    for (line_number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let Some((name, values)) = line.split_once('=') else {
+            warn!(
+                "animation line {}: expected 'name = start_frame end_frame repeat [blend_offset]'",
+                line_number + 1
+            );
+            continue;
+        };
+
+        let values: Vec<&str> = values.split_whitespace().collect();
+        let frame = |index: usize| values.get(index).and_then(|v| v.parse::<f32>().ok());
+
+        if let Some(event_name) = name.trim().strip_prefix("event ") {
+            let (Some(clip), Some(event_frame)) = (values.first(), frame(1)) else {
+                warn!("animation line {}: expected 'event name = clip frame'", line_number + 1);
+                continue;
+            };
+            events.push((
+                line_number,
+                Rc::<str>::from(*clip),
+                ClipEvent {
+                    frame: event_frame,
+                    name: Rc::from(event_name.trim()),
+                },
+            ));
+            continue;
+        }
+
+        let (Some(start_frame), Some(end_frame), Some(repeat)) = (frame(0), frame(1), values.get(2).and_then(|v| ClipRepeat::from_name(v))) else {
+            warn!(
+                "animation line {}: expected 'start_frame end_frame repeat' for '{}'",
+                line_number + 1,
+                name.trim()
+            );
+            continue;
+        };
+
+        let blend_offset = match values.get(3) {
+            None => 0.0,
+            Some(_) => match frame(3) {
+                Some(offset) => offset,
+                None => {
+                    warn!("animation line {}: bad blend offset '{}'", line_number + 1, values[3]);
+                    0.0
+                }
+            },
+        };
+
+        if end_frame < start_frame {
+            warn!("animation line {}: '{}' ends before it starts", line_number + 1, name.trim());
+            continue;
+        }
+
+        definitions.push((
+            Rc::from(name.trim()),
+            ClipDefinition {
+                start_frame,
+                end_frame,
+                repeat,
+                blend_offset,
+                events: vec![],
+            },
+        ));
+    }
+
+    for (line_number, clip, event) in events {
+        let Some((_, definition)) = definitions.iter_mut().find(|(name, _)| *name == clip) else {
+            warn!("animation line {}: event '{}' is for unknown clip '{}'", line_number + 1, event.name, clip);
+            continue;
+        };
+        if event.frame < definition.start_frame || event.frame > definition.end_frame {
+            warn!("animation line {}: event '{}' is outside clip '{}'", line_number + 1, event.name, clip);
+            continue;
+        }
+        definition.events.push(event);
+    }
+
+    definitions
+}
+
+pub struct AnimationSet {
+    definitions: HashMap<Rc<str>, ClipDefinition>,
+    clips: HashMap<Rc<str>, Rc<AnimationClip>>,
+}
+
+impl AnimationSet {
+    pub fn new(definitions: Vec<(Rc<str>, ClipDefinition)>) -> Self {
+        let mut set = Self {
+            definitions: HashMap::new(),
+            clips: HashMap::new(),
+        };
+        for (name, definition) in definitions {
+            set.clips.insert(name.clone(), Rc::new(definition.to_clip()));
+            set.definitions.insert(name, definition);
+        }
+        set
+    }
+
+    /// Loads the definition file. A missing or unreadable file leaves the model without clips.
+    pub fn load(path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref();
+        match fs::read_to_string(path) {
+            Ok(contents) => Self::new(parse_clip_definitions(&contents)),
+            Err(e) => {
+                error!("could not read animation definitions {:?}: {}", path, e);
+                Self::new(vec![])
+            }
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Rc<AnimationClip>> {
+        self.clips.get(name)
+    }
+
+    pub fn definition(&self, name: &str) -> Option<&ClipDefinition> {
+        self.definitions.get(name)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.definitions.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_clip_definitions() {
+        let contents = "\
+# name = start_frame end_frame repeat [blend_offset]
+idle = 55 130 forever
+back = 159 179 forever 10
+
+dead = 234 293 once
+broken
+bad_repeat = 1 2 sometimes
+backwards = 20 10 once
+event footstep = back 164
+event landed = dead 290
+event lost = missing 10
+event late = idle 200
+";
+        let definitions = parse_clip_definitions(contents);
+        let names: Vec<&str> = definitions.iter().map(|(name, _)| name.as_ref()).collect();
+        assert_eq!(names, ["idle", "back", "dead"]);
+
+        assert_eq!(
+            definitions[1].1,
+            ClipDefinition {
+                start_frame: 159.0,
+                end_frame: 179.0,
+                repeat: ClipRepeat::Forever,
+                blend_offset: 10.0,
+                events: vec![ClipEvent {
+                    frame: 164.0,
+                    name: Rc::from("footstep"),
+                }],
+            }
+        );
+        assert_eq!(definitions[2].1.repeat, ClipRepeat::Once);
+        assert_eq!(definitions[2].1.blend_offset, 0.0);
+        assert_eq!(definitions[2].1.events.len(), 1);
+        assert!(definitions[0].1.events.is_empty());
+    }
+
+    #[test]
+    fn test_events_between() {
+        let definitions = parse_clip_definitions(
+            "\
+walk = 10 40 forever
+event step = walk 10
+event step_back = walk 25
+dead = 0 60 once
+event landed = dead 60
+",
+        );
+        let (walk, dead) = (&definitions[0].1, &definitions[1].1);
+        let seconds = |frames: f32| frames / FRAMES_PER_SECOND;
+        let names = |events: Vec<Rc<str>>| events.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+
+        // a loop is 30 frames, step at 0 and step_back at 15
+        assert_eq!(names(walk.events_between(seconds(1.0), seconds(14.0))), Vec::<String>::new());
+        assert_eq!(names(walk.events_between(seconds(14.0), seconds(15.0))), ["step_back"]);
+        assert_eq!(names(walk.events_between(seconds(20.0), seconds(31.0))), ["step"]);
+        assert_eq!(names(walk.events_between(seconds(29.0), seconds(59.0))), ["step", "step_back"]);
+        // a longer gap only plays the last time round
+        assert_eq!(names(walk.events_between(seconds(29.0), seconds(76.0))), ["step", "step_back"]);
+        assert_eq!(names(walk.events_between(0.0, 1000.0)), ["step_back", "step"]);
+        assert!(walk.events_between(seconds(5.0), seconds(5.0)).is_empty());
+
+        assert_eq!(names(dead.events_between(seconds(-1.0), seconds(59.0))), Vec::<String>::new());
+        assert_eq!(names(dead.events_between(seconds(59.0), seconds(61.0))), ["landed"]);
+        assert!(dead.events_between(seconds(61.0), seconds(200.0)).is_empty());
+    }
+}
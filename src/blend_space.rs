@@ -0,0 +1,287 @@
+use crate::animation_set::AnimationSet;
+use glam::Vec2;
+use small_gl_core::animator::WeightedAnimation;
+use std::f32::consts::TAU;
+use std::rc::Rc;
+
+//
+// A 2D locomotion blend space. Clips are placed at directions relative to the way the character faces,
+// x across to its left and y ahead of it, with idle clips at zero. Each update blends the two clips either
+// side of the movement direction, hands the rest to idle as the speed drops, and eases every weight towards
+// its target over the transition time so changes of direction don't pop.
+//
+
+#[derive(Debug, Clone)]
+pub struct BlendClip {
+    pub name: Rc<str>,
+    /// Where the clip sits relative to the facing, zero for a clip played standing still.
+    pub direction: Vec2,
+}
+
+impl BlendClip {
+    pub fn new(name: &str, direction: Vec2) -> Self {
+        Self {
+            name: Rc::from(name),
+            direction,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BlendSpace {
+    clips: Vec<BlendClip>,
+    transition_time: f32,
+    weights: Vec<f32>,
+    is_settled: bool,
+}
+
+impl BlendSpace {
+    pub fn new(clips: Vec<BlendClip>, transition_time: f32) -> Self {
+        let weights = vec![0.0; clips.len()];
+        Self {
+            clips,
+            transition_time,
+            weights,
+            is_settled: false,
+        }
+    }
+
+    /// Forgets the current blend, so the next update starts straight at its targets.
+    pub fn reset(&mut self) {
+        self.weights.iter_mut().for_each(|w| *w = 0.0);
+        self.is_settled = false;
+    }
+
+    /// Moves the blend on by `delta_time` for `movement` in world x/z, up to unit length at full speed, while facing
+    /// along `facing`. Returns each clip's weight, in clip order, summing to one.
+    pub fn update(&mut self, movement: Vec2, facing: Vec2, delta_time: f32) -> Vec<f32> {
+        let targets = self.target_weights(movement, facing);
+
+        if !self.is_settled || self.transition_time <= 0.0 {
+            self.weights.copy_from_slice(&targets);
+            self.is_settled = true;
+        } else {
+            let max_change = delta_time.max(0.0) / self.transition_time;
+            for (weight, target) in self.weights.iter_mut().zip(targets) {
+                *weight += (target - *weight).clamp(-max_change, max_change);
+            }
+        }
+
+        self.weights()
+    }
+
+    /// The current weights, in clip order, summing to one. All zero when there are no clips to play.
+    pub fn weights(&self) -> Vec<f32> {
+        let sum: f32 = self.weights.iter().sum();
+        if sum <= f32::EPSILON {
+            return vec![0.0; self.weights.len()];
+        }
+        self.weights.iter().map(|w| w / sum).collect()
+    }
+
+    /// The clip with the most weight, `None` when there are no clips to play.
+    pub fn dominant_clip(&self) -> Option<&str> {
+        self.clips
+            .iter()
+            .zip(self.weights.iter())
+            .filter(|(_, weight)| **weight > 0.0)
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .map(|(clip, _)| clip.name.as_ref())
+    }
+
+    /// The current blend as animations for the model, scaled by `scale` so other clips can take a share.
+    /// Clips the animation set doesn't define are left out.
+    pub fn weighted_animations(&self, animations: &AnimationSet, scale: f32) -> Vec<WeightedAnimation> {
+        self.clips
+            .iter()
+            .zip(self.weights())
+            .filter_map(|(clip, weight)| animations.definition(&clip.name).map(|d| d.weighted(weight * scale, 0.0)))
+            .collect()
+    }
+
+    fn target_weights(&self, movement: Vec2, facing: Vec2) -> Vec<f32> {
+        let mut targets = vec![0.0; self.clips.len()];
+
+        let is_idle = |clip: &BlendClip| clip.direction == Vec2::ZERO;
+        let idle_count = self.clips.iter().filter(|c| is_idle(c)).count();
+        let has_directions = idle_count < self.clips.len();
+
+        let speed = if has_directions { movement.length().min(1.0) } else { 0.0 };
+        let idle_share = if idle_count > 0 { 1.0 - speed } else { 0.0 };
+
+        for (target, clip) in targets.iter_mut().zip(self.clips.iter()) {
+            if is_idle(clip) {
+                *target = idle_share / idle_count as f32;
+            }
+        }
+
+        if !has_directions {
+            return targets;
+        }
+
+        // the movement in the facing's frame, as an angle
+        let facing = facing.try_normalize().unwrap_or(Vec2::Y);
+        let local = Vec2::new(movement.perp_dot(facing), movement.dot(facing));
+
+        // with nothing to stand still with, the directional clips take all of the weight
+        let (move_share, local) = match (idle_count, speed > f32::EPSILON) {
+            (0, true) => (1.0, local),
+            (_, true) => (speed, local),
+            (_, false) if idle_count > 0 => return targets,
+            // stopped, so carry on with the blend it was moving with, or the clip ahead when it never moved
+            _ if self.is_settled => return self.weights.clone(),
+            _ => (1.0, Vec2::Y),
+        };
+        let move_angle = local.y.atan2(local.x);
+
+        // the nearest clip each way round from the movement
+        let mut next = (usize::MAX, f32::MAX);
+        let mut prev = (usize::MAX, f32::MAX);
+        for (index, clip) in self.clips.iter().enumerate().filter(|(_, c)| !is_idle(c)) {
+            let ahead = (clip.direction.y.atan2(clip.direction.x) - move_angle).rem_euclid(TAU);
+            if ahead < next.1 {
+                next = (index, ahead);
+            }
+            let behind = (TAU - ahead).rem_euclid(TAU);
+            if behind < prev.1 {
+                prev = (index, behind);
+            }
+        }
+
+        let span = next.1 + prev.1;
+        if next.0 == prev.0 || span <= f32::EPSILON {
+            targets[next.0] += move_share;
+        } else {
+            targets[next.0] += move_share * prev.1 / span;
+            targets[prev.0] += move_share * next.1 / span;
+        }
+
+        targets
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::vec2;
+
+    const FORWARD: Vec2 = Vec2::new(0.0, 1.0);
+
+    fn locomotion() -> BlendSpace {
+        BlendSpace::new(
+            vec![
+                BlendClip::new("idle", Vec2::ZERO),
+                BlendClip::new("forward", vec2(0.0, 1.0)),
+                BlendClip::new("back", vec2(0.0, -1.0)),
+                BlendClip::new("right", vec2(-1.0, 0.0)),
+                BlendClip::new("left", vec2(1.0, 0.0)),
+            ],
+            0.2,
+        )
+    }
+
+    fn assert_weights(actual: &[f32], expected: &[f32]) {
+        assert_eq!(actual.len(), expected.len());
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 1e-4, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn test_standing_still_is_idle() {
+        let mut blend = locomotion();
+        assert_weights(&blend.update(Vec2::ZERO, FORWARD, 0.016), &[1.0, 0.0, 0.0, 0.0, 0.0]);
+        // no facing either, which used to give NaN
+        assert_weights(&blend.update(Vec2::ZERO, Vec2::ZERO, 0.016), &[1.0, 0.0, 0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_directions_relative_to_facing() {
+        // facing +z, so +x is to the left
+        assert_weights(&locomotion().update(vec2(0.0, 1.0), FORWARD, 0.016), &[0.0, 1.0, 0.0, 0.0, 0.0]);
+        assert_weights(&locomotion().update(vec2(0.0, -1.0), FORWARD, 0.016), &[0.0, 0.0, 1.0, 0.0, 0.0]);
+        assert_weights(&locomotion().update(vec2(1.0, 0.0), FORWARD, 0.016), &[0.0, 0.0, 0.0, 0.0, 1.0]);
+        assert_weights(&locomotion().update(vec2(-1.0, 0.0), FORWARD, 0.016), &[0.0, 0.0, 0.0, 1.0, 0.0]);
+
+        // facing +x, moving +x is forward
+        assert_weights(&locomotion().update(vec2(1.0, 0.0), vec2(3.0, 0.0), 0.016), &[0.0, 1.0, 0.0, 0.0, 0.0]);
+
+        // diagonals split evenly, half speed shares with idle
+        assert_weights(&locomotion().update(vec2(1.0, 1.0).normalize(), FORWARD, 0.016), &[0.0, 0.5, 0.0, 0.0, 0.5]);
+        assert_weights(&locomotion().update(vec2(0.0, -0.5), FORWARD, 0.016), &[0.5, 0.0, 0.5, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_weights_ease_between_targets() {
+        let mut blend = locomotion();
+        blend.update(Vec2::ZERO, FORWARD, 0.016);
+
+        // a quarter of the way through the transition
+        assert_weights(&blend.update(vec2(0.0, 1.0), FORWARD, 0.05), &[0.75, 0.25, 0.0, 0.0, 0.0]);
+        assert_weights(&blend.update(vec2(0.0, 1.0), FORWARD, 1.0), &[0.0, 1.0, 0.0, 0.0, 0.0]);
+
+        assert_eq!(blend.dominant_clip(), Some("forward"));
+
+        blend.reset();
+        assert_eq!(blend.dominant_clip(), None);
+        assert_weights(&blend.update(vec2(0.0, -1.0), FORWARD, 0.0), &[0.0, 0.0, 1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_weights_always_normalized() {
+        let mut blend = locomotion();
+        for i in 0..200 {
+            let angle = i as f32 * 0.37;
+            let movement = vec2(angle.sin(), angle.cos()) * ((i % 7) as f32 / 6.0);
+            let facing = vec2((angle * 1.3).cos(), (angle * 0.7).sin());
+            let weights = blend.update(movement, facing, 0.016);
+
+            assert!(weights.iter().all(|w| w.is_finite() && *w >= 0.0), "{:?}", weights);
+            assert!((weights.iter().sum::<f32>() - 1.0).abs() < 1e-4, "{:?}", weights);
+        }
+    }
+
+    #[test]
+    fn test_without_idle_or_directions() {
+        let mut moving_only = BlendSpace::new(vec![BlendClip::new("walk", vec2(0.0, 1.0))], 0.2);
+        assert_weights(&moving_only.update(vec2(0.3, -0.2), FORWARD, 0.016), &[1.0]);
+        // standing still for well past the transition, with no idle to hand the weight to
+        for _ in 0..100 {
+            let weights = moving_only.update(Vec2::ZERO, FORWARD, 0.016);
+            assert!((weights.iter().sum::<f32>() - 1.0).abs() < 1e-4, "{:?}", weights);
+        }
+        assert_weights(&moving_only.weights(), &[1.0]);
+
+        let mut idle_only = BlendSpace::new(vec![BlendClip::new("idle", Vec2::ZERO)], 0.2);
+        assert_weights(&idle_only.update(vec2(1.0, 0.0), FORWARD, 0.016), &[1.0]);
+
+        assert!(BlendSpace::new(vec![], 0.2).update(vec2(1.0, 0.0), FORWARD, 0.016).is_empty());
+    }
+
+    #[test]
+    fn test_stopping_without_idle_keeps_the_blend() {
+        let mut strafing = BlendSpace::new(
+            vec![
+                BlendClip::new("forward", vec2(0.0, 1.0)),
+                BlendClip::new("back", vec2(0.0, -1.0)),
+                BlendClip::new("right", vec2(-1.0, 0.0)),
+                BlendClip::new("left", vec2(1.0, 0.0)),
+            ],
+            0.2,
+        );
+
+        // never moved, so the clip ahead
+        assert_weights(&strafing.update(Vec2::ZERO, FORWARD, 0.016), &[1.0, 0.0, 0.0, 0.0]);
+
+        // creeping along, the direction still decides the clip rather than the speed
+        assert_weights(&strafing.update(vec2(0.01, 0.0), FORWARD, 10.0), &[0.0, 0.0, 0.0, 1.0]);
+
+        // and stopping holds it, long after any transition would have finished
+        for _ in 0..1000 {
+            let weights = strafing.update(Vec2::ZERO, FORWARD, 0.016);
+            assert!((weights.iter().sum::<f32>() - 1.0).abs() < 1e-4, "{:?}", weights);
+        }
+        assert_weights(&strafing.weights(), &[0.0, 0.0, 0.0, 1.0]);
+        assert_eq!(strafing.dominant_clip(), Some("left"));
+    }
+}
@@ -0,0 +1,389 @@
+use crate::bullets::{BulletBehaviour, BulletStore};
+use crate::capsule::Capsule;
+use crate::enemy::EnemyKind;
+use crate::enemy_ai::{AiBehaviour, AttackStyle, ChaseStyle};
+use crate::explosions::Explosion;
+use crate::{State, MONSTER_SPEED};
+use glam::{vec3, Mat4, Vec3};
+use small_gl_core::gl;
+use small_gl_core::gl::GLuint;
+use small_gl_core::shader::Shader;
+use std::f32::consts::TAU;
+
+pub const BOSS_COLLIDER: Capsule = Capsule { height: 1.2, radius: 0.24 };
+pub const BOSS_HEALTH: f32 = 150.0;
+pub const BOSS_MASS: f32 = 12.0;
+pub const BOSS_MODEL_SCALE: f32 = 3.0; // relative to the other enemies
+
+pub const BOSS_BEHAVIOUR: AiBehaviour = AiBehaviour {
+    spawn_time: 1.5,
+    sight_range: 30.0,
+    wander_speed: MONSTER_SPEED * 0.5,
+    chase_speed: MONSTER_SPEED * 0.7,
+    chase: ChaseStyle::Direct,
+    attack_range: 5.0,
+    wind_up_time: 1.0,
+    attack: AttackStyle::Lunge { speed: 6.0 },
+    attack_time: 0.6,
+    attack_cool_down: 4.0,
+    retreat_time: 0.0,
+    retreat_speed: 0.0,
+    stun_time: 0.0,
+    dying_time: 3.0,
+};
+
+// Death sequence
+const DEATH_BLAST_INTERVAL: f32 = 0.25; // seconds
+const DEATH_BLAST_SCATTER: f32 = 1.0;
+const DEATH_BLAST_RADIUS: f32 = 1.0;
+pub const BOSS_FINAL_BLAST_RADIUS: f32 = 5.0;
+pub const BOSS_FINAL_BLAST_DAMAGE: f32 = 10.0;
+pub const BOSS_FINAL_BLAST_KNOCKBACK: f32 = 6.0;
+
+const RING_BULLET: BulletBehaviour = BulletBehaviour::new(0, 0);
+const SUMMON_RADIUS: f32 = 2.0; // from the boss
+const SPAWN_RADIUS: f32 = 12.0; // from the player
+
+// Health bar, flat above the boss
+const HEALTH_BAR_HEIGHT: f32 = 1.6;
+const HEALTH_BAR_HALF_WIDTH: f32 = 1.0;
+const HEALTH_BAR_HALF_DEPTH: f32 = 0.08;
+const HEALTH_BAR_COLOR: Vec3 = vec3(0.9, 0.1, 0.1);
+const HEALTH_BAR_BACK_COLOR: Vec3 = vec3(0.15, 0.15, 0.15);
+
+/// When the boss turns up, whichever condition is met first. `None` disables that condition.
+#[derive(Debug, Clone, Copy)]
+pub struct BossTrigger {
+    /// Seconds the players have survived.
+    pub survival_time: Option<f32>,
+    pub kill_count: Option<u32>,
+}
+
+impl Default for BossTrigger {
+    fn default() -> Self {
+        Self {
+            survival_time: Some(120.0),
+            kill_count: Some(100),
+        }
+    }
+}
+
+struct BossPhase {
+    /// The phase starts once health falls to this fraction of the full pool.
+    health_fraction: f32,
+    /// Seconds between projectile rings, zero for none.
+    ring_interval: f32,
+    ring_bullets: u32,
+    /// Seconds between summons, zero for none.
+    summon_interval: f32,
+    summon_count: u32,
+}
+
+const BOSS_PHASES: [BossPhase; 3] = [
+    // charges only
+    BossPhase {
+        health_fraction: 1.0,
+        ring_interval: 0.0,
+        ring_bullets: 0,
+        summon_interval: 0.0,
+        summon_count: 0,
+    },
+    BossPhase {
+        health_fraction: 0.66,
+        ring_interval: 3.0,
+        ring_bullets: 12,
+        summon_interval: 0.0,
+        summon_count: 0,
+    },
+    BossPhase {
+        health_fraction: 0.33,
+        ring_interval: 2.0,
+        ring_bullets: 18,
+        summon_interval: 6.0,
+        summon_count: 4,
+    },
+];
+
+/// What the boss does in one tick of the fight.
+#[derive(Debug, Default)]
+struct BossAttacks {
+    /// Directions of the ring's bullets, empty when no ring is fired.
+    ring: Vec<Vec3>,
+    /// Where to summon minions.
+    summons: Vec<Vec3>,
+}
+
+/// Tracks the one boss fight per game: when it starts, which phase it is in and the death sequence.
+/// The boss itself lives in `State::enemies` as an `EnemyKind::Boss` so bullets and explosions treat it like any other enemy.
+pub struct BossEncounter {
+    pub trigger: BossTrigger,
+    survival_time: f32,
+    has_spawned: bool,
+    phase: usize,
+    ring_count_down: f32,
+    ring_offset: f32,
+    summon_count_down: f32,
+    death_blast_count_down: f32,
+}
+
+impl BossEncounter {
+    pub fn new(trigger: BossTrigger) -> Self {
+        Self {
+            trigger,
+            survival_time: 0.0,
+            has_spawned: false,
+            phase: 0,
+            ring_count_down: 0.0,
+            ring_offset: 0.0,
+            summon_count_down: 0.0,
+            death_blast_count_down: 0.0,
+        }
+    }
+
+    pub fn phase(&self) -> usize {
+        self.phase
+    }
+
+    /// Counts up the survival time. Returns where to spawn the boss, around the target, once the trigger is met.
+    pub fn update_trigger(&mut self, state: &mut State, target: Vec3) -> Option<Vec3> {
+        self.survival_time += state.delta_time;
+
+        if self.has_spawned {
+            return None;
+        }
+
+        if self.is_triggered(state.kill_count) {
+            let theta = state.rng.rand_float() * TAU;
+            let position = vec3(theta.sin().mul_add(SPAWN_RADIUS, target.x), target.y, theta.cos().mul_add(SPAWN_RADIUS, target.z));
+            self.has_spawned = true;
+            info!("Boss spawned after {:.1} seconds and {} kills", self.survival_time, state.kill_count);
+            return Some(position);
+        }
+
+        None
+    }
+
+    fn is_triggered(&self, kill_count: u32) -> bool {
+        let time_reached = self.trigger.survival_time.is_some_and(|time| self.survival_time >= time);
+        let kills_reached = self.trigger.kill_count.is_some_and(|kills| kill_count >= kills);
+        time_reached || kills_reached
+    }
+
+    /// Runs the phase attacks and the death sequence. Returns where to summon minions this tick.
+    pub fn update(&mut self, state: &mut State, projectiles: &mut BulletStore) -> Vec<Vec3> {
+        let Some(boss) = state.enemies.iter().find(|e| e.kind == EnemyKind::Boss) else {
+            return vec![];
+        };
+        let boss_position = boss.position;
+        let health_fraction = boss.health / BOSS_HEALTH;
+        let is_dying = boss.ai.is_dying();
+        let is_fighting = boss.ai.is_dangerous();
+
+        if is_dying {
+            self.death_blast_count_down -= state.delta_time;
+            if self.death_blast_count_down <= 0.0 {
+                let scatter = vec3(state.rng.random_clamped(), 0.0, state.rng.random_clamped()) * DEATH_BLAST_SCATTER;
+                state.explosions.queue(Explosion::new(boss_position + scatter, DEATH_BLAST_RADIUS, 0.0, 0.0));
+                self.death_blast_count_down += DEATH_BLAST_INTERVAL;
+            }
+            return vec![];
+        }
+
+        let attacks = self.fight(boss_position, health_fraction, is_fighting, state.delta_time);
+        for direction in attacks.ring {
+            projectiles.create_bullet(boss_position, direction, RING_BULLET);
+        }
+        attacks.summons
+    }
+
+    // Moves through the phases as the boss loses health and counts down to the phase's attacks.
+    fn fight(&mut self, boss_position: Vec3, health_fraction: f32, is_fighting: bool, delta_time: f32) -> BossAttacks {
+        let mut attacks = BossAttacks::default();
+
+        while self.phase + 1 < BOSS_PHASES.len() && health_fraction <= BOSS_PHASES[self.phase + 1].health_fraction {
+            self.phase += 1;
+            let phase = &BOSS_PHASES[self.phase];
+            self.ring_count_down = phase.ring_interval;
+            self.summon_count_down = phase.summon_interval;
+            info!("Boss entered phase {}", self.phase + 1);
+        }
+
+        if !is_fighting {
+            return attacks;
+        }
+
+        let phase = &BOSS_PHASES[self.phase];
+
+        if phase.ring_interval > 0.0 {
+            self.ring_count_down -= delta_time;
+            if self.ring_count_down <= 0.0 {
+                // each ring is turned half a gap from the last so the safe spots move
+                for i in 0..phase.ring_bullets {
+                    let theta = (i as f32 + self.ring_offset) * TAU / phase.ring_bullets as f32;
+                    attacks.ring.push(vec3(theta.sin(), 0.0, theta.cos()));
+                }
+                self.ring_offset = 0.5 - self.ring_offset;
+                self.ring_count_down += phase.ring_interval;
+            }
+        }
+
+        if phase.summon_interval > 0.0 {
+            self.summon_count_down -= delta_time;
+            if self.summon_count_down <= 0.0 {
+                for i in 0..phase.summon_count {
+                    let theta = i as f32 * TAU / phase.summon_count as f32;
+                    attacks.summons.push(boss_position + vec3(theta.sin(), 0.0, theta.cos()) * SUMMON_RADIUS);
+                }
+                self.summon_count_down += phase.summon_interval;
+            }
+        }
+
+        attacks
+    }
+
+    /// World space health bar floating above the boss, drawn with the flat color shader.
+    pub fn draw_health_bar(&self, shader: &Shader, projection_view: &Mat4, unit_square_vao: GLuint, state: &State) {
+        let Some(boss) = state.enemies.iter().find(|e| e.kind == EnemyKind::Boss) else {
+            return;
+        };
+        if boss.ai.is_dying() {
+            return;
+        }
+        let fraction = (boss.health / BOSS_HEALTH).clamp(0.0, 1.0);
+        let center = vec3(boss.position.x, HEALTH_BAR_HEIGHT, boss.position.z);
+
+        shader.use_shader();
+        shader.set_mat4("PV", projection_view);
+
+        unsafe {
+            gl::Disable(gl::CULL_FACE);
+            gl::BindVertexArray(unit_square_vao);
+        }
+
+        // the fill sits a little above the background and shrinks toward the left end
+        let bars = [
+            (center, HEALTH_BAR_HALF_WIDTH, HEALTH_BAR_BACK_COLOR),
+            (
+                center + vec3(0.0, 0.01, (fraction - 1.0) * HEALTH_BAR_HALF_WIDTH),
+                HEALTH_BAR_HALF_WIDTH * fraction,
+                HEALTH_BAR_COLOR,
+            ),
+        ];
+
+        for (position, half_width, color) in bars {
+            let mut model = Mat4::from_translation(position);
+            model *= Mat4::from_rotation_x(-90.0f32.to_radians());
+            model *= Mat4::from_scale(vec3(HEALTH_BAR_HALF_DEPTH, half_width, 1.0));
+
+            shader.set_mat4("model", &model);
+            shader.set_vec3("color", &color);
+
+            unsafe {
+                gl::DrawArrays(gl::TRIANGLES, 0, 6);
+            }
+        }
+
+        unsafe {
+            gl::Enable(gl::CULL_FACE);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DELTA_TIME: f32 = 0.25;
+
+    // Fights for a number of ticks at a health fraction, counting the rings and summons.
+    fn fight_for(boss: &mut BossEncounter, health_fraction: f32, ticks: usize) -> (Vec<usize>, usize) {
+        let mut rings = vec![];
+        let mut summons = 0;
+        for _ in 0..ticks {
+            let attacks = boss.fight(Vec3::ZERO, health_fraction, true, DELTA_TIME);
+            if !attacks.ring.is_empty() {
+                rings.push(attacks.ring.len());
+            }
+            summons += attacks.summons.len();
+        }
+        (rings, summons)
+    }
+
+    #[test]
+    fn test_trigger() {
+        let mut boss = BossEncounter::new(BossTrigger {
+            survival_time: Some(60.0),
+            kill_count: Some(10),
+        });
+        assert!(!boss.is_triggered(9));
+        assert!(boss.is_triggered(10));
+
+        boss.survival_time = 60.0;
+        assert!(boss.is_triggered(0));
+
+        boss.trigger.survival_time = None;
+        assert!(!boss.is_triggered(0));
+    }
+
+    #[test]
+    fn test_phase_thresholds() {
+        let mut boss = BossEncounter::new(BossTrigger::default());
+
+        // charges only until two thirds of its health is left
+        assert_eq!(fight_for(&mut boss, 0.7, 40), (vec![], 0));
+        assert_eq!(boss.phase(), 0);
+
+        // a ring of 12 every 3 seconds
+        assert_eq!(fight_for(&mut boss, 0.66, 12), (vec![12], 0));
+        assert_eq!(boss.phase(), 1);
+        assert_eq!(fight_for(&mut boss, 0.5, 12), (vec![12], 0));
+
+        // healing doesn't go back a phase
+        fight_for(&mut boss, 1.0, 1);
+        assert_eq!(boss.phase(), 1);
+
+        // rings of 18 every 2 seconds, and 4 minions every 6
+        assert_eq!(fight_for(&mut boss, 0.33, 24), (vec![18; 3], 4));
+        assert_eq!(boss.phase(), 2);
+        assert_eq!(fight_for(&mut boss, 0.1, 24), (vec![18; 3], 4));
+    }
+
+    #[test]
+    fn test_big_hit_skips_a_phase() {
+        let mut boss = BossEncounter::new(BossTrigger::default());
+        fight_for(&mut boss, 0.2, 1);
+        assert_eq!(boss.phase(), 2);
+    }
+
+    #[test]
+    fn test_rings_alternate_and_summons_surround_the_boss() {
+        let mut boss = BossEncounter::new(BossTrigger::default());
+        let boss_position = vec3(3.0, 0.0, -1.0);
+
+        // the countdown starts when the phase does
+        let mut fired = vec![];
+        let mut summons = vec![];
+        for _ in 0..24 {
+            let attacks = boss.fight(boss_position, 0.2, true, DELTA_TIME);
+            fired.extend(attacks.ring.first().copied());
+            summons.extend(attacks.summons);
+        }
+        assert_eq!(fired.len(), 3);
+        assert!(fired[0].abs_diff_eq(Vec3::Z, 1e-5));
+        let half_gap = TAU / 18.0 / 2.0;
+        assert!(fired[1].abs_diff_eq(vec3(half_gap.sin(), 0.0, half_gap.cos()), 1e-5));
+        assert!(fired[2].abs_diff_eq(Vec3::Z, 1e-5));
+
+        assert_eq!(summons.len(), 4);
+        for summon in summons {
+            assert!((summon.distance(boss_position) - SUMMON_RADIUS).abs() < 1e-5);
+        }
+
+        // nothing while the boss is still appearing
+        let mut boss = BossEncounter::new(BossTrigger::default());
+        for _ in 0..24 {
+            let attacks = boss.fight(boss_position, 0.2, false, DELTA_TIME);
+            assert!(attacks.ring.is_empty() && attacks.summons.is_empty());
+        }
+    }
+}
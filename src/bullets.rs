@@ -1,14 +1,18 @@
-use crate::aabb::Aabb;
-use crate::capsule::Capsule;
-use crate::enemy::{Enemy, ENEMY_COLLIDER};
-use crate::geom::{distance_between_line_segments, oriented_angle};
+use crate::boss::{BOSS_FINAL_BLAST_DAMAGE, BOSS_FINAL_BLAST_KNOCKBACK, BOSS_FINAL_BLAST_RADIUS};
+use crate::capsule::{Capsule, Collider};
+use crate::enemy::{Enemy, EnemyKind, ENEMY_EXPLOSION_DAMAGE, ENEMY_EXPLOSION_KNOCKBACK, ENEMY_EXPLOSION_RADIUS, LARGEST_ENEMY_COLLIDER};
+use crate::explosions::Explosion;
+use crate::floor::FLOOR_SIZE;
+use crate::geom::oriented_angle;
+use crate::rng::SimRng;
+use crate::spatial_hash::SpatialHash;
 use crate::sprite_sheet::{SpriteSheet, SpriteSheetSprite};
-use crate::State;
+use crate::thread_pool::ThreadPool;
+use crate::{State, MONSTER_Y, PLAYER_COLLISION_RADIUS};
 use glam::{vec3, vec4, Mat4, Quat, Vec3, Vec4Swizzles};
 use small_gl_core::gl::{GLsizei, GLsizeiptr, GLuint, GLvoid};
 use small_gl_core::shader::Shader;
 use small_gl_core::texture::{bind_texture, Texture, TextureConfig, TextureFilter, TextureType, TextureWrap};
-use small_gl_core::utils::random_clamped;
 use small_gl_core::{gl, NULL, SIZE_OF_FLOAT, SIZE_OF_QUAT, SIZE_OF_VEC3};
 use std::f32::consts::PI;
 
@@ -28,11 +32,308 @@ impl BulletGroup {
     }
 }
 
+/// Who fired the bullets in a store, and so who they can hit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Team {
+    Player,
+    Enemy,
+}
+
+/// How a bullet reacts when it hits something.
+#[derive(Debug, Clone, Copy)]
+pub struct BulletBehaviour {
+    /// Number of enemies the bullet passes through before it is spent.
+    pub pierce_count: i32,
+    /// Number of bounces off the floor and arena edges before it is spent.
+    pub ricochet_count: i32,
+}
+
+impl BulletBehaviour {
+    pub const fn new(pierce_count: i32, ricochet_count: i32) -> Self {
+        Self {
+            pierce_count,
+            ricochet_count,
+        }
+    }
+}
+
+// One worker's share of the per bullet arrays, starting at `start_index` in the store.
+struct BulletChunk<'a> {
+    start_index: usize,
+    positions: &'a mut [Vec3],
+    previous_positions: &'a mut [Vec3],
+    directions: &'a mut [Vec3],
+    quats: &'a mut [Quat],
+    ricochets: &'a mut [i32],
+    spent: &'a mut [bool],
+}
+
+impl<'a> BulletChunk<'a> {
+    fn split_at(self, mid: usize) -> (BulletChunk<'a>, BulletChunk<'a>) {
+        let (positions, rest_positions) = self.positions.split_at_mut(mid);
+        let (previous_positions, rest_previous_positions) = self.previous_positions.split_at_mut(mid);
+        let (directions, rest_directions) = self.directions.split_at_mut(mid);
+        let (quats, rest_quats) = self.quats.split_at_mut(mid);
+        let (ricochets, rest_ricochets) = self.ricochets.split_at_mut(mid);
+        let (spent, rest_spent) = self.spent.split_at_mut(mid);
+        (
+            BulletChunk {
+                start_index: self.start_index,
+                positions,
+                previous_positions,
+                directions,
+                quats,
+                ricochets,
+                spent,
+            },
+            BulletChunk {
+                start_index: self.start_index + mid,
+                positions: rest_positions,
+                previous_positions: rest_previous_positions,
+                directions: rest_directions,
+                quats: rest_quats,
+                ricochets: rest_ricochets,
+                spent: rest_spent,
+            },
+        )
+    }
+
+    fn integrate(&mut self, delta_position_magnitude: f32) {
+        for i in 0..self.positions.len() {
+            if self.spent[i] {
+                continue;
+            }
+            self.previous_positions[i] = self.positions[i];
+            self.positions[i] += self.directions[i] * delta_position_magnitude;
+            self.ricochet(i);
+        }
+    }
+
+    // Bounces the bullet off the floor and the arena edges, or spends it when it has no ricochets left.
+    fn ricochet(&mut self, i: usize) {
+        let position = self.positions[i];
+        let direction = self.directions[i];
+
+        // surface plane as normal and offset, normal.dot(p) + offset = 0
+        let (normal, plane_offset) = if position.y < 0.0 && direction.y < 0.0 {
+            (vec3(0.0, 1.0, 0.0), 0.0)
+        } else if position.x.abs() > ARENA_HALF_SIZE && position.x * direction.x > 0.0 {
+            (vec3(-position.x.signum(), 0.0, 0.0), ARENA_HALF_SIZE)
+        } else if position.z.abs() > ARENA_HALF_SIZE && position.z * direction.z > 0.0 {
+            (vec3(0.0, 0.0, -position.z.signum()), ARENA_HALF_SIZE)
+        } else {
+            return;
+        };
+
+        if self.ricochets[i] <= 0 {
+            self.spent[i] = true;
+            self.quats[i] = SPENT_BULLET_QUAT;
+            return;
+        }
+        self.ricochets[i] -= 1;
+
+        let reflected = direction - 2.0 * direction.dot(normal) * normal;
+
+        // mirror the overshoot back onto the playing side of the surface
+        let overshoot = normal.dot(position) + plane_offset;
+
+        self.positions[i] = position - 2.0 * overshoot * normal;
+        self.directions[i] = reflected;
+        self.quats[i] = Quat::from_rotation_arc(direction, reflected) * self.quats[i];
+    }
+}
+
+// The per bullet arrays, apart from the GL objects they are drawn with.
+#[derive(Default)]
+struct Bullets {
+    positions: Vec<Vec3>,
+    // where each bullet was before this tick's move, the start of the path swept for enemy hits
+    previous_positions: Vec<Vec3>,
+    quats: Vec<Quat>,
+    directions: Vec<Vec3>,
+    pierces: Vec<i32>,
+    ricochets: Vec<i32>,
+    spent: Vec<bool>,
+    // ids of the enemies each bullet has already gone through, a piercing bullet can still be inside one next tick
+    hit_enemies: Vec<Vec<u32>>,
+}
+
+impl Bullets {
+    fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    // Adds `count` bullets at the end for the caller to place and aim.
+    fn grow(&mut self, count: usize, behaviour: BulletBehaviour) {
+        let len = self.len() + count;
+        self.positions.resize(len, Vec3::default());
+        self.previous_positions.resize(len, Vec3::default());
+        self.quats.resize(len, Quat::default());
+        self.directions.resize(len, Vec3::default());
+        self.pierces.resize(len, behaviour.pierce_count);
+        self.ricochets.resize(len, behaviour.ricochet_count);
+        self.spent.resize(len, false);
+        self.hit_enemies.resize_with(len, Vec::new);
+    }
+
+    fn push(&mut self, position: Vec3, direction: Vec3, quat: Quat, behaviour: BulletBehaviour) {
+        let index = self.len();
+        self.grow(1, behaviour);
+        self.positions[index] = position;
+        self.previous_positions[index] = position;
+        self.directions[index] = direction;
+        self.quats[index] = quat;
+    }
+
+    fn clear(&mut self) {
+        self.positions.clear();
+        self.previous_positions.clear();
+        self.quats.clear();
+        self.directions.clear();
+        self.pierces.clear();
+        self.ricochets.clear();
+        self.spent.clear();
+        self.hit_enemies.clear();
+    }
+
+    // Removes the first `count` bullets.
+    fn drain_front(&mut self, count: usize) {
+        self.positions.drain(0..count);
+        self.previous_positions.drain(0..count);
+        self.directions.drain(0..count);
+        self.quats.drain(0..count);
+        self.pierces.drain(0..count);
+        self.ricochets.drain(0..count);
+        self.spent.drain(0..count);
+        self.hit_enemies.drain(0..count);
+    }
+
+    // Splits the per bullet arrays over the range into chunks of `chunk_len` bullets for the workers.
+    fn chunks(&mut self, range: std::ops::Range<usize>, chunk_len: usize) -> Vec<BulletChunk<'_>> {
+        let mut rest = BulletChunk {
+            start_index: range.start,
+            positions: &mut self.positions[range.clone()],
+            previous_positions: &mut self.previous_positions[range.clone()],
+            directions: &mut self.directions[range.clone()],
+            quats: &mut self.quats[range.clone()],
+            ricochets: &mut self.ricochets[range.clone()],
+            spent: &mut self.spent[range],
+        };
+
+        let mut chunks = vec![];
+        while rest.positions.len() > chunk_len {
+            let (front, back) = rest.split_at(chunk_len);
+            chunks.push(front);
+            rest = back;
+        }
+        chunks.push(rest);
+        chunks
+    }
+
+    fn spend(&mut self, bullet_index: usize) {
+        self.spent[bullet_index] = true;
+        self.quats[bullet_index] = SPENT_BULLET_QUAT;
+    }
+
+    // Each run of bullets asks the grid once for the enemies around the box its paths fill this tick, and its bullets
+    // only test those. Finding the hits only reads, so it is spread across the workers, then the hits are applied in
+    // bullet order as a single thread would. Returns where the killing shots met their enemies.
+    fn collide_with_enemies(&mut self, runs: &[std::ops::Range<usize>], grid: &SpatialHash, enemies: &mut [Enemy], thread_pool: &ThreadPool) -> Vec<Vec3> {
+        let positions = &self.positions;
+        let previous_positions = &self.previous_positions;
+        let directions = &self.directions;
+        let spent = &self.spent;
+        let hit_enemies = &self.hit_enemies;
+        let nearby = &*enemies;
+
+        let hits = thread_pool.map_chunks(runs.len(), MIN_COLLIDING_BULLETS_PER_WORKER / COLLISION_RUN_LEN, |range| {
+            let mut nearby_enemies = vec![];
+            let mut hits = vec![];
+
+            for run in runs[range].iter() {
+                let live_bullets = run.clone().filter(|&i| !spent[i]);
+                let (min, max) = live_bullets.fold((Vec3::MAX, Vec3::MIN), |(min, max), i| {
+                    let (start, end) = (previous_positions[i], positions[i]);
+                    (min.min(start).min(end), max.max(start).max(end))
+                });
+                if min.x > max.x {
+                    continue;
+                }
+
+                grid.query_box(
+                    min - BULLET_ENEMY_MAX_COLLISION_DIST,
+                    max + BULLET_ENEMY_MAX_COLLISION_DIST,
+                    &mut nearby_enemies,
+                );
+                if nearby_enemies.is_empty() {
+                    continue;
+                }
+
+                for bullet_index in run.clone() {
+                    if spent[bullet_index] {
+                        continue;
+                    }
+
+                    let start = previous_positions[bullet_index];
+                    let end = positions[bullet_index];
+                    let direction = directions[bullet_index];
+
+                    let first_hit = hits.len();
+                    for &enemy_index in nearby_enemies.iter() {
+                        let enemy = &nearby[enemy_index];
+                        if !enemy.can_be_hit() || hit_enemies[bullet_index].contains(&enemy.id) {
+                            continue;
+                        }
+                        if let Some(time_of_impact) = bullet_collides_with_enemy(&start, &end, &direction, enemy) {
+                            hits.push((bullet_index, enemy_index, time_of_impact));
+                        }
+                    }
+
+                    // a piercing bullet goes through the enemies in the order it reaches them
+                    hits[first_hit..].sort_by(|a, b| a.2.total_cmp(&b.2));
+                }
+            }
+
+            hits
+        });
+
+        let mut kills = vec![];
+
+        for (bullet_index, enemy_index, time_of_impact) in hits {
+            let enemy = &mut enemies[enemy_index];
+
+            // spent on an earlier enemy, or the enemy was finished off by an earlier bullet
+            if self.spent[bullet_index] || !enemy.can_be_hit() {
+                continue;
+            }
+
+            let direction = self.directions[bullet_index];
+            enemy.take_hit(BULLET_DAMAGE, direction * BULLET_IMPULSE);
+            self.hit_enemies[bullet_index].push(enemy.id);
+
+            if !enemy.can_be_hit() {
+                // the killing shot, shown where the bullet actually met the enemy rather than where it ended the tick
+                enemy.killed_by_bullet = true;
+                kills.push(self.previous_positions[bullet_index].lerp(self.positions[bullet_index], time_of_impact));
+            }
+
+            if self.pierces[bullet_index] > 0 {
+                self.pierces[bullet_index] -= 1;
+            } else {
+                self.spend(bullet_index);
+            }
+        }
+
+        kills
+    }
+}
+
 pub struct BulletStore {
-    all_bullet_positions: Vec<Vec3>,
-    all_bullet_quats: Vec<Quat>,
-    all_bullet_directions: Vec<Vec3>,
-    // thread_pool
+    team: Team,
+    bullet_speed: f32,
+    bullet_lifetime: f32,
+    bullets: Bullets,
+    enemy_grid: SpatialHash,
     bullet_vao: GLuint,
     rotation_vbo: GLuint,
     offset_vbo: GLuint,
@@ -50,15 +351,40 @@ const BULLET_LIFETIME: f32 = 1.0;
 const BULLET_SPEED: f32 = 15.0;
 // const BULLET_SPEED: f32 = 1.0;
 // Game units per second
+const ENEMY_BULLET_LIFETIME: f32 = 3.0;
+const ENEMY_BULLET_SPEED: f32 = 6.0;
+const BULLET_DAMAGE: f32 = 1.0;
+const BULLET_IMPULSE: f32 = 0.4;
 const ROTATION_PER_BULLET: f32 = 3.0 * PI / 180.0;
 
 const SCALE_VEC: Vec3 = vec3(BULLET_SCALE, BULLET_SCALE, BULLET_SCALE);
 const BULLET_NORMAL: Vec3 = vec3(0.0, 1.0, 0.0);
 const CANONICAL_DIR: Vec3 = vec3(0.0, 0.0, 1.0);
 
+const ARENA_HALF_SIZE: f32 = FLOOR_SIZE / 2.0;
+
+// A zero quaternion collapses the bullet quads to a point in the instanced shader,
+// which hides spent bullets until their group expires.
+const SPENT_BULLET_QUAT: Quat = Quat::from_xyzw(0.0, 0.0, 0.0, 0.0);
+
 const BULLET_COLLIDER: Capsule = Capsule { height: 0.3, radius: 0.03 };
 
-const BULLET_ENEMY_MAX_COLLISION_DIST: f32 = BULLET_COLLIDER.height / 2.0 + BULLET_COLLIDER.radius + ENEMY_COLLIDER.height / 2.0 + ENEMY_COLLIDER.radius;
+const BULLET_ENEMY_MAX_COLLISION_DIST: f32 =
+    BULLET_COLLIDER.height / 2.0 + BULLET_COLLIDER.radius + LARGEST_ENEMY_COLLIDER.height / 2.0 + LARGEST_ENEMY_COLLIDER.radius;
+// Measured with benches/bullet_broadphase.rs. Larger cells make the per tick rebuild cheaper, smaller ones give
+// fewer candidates, and a run of bullets from one shot sits close enough together for one query to serve it.
+const ENEMY_GRID_CELL_SIZE: f32 = 2.0;
+const COLLISION_RUN_LEN: usize = 16;
+const BULLET_PLAYER_MAX_COLLISION_DIST: f32 = BULLET_COLLIDER.height / 2.0 + BULLET_COLLIDER.radius + PLAYER_COLLISION_RADIUS;
+
+// Handing a chunk to a worker costs around fifteen microseconds, so each one needs to take on more work than that.
+// Set from benches/thread_pool.rs. A whole 20 by 20 spread takes less, and stays on the calling thread.
+const MIN_SPREAD_ROWS_PER_WORKER: usize = 48;
+const MIN_INTEGRATED_BULLETS_PER_WORKER: usize = 2560;
+const MIN_COLLIDING_BULLETS_PER_WORKER: usize = 896;
+
+// create_bullets starts from a half turn around x, single bullets use the same convention
+const BULLET_BASE_QUAT: Quat = Quat::from_xyzw(1.0, 0.0, 0.0, 0.0);
 
 // Trim off margin around the bullet image
 // const TEXTURE_MARGIN: f32 = 0.0625;
@@ -111,7 +437,7 @@ const BULLET_INDICES_H_V: [i32; 12] = [
 ];
 
 impl BulletStore {
-    pub fn new(unit_square_vao: i32) -> Self {
+    pub fn new(unit_square_vao: i32, team: Team) -> Self {
         // initialize_buffer_and_create
         let mut bullet_vao: GLuint = 0;
         let mut bullet_vertices_vbo: GLuint = 0;
@@ -129,8 +455,11 @@ impl BulletStore {
             wrap: TextureWrap::Repeat,
         };
 
-        let bullet_texture = Texture::new("angrygl_assets/bullet/bullet_texture_transparent.png", &texture_config).unwrap();
-        // let bullet_texture = Texture::new("angrygl_assets/bullet/red_bullet_transparent.png", &texture_config).unwrap();
+        let (bullet_texture_path, bullet_speed, bullet_lifetime) = match team {
+            Team::Player => ("angrygl_assets/bullet/bullet_texture_transparent.png", BULLET_SPEED, BULLET_LIFETIME),
+            Team::Enemy => ("angrygl_assets/bullet/red_bullet_transparent.png", ENEMY_BULLET_SPEED, ENEMY_BULLET_LIFETIME),
+        };
+        let bullet_texture = Texture::new(bullet_texture_path, &texture_config).unwrap();
         // let bullet_texture = Texture::new("angrygl_assets/bullet/red_and_green_bullet_transparent.png", &texture_config).unwrap();
 
         let vertices = BULLET_VERTICES_H_V;
@@ -195,9 +524,11 @@ impl BulletStore {
         let bullet_impact_spritesheet = SpriteSheet::new(texture_impact_sprite_sheet, 11, 0.05);
 
         Self {
-            all_bullet_positions: Default::default(),
-            all_bullet_quats: Default::default(),
-            all_bullet_directions: Default::default(),
+            team,
+            bullet_speed,
+            bullet_lifetime,
+            bullets: Bullets::default(),
+            enemy_grid: SpatialHash::new(ENEMY_GRID_CELL_SIZE),
             bullet_vao,
             rotation_vbo: instance_rotation_vbo,
             offset_vbo: instance_offset_vbo,
@@ -209,7 +540,17 @@ impl BulletStore {
         }
     }
 
-    pub fn create_bullets(&mut self, dx: f32, dz: f32, muzzle_transform: &Mat4, spread_amount: i32) {
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_bullets(
+        &mut self,
+        dx: f32,
+        dz: f32,
+        muzzle_transform: &Mat4,
+        spread_amount: i32,
+        behaviour: BulletBehaviour,
+        rng: &mut SimRng,
+        thread_pool: &ThreadPool,
+    ) {
         // let spreadAmount = 100;
 
         let muzzle_world_position = *muzzle_transform * vec4(0.0, 0.0, 0.0, 1.0);
@@ -228,34 +569,35 @@ impl BulletStore {
         // direction angle with respect to the canonical direction
         let theta = oriented_angle(x, y, rot_vec) * -1.0;
 
-        let mut mid_dir_quat = Quat::from_xyzw(1.0, 0.0, 0.0, 0.0);
+        let mut mid_dir_quat = BULLET_BASE_QUAT;
 
         mid_dir_quat *= Quat::from_axis_angle(rot_vec, theta.to_radians());
 
-        let start_index = self.all_bullet_positions.len();
+        let start_index = self.bullets.len();
 
         let bullet_group_size = spread_amount * spread_amount;
 
-        let bullet_group = BulletGroup::new(start_index, bullet_group_size, BULLET_LIFETIME);
+        let bullet_group = BulletGroup::new(start_index, bullet_group_size, self.bullet_lifetime);
 
-        self.all_bullet_positions.resize(start_index + bullet_group_size as usize, Vec3::default());
-        self.all_bullet_quats.resize(start_index + bullet_group_size as usize, Quat::default());
-        self.all_bullet_directions.resize(start_index + bullet_group_size as usize, Vec3::default());
+        self.bullets.grow(bullet_group_size as usize, behaviour);
 
-        let parallelism = 1; // threadPool->numWorkers();
-        let worker_group_size = spread_amount / parallelism;
+        // the noise is drawn up front in row order, so the random sequence doesn't depend on the workers
+        let row_noise: Vec<f32> = (0..spread_amount).map(|_| rng.random_clamped() * 0.02).collect();
+        let spread_centering = ROTATION_PER_BULLET * (spread_amount as f32 - 1.0) / 4.0;
+        // let spread_centering = 0.0;
 
-        // todo: make async
-        for p in 0..parallelism {
-            let i_start = p * worker_group_size;
+        let row_len = spread_amount as usize;
+        let rows_per_worker = thread_pool.chunk_len(row_len, MIN_SPREAD_ROWS_PER_WORKER);
+        let chunks = self
+            .bullets
+            .chunks(start_index..start_index + bullet_group_size as usize, rows_per_worker * row_len);
 
-            let i_end = if p == (parallelism - 1) { spread_amount } else { i_start + worker_group_size };
+        thread_pool.for_each(chunks, |chunk| {
+            let first_row = (chunk.start_index - start_index) / row_len;
 
-            let spread_centering = ROTATION_PER_BULLET * (spread_amount as f32 - 1.0) / 4.0;
-            // let spread_centering = 0.0;
-
-            for i in i_start..i_end {
-                let noise = random_clamped() * 0.02;
+            for row in 0..chunk.positions.len() / row_len {
+                let i = (first_row + row) as i32;
+                let noise = row_noise[i as usize];
 
                 let y_quat = mid_dir_quat
                     * Quat::from_axis_angle(
@@ -274,84 +616,88 @@ impl BulletStore {
 
                     let dir_glam = rot_quat.mul_vec3(CANONICAL_DIR * -1.0);
 
-                    let pos = (i * spread_amount + j) as usize + start_index;
+                    let pos = row * row_len + j as usize;
 
-                    self.all_bullet_positions[pos] = projectile_spawn_point;
-                    self.all_bullet_directions[pos] = dir_glam;
-                    self.all_bullet_quats[pos] = rot_quat;
+                    chunk.positions[pos] = projectile_spawn_point;
+                    chunk.directions[pos] = dir_glam;
+                    chunk.quats[pos] = rot_quat;
                 }
             }
-        }
+        });
 
         self.bullet_groups.push(bullet_group);
     }
 
+    /// Removes every bullet and impact, for a restart.
+    pub fn clear(&mut self) {
+        self.bullets.clear();
+        self.bullet_groups.clear();
+        self.bullet_impact_sprites.clear();
+    }
+
+    /// Fires a single bullet, used by enemies.
+    pub fn create_bullet(&mut self, position: Vec3, direction: Vec3, behaviour: BulletBehaviour) {
+        let direction = direction.normalize_or_zero();
+        if direction == Vec3::ZERO {
+            return;
+        }
+
+        // direction = quat * -CANONICAL_DIR, with the same roll as the spread bullets
+        let quat = BULLET_BASE_QUAT * Quat::from_rotation_arc(CANONICAL_DIR * -1.0, BULLET_BASE_QUAT * direction);
+
+        let start_index = self.bullets.len();
+
+        self.bullets.push(position, direction, quat, behaviour);
+
+        self.bullet_groups.push(BulletGroup::new(start_index, 1, self.bullet_lifetime));
+    }
+
     pub fn update_bullets(&mut self, state: &mut State) {
         //}, bulletImpactSprites: &mut Vec<SpriteSheetSprite>) {
 
-        let use_aabb = !state.enemies.is_empty();
-        let num_sub_groups = if use_aabb { 9 } else { 1 };
+        let collide_with_enemies = !state.enemies.is_empty() && self.team == Team::Player;
+        if collide_with_enemies {
+            self.enemy_grid.rebuild(state.enemies.iter().map(|e| e.position));
+        }
 
-        let delta_position_magnitude = state.delta_time * BULLET_SPEED;
+        let delta_position_magnitude = state.delta_time * self.bullet_speed;
 
         let mut first_live_bullet_group: usize = 0;
 
+        // groups share a lifetime, so the expired ones are always at the front
         for group in self.bullet_groups.iter_mut() {
             group.time_to_live -= state.delta_time;
-
             if group.time_to_live <= 0.0 {
                 first_live_bullet_group += 1;
-            } else {
-                // could make this async
-                let bullet_group_start_index = group.start_index as i32;
-                let num_bullets_in_group = group.group_size;
-                let sub_group_size = num_bullets_in_group / num_sub_groups;
-
-                for sub_group in 0..num_sub_groups {
-                    let mut bullet_start = sub_group_size * sub_group;
-
-                    let mut bullet_end = if sub_group == (num_sub_groups - 1) {
-                        num_bullets_in_group
-                    } else {
-                        bullet_start + sub_group_size
-                    };
-
-                    bullet_start += bullet_group_start_index;
-                    bullet_end += bullet_group_start_index;
-
-                    for bullet_index in bullet_start..bullet_end {
-                        self.all_bullet_positions[bullet_index as usize] += self.all_bullet_directions[bullet_index as usize] * delta_position_magnitude;
-                    }
-
-                    let mut subgroup_bound_box = Aabb::new();
-
-                    if use_aabb {
-                        for bullet_index in bullet_start..bullet_end {
-                            subgroup_bound_box.expand_to_include(self.all_bullet_positions[bullet_index as usize]);
-                        }
-
-                        subgroup_bound_box.expand_by(BULLET_ENEMY_MAX_COLLISION_DIST);
-                    }
-
-                    for i in 0..state.enemies.len() {
-                        let enemy = &mut state.enemies[i];
+            }
+        }
 
-                        if use_aabb && !subgroup_bound_box.contains_point(enemy.position) {
-                            continue;
-                        }
-                        for bullet_index in bullet_start..bullet_end {
-                            if bullet_collides_with_enemy(
-                                &self.all_bullet_positions[bullet_index as usize],
-                                &self.all_bullet_directions[bullet_index as usize],
-                                enemy,
-                            ) {
-                                // println!("killed enemy!");
-                                enemy.is_alive = false;
-                                break;
-                            }
-                        }
-                    }
-                }
+        let live_start = self.bullet_groups.get(first_live_bullet_group).map_or(self.bullets.len(), |g| g.start_index);
+        let live_bullets = live_start..self.bullets.len();
+
+        let chunk_len = state.thread_pool.chunk_len(live_bullets.len(), MIN_INTEGRATED_BULLETS_PER_WORKER);
+        state.thread_pool.for_each(self.bullets.chunks(live_bullets.clone(), chunk_len), |mut chunk| {
+            chunk.integrate(delta_position_magnitude)
+        });
+
+        if self.team == Team::Enemy {
+            self.collide_with_player(live_bullets, state);
+        } else if collide_with_enemies {
+            // runs don't cross from one shot to the next, which would stretch their box across the arena
+            let runs: Vec<std::ops::Range<usize>> = self.bullet_groups[first_live_bullet_group..]
+                .iter()
+                .flat_map(|group| {
+                    let end = group.start_index + group.group_size as usize;
+                    (group.start_index..end)
+                        .step_by(COLLISION_RUN_LEN)
+                        .map(move |start| start..(start + COLLISION_RUN_LEN).min(end))
+                })
+                .collect();
+            let kills = self
+                .bullets
+                .collide_with_enemies(&runs, &self.enemy_grid, &mut state.enemies, &state.thread_pool);
+            for hit_point in kills {
+                self.bullet_impact_sprites.push(SpriteSheetSprite::new(hit_point));
             }
         }
 
@@ -364,9 +710,7 @@ impl BulletStore {
         }
 
         if first_live_bullet != 0 {
-            self.all_bullet_positions.drain(0..first_live_bullet);
-            self.all_bullet_directions.drain(0..first_live_bullet);
-            self.all_bullet_quats.drain(0..first_live_bullet);
+            self.bullets.drain_front(first_live_bullet);
 
             for group in self.bullet_groups.iter_mut() {
                 group.start_index -= first_live_bullet;
@@ -384,17 +728,66 @@ impl BulletStore {
 
         for enemy in state.enemies.iter() {
             if !enemy.is_alive {
-                self.bullet_impact_sprites.push(SpriteSheetSprite::new(enemy.position));
+                // bullet kills had their impact shown where the shot landed
+                if !enemy.killed_by_bullet {
+                    self.bullet_impact_sprites.push(SpriteSheetSprite::new(enemy.position));
+                }
                 state.burn_marks.add_mark(enemy.position);
                 state.sound_system.play_enemy_destroyed();
+                state.kill_count += 1;
+
+                match enemy.kind {
+                    EnemyKind::Exploding => state.explosions.queue(Explosion::new(
+                        enemy.position,
+                        ENEMY_EXPLOSION_RADIUS,
+                        ENEMY_EXPLOSION_DAMAGE,
+                        ENEMY_EXPLOSION_KNOCKBACK,
+                    )),
+                    // the end of the boss death sequence
+                    EnemyKind::Boss => state.explosions.queue(Explosion::new(
+                        enemy.position,
+                        BOSS_FINAL_BLAST_RADIUS,
+                        BOSS_FINAL_BLAST_DAMAGE,
+                        BOSS_FINAL_BLAST_KNOCKBACK,
+                    )),
+                    EnemyKind::Melee | EnemyKind::Ranged => {}
+                }
             }
         }
 
         state.enemies.retain(|e| e.is_alive);
     }
 
+    fn collide_with_player(&mut self, bullets: std::ops::Range<usize>, state: &mut State) {
+        for player in state.players.iter() {
+            let mut player = player.borrow_mut();
+
+            if !player.is_alive || player.is_invulnerable(state.frame_time) {
+                continue;
+            }
+
+            let player_collision_position = vec3(player.position.x, MONSTER_Y, player.position.z);
+
+            for bullet_index in bullets.clone() {
+                if self.bullets.spent[bullet_index] {
+                    continue;
+                }
+                if bullet_collides_with_player(
+                    &self.bullets.positions[bullet_index],
+                    &self.bullets.directions[bullet_index],
+                    &player_collision_position,
+                ) {
+                    self.bullet_impact_sprites.push(SpriteSheetSprite::new(self.bullets.positions[bullet_index]));
+                    self.bullets.spend(bullet_index);
+                    player.die(state.frame_time);
+                    break;
+                }
+            }
+        }
+    }
+
     pub fn draw_bullets(&mut self, shader: &Shader, projection_view: &Mat4) {
-        if self.all_bullet_positions.is_empty() {
+        if self.bullets.positions.is_empty() {
             return;
         }
 
@@ -431,8 +824,8 @@ impl BulletStore {
 
             gl::BufferData(
                 gl::ARRAY_BUFFER,
-                (self.all_bullet_quats.len() * SIZE_OF_QUAT) as GLsizeiptr,
-                self.all_bullet_quats.as_ptr() as *const GLvoid,
+                (self.bullets.quats.len() * SIZE_OF_QUAT) as GLsizeiptr,
+                self.bullets.quats.as_ptr() as *const GLvoid,
                 gl::STREAM_DRAW,
             );
 
@@ -440,8 +833,8 @@ impl BulletStore {
 
             gl::BufferData(
                 gl::ARRAY_BUFFER,
-                (self.all_bullet_positions.len() * SIZE_OF_VEC3) as GLsizeiptr,
-                self.all_bullet_positions.as_ptr() as *const GLvoid,
+                (self.bullets.positions.len() * SIZE_OF_VEC3) as GLsizeiptr,
+                self.bullets.positions.as_ptr() as *const GLvoid,
                 gl::STREAM_DRAW,
             );
 
@@ -450,7 +843,7 @@ impl BulletStore {
                 12, // 6,
                 gl::UNSIGNED_INT,
                 NULL,
-                self.all_bullet_positions.len() as GLsizei,
+                self.bullets.positions.len() as GLsizei,
             );
         }
     }
@@ -506,19 +899,43 @@ impl BulletStore {
     }
 }
 
-fn bullet_collides_with_enemy(position: &Vec3, direction: &Vec3, enemy: &Enemy) -> bool {
-    if position.distance(enemy.position) > BULLET_ENEMY_MAX_COLLISION_DIST {
-        return false;
+// Sweeps the bullet capsule over the path it took this tick, from `start` to `end`, so fast bullets can't skip through
+// thin enemies on a long frame. A bullet that bounced is swept straight across from where it was to where it ended up.
+// Returns the time of impact as a fraction of the tick, 0 at `start` and 1 at `end`.
+fn bullet_collides_with_enemy(start: &Vec3, end: &Vec3, direction: &Vec3, enemy: &Enemy) -> Option<f32> {
+    let path = *end - *start;
+    let travel = path.length();
+    // a bullet that didn't move lies along its heading
+    let direction = path.try_normalize().unwrap_or(*direction);
+
+    let path_middle = start.lerp(*end, 0.5);
+    if path_middle.distance(enemy.position) > BULLET_ENEMY_MAX_COLLISION_DIST + travel / 2.0 {
+        return None;
     }
 
-    let a0 = *position - *direction * (BULLET_COLLIDER.height / 2.0);
-    let a1 = *position + *direction * (BULLET_COLLIDER.height / 2.0);
-    let b0 = enemy.position - enemy.dir * (ENEMY_COLLIDER.height / 2.0);
-    let b1 = enemy.position + enemy.dir * (ENEMY_COLLIDER.height / 2.0);
+    let bullet = BULLET_COLLIDER.at(*start, direction);
+    let enemy_collider = enemy.kind.collider().at(enemy.position, enemy.dir);
+
+    if bullet.intersects(&enemy_collider) {
+        return Some(0.0);
+    }
 
-    let closet_distance = distance_between_line_segments(&a0, &a1, &b0, &b1);
+    // moving along its own length, the bullet can only come into contact at its leading tip
+    let reach = Collider {
+        radius: enemy_collider.radius + bullet.radius,
+        ..enemy_collider
+    };
+    let distance = reach.ray_intersection(bullet.b, direction)?;
 
-    closet_distance <= (BULLET_COLLIDER.radius + ENEMY_COLLIDER.radius)
+    (distance <= travel).then_some(distance / travel)
+}
+
+fn bullet_collides_with_player(position: &Vec3, direction: &Vec3, player_position: &Vec3) -> bool {
+    if position.distance(*player_position) > BULLET_PLAYER_MAX_COLLISION_DIST {
+        return false;
+    }
+
+    BULLET_COLLIDER.at(*position, *direction).intersects_sphere(*player_position, PLAYER_COLLISION_RADIUS)
 }
 
 pub fn rotate_by_quat(v: &Vec3, q: &Quat) -> Vec3 {
@@ -591,4 +1008,191 @@ mod tests {
             println!("angle: {}  direction: {:?}   theta: {:?}", angle, normalized_direction, theta);
         }
     }
+
+    #[test]
+    fn test_swept_collision_catches_fast_bullets() {
+        use super::bullet_collides_with_enemy;
+        use crate::enemy::Enemy;
+        use glam::Vec3;
+
+        let enemy = Enemy::new(vec3(0.0, 0.0, 0.0), vec3(0.0, 0.0, 1.0));
+        let direction = vec3(1.0, 0.0, 0.0);
+        let sweep = |start: Vec3, end: Vec3, direction: Vec3| bullet_collides_with_enemy(&start, &end, &direction, &enemy);
+
+        // a long tick carries the bullet from well before the enemy to well past it
+        let start = vec3(-5.0, 0.0, 0.0);
+        let end = vec3(5.0, 0.0, 0.0);
+        let toi = sweep(start, end, direction).expect("the bullet passed through the enemy");
+        let hit_x = start.lerp(end, toi).x;
+        assert!(hit_x < 0.0 && hit_x > -1.0, "hit at {}", hit_x);
+
+        assert_eq!(sweep(vec3(0.1, 0.0, 0.0), vec3(0.1, 0.0, 0.0), direction), Some(0.0));
+        assert_eq!(sweep(vec3(-5.0, 0.0, 3.0), vec3(5.0, 0.0, 3.0), direction), None);
+        assert_eq!(sweep(vec3(-6.0, 0.0, 0.0), vec3(-5.0, 0.0, 0.0), direction), None);
+
+        // bounced back off a wall this tick, the sweep runs from where the bullet was rather than back along its new heading
+        let toi = sweep(vec3(-1.0, 0.0, 0.0), vec3(1.0, 0.0, 0.0), -direction).expect("the bullet passed through the enemy");
+        assert!(toi > 0.0 && toi < 1.0);
+        assert_eq!(sweep(vec3(2.0, 0.0, 0.0), vec3(1.0, 0.0, 0.0), direction), None);
+    }
+
+    #[test]
+    fn test_piercing_bullet_hits_an_enemy_once() {
+        use super::{BulletBehaviour, Bullets, BULLET_COLLIDER, BULLET_DAMAGE, ENEMY_GRID_CELL_SIZE};
+        use crate::enemy::Enemy;
+        use crate::spatial_hash::SpatialHash;
+        use crate::thread_pool::ThreadPool;
+        use glam::Quat;
+
+        let mut enemies = vec![Enemy::new(vec3(0.0, 0.0, 0.0), vec3(0.0, 0.0, 1.0))];
+        enemies[0].id = 7;
+        let full_health = enemies[0].health;
+
+        let mut bullets = Bullets::default();
+        bullets.push(vec3(-1.0, 0.0, 0.0), vec3(1.0, 0.0, 0.0), Quat::IDENTITY, BulletBehaviour::new(2, 0));
+
+        let mut grid = SpatialHash::new(ENEMY_GRID_CELL_SIZE);
+        let pool = ThreadPool::new(1);
+
+        // slow enough that the bullet is inside the enemy for several ticks on its way through
+        let travel = 0.05;
+        let mut ticks_overlapping = 0;
+        for _ in 0..40 {
+            for mut chunk in bullets.chunks(0..1, 1) {
+                chunk.integrate(travel);
+            }
+            if enemies[0]
+                .collider()
+                .intersects(&BULLET_COLLIDER.at(bullets.positions[0], bullets.directions[0]))
+            {
+                ticks_overlapping += 1;
+            }
+            grid.rebuild(enemies.iter().map(|e| e.position));
+            bullets.collide_with_enemies(std::slice::from_ref(&(0..1)), &grid, &mut enemies, &pool);
+        }
+
+        assert!(ticks_overlapping > 1, "overlapped for {} ticks", ticks_overlapping);
+        assert_eq!(enemies[0].health, full_health - BULLET_DAMAGE);
+        assert_eq!(bullets.pierces[0], 1);
+        assert!(!bullets.spent[0]);
+        assert_eq!(bullets.hit_enemies[0], vec![7]);
+    }
+
+    #[test]
+    fn test_killing_shot_shows_its_impact_once() {
+        use super::{BulletBehaviour, Bullets, ENEMY_GRID_CELL_SIZE};
+        use crate::enemy::Enemy;
+        use crate::spatial_hash::SpatialHash;
+        use crate::thread_pool::ThreadPool;
+        use glam::Quat;
+
+        let mut enemies = vec![Enemy::new(vec3(0.0, 0.0, 0.0), vec3(0.0, 0.0, 1.0))];
+        enemies[0].health = 1.0;
+
+        let mut bullets = Bullets::default();
+        bullets.push(vec3(-1.0, 0.0, 0.0), vec3(1.0, 0.0, 0.0), Quat::IDENTITY, BulletBehaviour::new(0, 0));
+        for mut chunk in bullets.chunks(0..1, 1) {
+            chunk.integrate(2.0);
+        }
+
+        let mut grid = SpatialHash::new(ENEMY_GRID_CELL_SIZE);
+        grid.rebuild(enemies.iter().map(|e| e.position));
+        let kills = bullets.collide_with_enemies(std::slice::from_ref(&(0..1)), &grid, &mut enemies, &ThreadPool::new(1));
+
+        // one impact where the shot met the enemy, the marked enemy gets none where it stood when it is removed
+        assert_eq!(kills.len(), 1);
+        assert!(kills[0].x < 0.0 && kills[0].x > -1.0, "hit at {}", kills[0]);
+        assert!(enemies[0].killed_by_bullet);
+        assert!(bullets.spent[0]);
+    }
+
+    #[test]
+    fn test_chunked_integration_matches_single_worker() {
+        use super::{BulletBehaviour, Bullets, ARENA_HALF_SIZE, MIN_INTEGRATED_BULLETS_PER_WORKER};
+        use crate::thread_pool::ThreadPool;
+        use glam::Quat;
+
+        let count = 4 * MIN_INTEGRATED_BULLETS_PER_WORKER;
+
+        let run = |workers: usize| {
+            let mut bullets = Bullets::default();
+            for i in 0..count {
+                let position = vec3((i % 71) as f32 - 35.0, (i % 5) as f32 * 0.1, ARENA_HALF_SIZE - (i % 3) as f32);
+                let direction = vec3((i as f32).sin(), -0.3, (i as f32).cos()).normalize();
+                bullets.push(position, direction, Quat::IDENTITY, BulletBehaviour::new(0, (i % 2) as i32));
+            }
+
+            let pool = ThreadPool::new(workers);
+            let chunk_len = pool.chunk_len(count, MIN_INTEGRATED_BULLETS_PER_WORKER);
+            let chunks = bullets.chunks(0..count, chunk_len);
+            assert_eq!(chunks.len(), workers, "the bullets should be shared by every worker");
+            pool.for_each(chunks, |mut chunk| chunk.integrate(0.5));
+
+            (
+                bullets.positions,
+                bullets.previous_positions,
+                bullets.directions,
+                bullets.quats,
+                bullets.ricochets,
+                bullets.spent,
+            )
+        };
+
+        let single = run(1);
+        assert!(single.5.iter().any(|&s| s), "some bullets should run out of ricochets");
+        assert_eq!(run(4), single);
+    }
+
+    #[test]
+    fn test_chunked_collision_matches_single_worker() {
+        use super::{BulletBehaviour, Bullets, COLLISION_RUN_LEN, ENEMY_GRID_CELL_SIZE, MIN_COLLIDING_BULLETS_PER_WORKER};
+        use crate::enemy::Enemy;
+        use crate::spatial_hash::SpatialHash;
+        use crate::thread_pool::ThreadPool;
+        use glam::Quat;
+
+        let count = 4 * MIN_COLLIDING_BULLETS_PER_WORKER;
+
+        let run = |workers: usize| {
+            // a ring of enemies with shots fanned out from the middle, so most runs reach some of them
+            let mut enemies: Vec<Enemy> = (0..500)
+                .map(|i| {
+                    let theta = i as f32 * 0.37;
+                    let distance = 2.0 + (i % 9) as f32 * 0.5;
+                    let mut enemy = Enemy::new(vec3(theta.sin() * distance, 0.0, theta.cos() * distance), vec3(0.0, 0.0, 1.0));
+                    enemy.id = i;
+                    enemy
+                })
+                .collect();
+
+            let mut bullets = Bullets::default();
+            for i in 0..count {
+                let theta = (i / COLLISION_RUN_LEN) as f32 * 0.9 + (i % COLLISION_RUN_LEN) as f32 * 0.01;
+                let direction = vec3(theta.sin(), 0.0, theta.cos());
+                bullets.push(direction * (i % 7) as f32, direction, Quat::IDENTITY, BulletBehaviour::new((i % 3) as i32, 0));
+            }
+
+            let mut grid = SpatialHash::new(ENEMY_GRID_CELL_SIZE);
+            let pool = ThreadPool::new(workers);
+            let runs: Vec<_> = (0..count).step_by(COLLISION_RUN_LEN).map(|start| start..start + COLLISION_RUN_LEN).collect();
+            assert!(pool.chunk_len(runs.len(), MIN_COLLIDING_BULLETS_PER_WORKER / COLLISION_RUN_LEN) * workers <= runs.len());
+
+            let mut kills = vec![];
+            for _ in 0..10 {
+                for mut chunk in bullets.chunks(0..count, count) {
+                    chunk.integrate(0.5);
+                }
+                enemies.retain(|e| e.health > 0.0);
+                grid.rebuild(enemies.iter().map(|e| e.position));
+                kills.extend(bullets.collide_with_enemies(&runs, &grid, &mut enemies, &pool));
+            }
+
+            let enemies: Vec<_> = enemies.iter().map(|e| (e.id, e.health, e.killed_by_bullet)).collect();
+            (enemies, kills, bullets.pierces, bullets.spent, bullets.hit_enemies)
+        };
+
+        let single = run(1);
+        assert!(!single.1.is_empty(), "some enemies should have been killed");
+        assert_eq!(run(4), single);
+    }
 }
@@ -29,6 +29,10 @@ impl BurnMarks {
         }
     }
 
+    pub fn clear(&mut self) {
+        self.marks.clear();
+    }
+
     pub fn add_mark(&mut self, position: Vec3) {
         self.marks.push(BurnMark {
             position,
@@ -1,3 +1,7 @@
+use crate::aabb::Aabb;
+use crate::geom::{closest_point_on_line_segment, closest_points_between_line_segments, ray_capsule_intersection};
+use glam::Vec3;
+
 pub struct Capsule {
     pub(crate) height: f32,
     pub(crate) radius: f32,
@@ -7,4 +11,294 @@ impl Capsule {
     pub const fn new(height: f32, radius: f32) -> Self {
         Self { height, radius }
     }
+
+    /// The capsule in the world, centred on `position` and lying along `dir`.
+    pub fn at(&self, position: Vec3, dir: Vec3) -> Collider {
+        let half_axis = dir.normalize_or_zero() * (self.height / 2.0);
+        Collider {
+            a: position - half_axis,
+            b: position + half_axis,
+            radius: self.radius,
+        }
+    }
+}
+
+/// A placed capsule, every point within `radius` of the segment `a`..`b`. When the ends meet it is a sphere.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Collider {
+    pub a: Vec3,
+    pub b: Vec3,
+    pub radius: f32,
+}
+
+impl Collider {
+    pub const fn sphere(center: Vec3, radius: f32) -> Self {
+        Self { a: center, b: center, radius }
+    }
+
+    /// The point on the surface nearest to `point`, or `point` itself when it is inside.
+    pub fn closest_point(&self, point: Vec3) -> Vec3 {
+        let on_axis = closest_point_on_line_segment(&point, &self.a, &self.b);
+        let offset = point - on_axis;
+        if offset.length_squared() <= self.radius * self.radius {
+            return point;
+        }
+        on_axis + offset.normalize() * self.radius
+    }
+
+    /// Distance from `point` to the surface, 0 when it is inside.
+    pub fn distance_to_point(&self, point: Vec3) -> f32 {
+        (closest_point_on_line_segment(&point, &self.a, &self.b).distance(point) - self.radius).max(0.0)
+    }
+
+    /// Capsule against capsule, which also covers spheres. Touching counts.
+    pub fn intersects(&self, other: &Collider) -> bool {
+        let (p, q) = closest_points_between_line_segments(&self.a, &self.b, &other.a, &other.b);
+        let reach = self.radius + other.radius;
+        p.distance_squared(q) <= reach * reach
+    }
+
+    /// How deep `self` sinks into `other`, as the direction to move `self` to get out and the distance to move it,
+    /// or `None` when they don't overlap. The direction is zero when their axes cross and there is no way out to prefer.
+    pub fn penetration(&self, other: &Collider) -> Option<(Vec3, f32)> {
+        let (p, q) = closest_points_between_line_segments(&self.a, &self.b, &other.a, &other.b);
+        let offset = p - q;
+        let depth = self.radius + other.radius - offset.length();
+        (depth > 0.0).then(|| (offset.normalize_or_zero(), depth))
+    }
+
+    pub fn intersects_sphere(&self, center: Vec3, radius: f32) -> bool {
+        self.intersects(&Collider::sphere(center, radius))
+    }
+
+    /// Distance along the ray to where it first touches the surface, or `None` if it misses.
+    /// `direction` must be normalized. A ray starting inside hits at 0.
+    pub fn ray_intersection(&self, origin: Vec3, direction: Vec3) -> Option<f32> {
+        ray_capsule_intersection(&origin, &direction, &self.a, &self.b, self.radius)
+    }
+
+    pub fn aabb(&self) -> Aabb {
+        let mut aabb = Aabb::from_min_max(self.a, self.b);
+        aabb.expand_by(self.radius);
+        aabb
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aabb::aabbs_intersect;
+    use crate::geom::distance_between_point_and_line_segment;
+    use crate::rng::SimRng;
+    use glam::vec3;
+
+    const CASES: usize = 2000;
+    const TOLERANCE: f32 = 1e-3;
+
+    fn random_point(rng: &mut SimRng) -> Vec3 {
+        vec3(rng.random_clamped(), rng.random_clamped(), rng.random_clamped()) * 3.0
+    }
+
+    fn random_direction(rng: &mut SimRng) -> Vec3 {
+        loop {
+            let v = vec3(rng.random_clamped(), rng.random_clamped(), rng.random_clamped());
+            if v.length_squared() > 0.01 {
+                return v.normalize();
+            }
+        }
+    }
+
+    // Mixes in the degenerate shapes: points, axis aligned and parallel segments, zero radius.
+    fn random_collider(rng: &mut SimRng) -> Collider {
+        let a = random_point(rng);
+        let b = match (rng.rand_float() * 4.0) as u32 {
+            0 => a,
+            1 => a + vec3(0.0, 0.0, rng.rand_float() * 2.0),
+            _ => random_point(rng),
+        };
+        let radius = if rng.rand_float() < 0.1 { 0.0 } else { rng.rand_float() };
+        Collider { a, b, radius }
+    }
+
+    fn random_box(rng: &mut SimRng) -> Aabb {
+        let a = random_point(rng);
+        let b = if rng.rand_float() < 0.1 { a } else { random_point(rng) };
+        Aabb::from_min_max(a, b)
+    }
+
+    // Distance from a point to the axis with the older point-to-segment test, to check the new closed forms against.
+    fn axis_distance(collider: &Collider, point: Vec3) -> f32 {
+        distance_between_point_and_line_segment(&point, &collider.a, &collider.b)
+    }
+
+    #[test]
+    fn test_closest_point_is_on_the_surface() {
+        let mut rng = SimRng::new(1);
+        for _ in 0..CASES {
+            let collider = random_collider(&mut rng);
+            let point = random_point(&mut rng);
+            let closest = collider.closest_point(point);
+
+            let distance = axis_distance(&collider, point);
+            if distance <= collider.radius - TOLERANCE {
+                assert_eq!(closest, point);
+                assert_eq!(collider.distance_to_point(point), 0.0);
+            } else if distance > collider.radius + TOLERANCE {
+                assert!((axis_distance(&collider, closest) - collider.radius).abs() < TOLERANCE);
+                assert!((closest.distance(point) - collider.distance_to_point(point)).abs() < TOLERANCE);
+            }
+        }
+    }
+
+    #[test]
+    fn test_capsules_intersect_matches_sampling() {
+        let mut rng = SimRng::new(2);
+        for _ in 0..CASES {
+            let first = random_collider(&mut rng);
+            let second = random_collider(&mut rng);
+
+            // walk the second axis
+            let gap = (0..=1000)
+                .map(|i| axis_distance(&first, second.a.lerp(second.b, i as f32 / 1000.0)))
+                .fold(f32::MAX, f32::min)
+                - first.radius
+                - second.radius;
+
+            if gap.abs() > 0.01 {
+                assert_eq!(first.intersects(&second), gap < 0.0, "{:?} {:?} gap {}", first, second, gap);
+            }
+            assert_eq!(first.intersects(&second), second.intersects(&first));
+            assert_eq!(
+                first.intersects_sphere(second.a, second.radius),
+                first.intersects(&Collider::sphere(second.a, second.radius))
+            );
+        }
+    }
+
+    #[test]
+    fn test_ray_hits_land_on_the_surface() {
+        let mut rng = SimRng::new(3);
+        let mut hits = 0;
+        for _ in 0..CASES {
+            let collider = random_collider(&mut rng);
+            let origin = random_point(&mut rng) * 2.0;
+            // half the rays aim near the collider so there are plenty of hits
+            let direction = if rng.rand_float() < 0.5 {
+                (collider.a.lerp(collider.b, rng.rand_float()) + random_direction(&mut rng) * collider.radius * 1.5 - origin).normalize()
+            } else {
+                random_direction(&mut rng)
+            };
+
+            let Some(t) = collider.ray_intersection(origin, direction) else {
+                // a miss never passes inside
+                let deepest = (0..=1000)
+                    .map(|i| origin + direction * (i as f32 * 0.02))
+                    .map(|p| collider.distance_to_point(p))
+                    .fold(f32::MAX, f32::min);
+                assert!(deepest > 0.0, "{:?} missed but passes through", collider);
+                continue;
+            };
+
+            hits += 1;
+            assert!(t >= 0.0);
+            let hit = origin + direction * t;
+            if t > 0.0 {
+                assert!(
+                    (axis_distance(&collider, hit) - collider.radius).abs() < TOLERANCE,
+                    "{:?} hit at {}",
+                    collider,
+                    t
+                );
+                // nothing nearer along the ray is inside
+                assert!(collider.distance_to_point(origin + direction * (t - 0.01).max(0.0)) > 0.0 || t < 0.01);
+            } else {
+                assert_eq!(collider.distance_to_point(origin), 0.0);
+            }
+        }
+        assert!(hits > CASES / 10, "too few hits to mean anything: {}", hits);
+    }
+
+    #[test]
+    fn test_penetration_moves_out_of_contact() {
+        let mut rng = SimRng::new(7);
+        for _ in 0..CASES {
+            let first = random_collider(&mut rng);
+            let second = random_collider(&mut rng);
+
+            match first.penetration(&second) {
+                Some((direction, depth)) if direction != Vec3::ZERO => {
+                    let moved = Collider {
+                        a: first.a + direction * (depth + TOLERANCE),
+                        b: first.b + direction * (depth + TOLERANCE),
+                        ..first
+                    };
+                    assert!(depth > 0.0);
+                    assert!(!moved.intersects(&second), "{:?} still in {:?}", moved, second);
+                }
+                Some(_) => {}
+                None => assert!(!first.intersects(&second) || first.radius + second.radius == 0.0),
+            }
+        }
+    }
+
+    #[test]
+    fn test_collider_aabb_contains_collider() {
+        let mut rng = SimRng::new(4);
+        for _ in 0..CASES {
+            let collider = random_collider(&mut rng);
+            let aabb = collider.aabb();
+            let point = random_point(&mut rng);
+            if collider.distance_to_point(point) == 0.0 {
+                assert!(aabb.contains_point(point));
+            }
+        }
+    }
+
+    #[test]
+    fn test_aabbs_intersect_matches_closest_point() {
+        let mut rng = SimRng::new(5);
+        for _ in 0..CASES {
+            let first = random_box(&mut rng);
+            let second = random_box(&mut rng);
+
+            // per axis, the point of the second box nearest a point in the first is in the first whenever they overlap
+            let in_first = first.closest_point(random_point(&mut rng));
+            let meets = first.contains_point(second.closest_point(in_first));
+
+            assert_eq!(
+                aabbs_intersect(&first, &second),
+                meets,
+                "{:?}..{:?} {:?}..{:?}",
+                first.min(),
+                first.max(),
+                second.min(),
+                second.max()
+            );
+            assert_eq!(aabbs_intersect(&first, &second), aabbs_intersect(&second, &first));
+        }
+    }
+
+    #[test]
+    fn test_aabb_ray_hits_land_on_the_box() {
+        let mut rng = SimRng::new(6);
+        for _ in 0..CASES {
+            let aabb = random_box(&mut rng);
+            let origin = random_point(&mut rng) * 2.0;
+            let direction = random_direction(&mut rng);
+
+            match aabb.ray_intersection(origin, direction) {
+                Some(t) => {
+                    let hit = origin + direction * t;
+                    assert!(aabb.closest_point(hit).distance(hit) < TOLERANCE);
+                }
+                None => {
+                    let nearest = (0..=1000)
+                        .map(|i| origin + direction * (i as f32 * 0.02))
+                        .map(|p| aabb.closest_point(p).distance(p))
+                        .fold(f32::MAX, f32::min);
+                    assert!(nearest > 0.0);
+                }
+            }
+        }
+    }
 }
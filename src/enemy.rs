@@ -1,46 +1,242 @@
-use crate::capsule::Capsule;
-use crate::geom::distance_between_point_and_line_segment;
+use crate::animation_set::AnimationSet;
+use crate::boss::{BossEncounter, BossTrigger, BOSS_BEHAVIOUR, BOSS_COLLIDER, BOSS_HEALTH, BOSS_MASS, BOSS_MODEL_SCALE};
+use crate::bullets::{BulletBehaviour, BulletStore, Team};
+use crate::capsule::{Capsule, Collider};
+use crate::enemy_ai::{AiBehaviour, AttackStyle, ChaseStyle, EnemyAi};
+use crate::enemy_animation::EnemyAnimation;
+use crate::rng::SimRng;
+use crate::spatial_hash::SpatialHash;
+use crate::thread_pool::ThreadPool;
 use crate::{State, MONSTER_SPEED, MONSTER_Y, PLAYER_COLLISION_RADIUS};
-use glam::{vec2, vec3, Mat4, Vec3};
+use glam::{vec3, Mat4, Vec3};
+use small_gl_core::gl::GLuint;
 use small_gl_core::model::{Model, ModelBuilder};
 use small_gl_core::shader::Shader;
-use small_gl_core::utils::rand_float;
 use std::f32::consts::PI;
 
 pub const ENEMY_COLLIDER: Capsule = Capsule { height: 0.4, radius: 0.08 };
+/// The biggest collider of any enemy kind, for broad phase bounds.
+pub const LARGEST_ENEMY_COLLIDER: Capsule = BOSS_COLLIDER;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EnemyKind {
+    /// Walks straight at the player and lunges when close.
+    Melee,
+    /// Closes to firing range, shoots at the player and backs off.
+    Ranged,
+    /// Circles the player, dives in and explodes when killed.
+    Exploding,
+    /// Turns up once per game, see `BossEncounter`.
+    Boss,
+}
+
+impl EnemyKind {
+    pub const fn behaviour(self) -> &'static AiBehaviour {
+        match self {
+            EnemyKind::Melee => &MELEE_BEHAVIOUR,
+            EnemyKind::Ranged => &RANGED_BEHAVIOUR,
+            EnemyKind::Exploding => &EXPLODING_BEHAVIOUR,
+            EnemyKind::Boss => &BOSS_BEHAVIOUR,
+        }
+    }
+
+    pub const fn collider(self) -> &'static Capsule {
+        match self {
+            EnemyKind::Boss => &BOSS_COLLIDER,
+            _ => &ENEMY_COLLIDER,
+        }
+    }
+}
 
 pub struct Enemy {
+    /// Stays the same for the enemy's life, unlike its index in `State::enemies`.
+    pub id: u32,
     pub position: Vec3,
     pub dir: Vec3,
     pub is_alive: bool,
+    pub health: f32,
+    pub kind: EnemyKind,
+    pub ai: EnemyAi,
+    /// Current motion, pulled back towards the chase velocity each tick after a hit knocks it off course.
+    pub velocity: Vec3,
+    pub mass: f32,
+    pub animation: EnemyAnimation,
+    /// Set by the killing shot, which already showed its impact where it hit.
+    pub killed_by_bullet: bool,
 }
 
 impl Enemy {
     pub const fn new(position: Vec3, dir: Vec3) -> Self {
-        Self { position, dir, is_alive: true }
+        Self::with_kind(position, dir, EnemyKind::Melee)
+    }
+
+    pub const fn with_kind(position: Vec3, dir: Vec3, kind: EnemyKind) -> Self {
+        Self {
+            id: 0,
+            position,
+            dir,
+            is_alive: true,
+            health: match kind {
+                EnemyKind::Boss => BOSS_HEALTH,
+                _ => ENEMY_HEALTH,
+            },
+            kind,
+            ai: EnemyAi::new(),
+            velocity: Vec3::ZERO,
+            mass: match kind {
+                EnemyKind::Melee => 1.0,
+                EnemyKind::Ranged => 0.8,
+                EnemyKind::Exploding => 1.5,
+                EnemyKind::Boss => BOSS_MASS,
+            },
+            animation: EnemyAnimation::new(0.0),
+            killed_by_bullet: false,
+        }
+    }
+
+    /// Knocks the enemy along the floor, heavier enemies move less.
+    pub fn apply_impulse(&mut self, impulse: Vec3) {
+        self.velocity += vec3(impulse.x, 0.0, impulse.z) / self.mass;
+    }
+
+    /// The enemy's capsule where it stands, lying along its facing.
+    pub fn collider(&self) -> Collider {
+        self.kind.collider().at(self.position, self.dir)
+    }
+
+    /// Whether bullets and explosions still connect, dying enemies are on their way out.
+    pub fn can_be_hit(&self) -> bool {
+        self.is_alive && !self.ai.is_dying()
+    }
+
+    /// Damage and knockback from a bullet or explosion. Enemies that run out of health start dying.
+    pub fn take_hit(&mut self, damage: f32, impulse: Vec3) {
+        self.apply_impulse(impulse);
+        self.health -= damage;
+        if self.health <= 0.0 {
+            self.ai.on_killed();
+        } else if damage > 0.0 {
+            self.ai.on_hit(self.kind.behaviour());
+        }
     }
 }
 
 const ENEMY_SPAWN_INTERVAL: f32 = 1.0; // seconds
 const SPAWNS_PER_INTERVAL: i32 = 1;
 const SPAWN_RADIUS: f32 = 10.0; // from player
+const ENEMY_HEALTH: f32 = 3.0;
+const ENEMY_ANIMATIONS_PATH: &str = "angrygl_assets/Enemy/enemy_animations.cfg";
+const STEERING_RECOVERY: f32 = 3.0; // per second, how quickly the chase velocity takes over again after a knockback
+const MIN_ENEMIES_PER_WORKER: usize = 576; // below this handing a chunk to a worker costs more than the work in it, see benches/thread_pool.rs
+const CONTACT_ITERATIONS: usize = 2; // passes of pushing apart, more settles big crowds faster
+const CONTACT_GRID_CELL_SIZE: f32 = 1.0;
+// furthest apart two enemy centres can be and still touch
+const CONTACT_REACH: f32 = LARGEST_ENEMY_COLLIDER.height + 2.0 * LARGEST_ENEMY_COLLIDER.radius;
+
+const MELEE_BEHAVIOUR: AiBehaviour = AiBehaviour {
+    spawn_time: 0.5,
+    sight_range: 15.0,
+    wander_speed: MONSTER_SPEED * 0.5,
+    chase_speed: MONSTER_SPEED,
+    chase: ChaseStyle::Direct,
+    attack_range: 1.2,
+    wind_up_time: 0.35,
+    attack: AttackStyle::Lunge { speed: 3.0 },
+    attack_time: 0.3,
+    attack_cool_down: 1.5,
+    retreat_time: 0.0,
+    retreat_speed: 0.0,
+    stun_time: 0.25,
+    dying_time: 0.3,
+};
+
+// Ranged enemies
+const RANGED_SPAWN_CHANCE: f32 = 0.25;
+const RANGED_BULLET: BulletBehaviour = BulletBehaviour::new(0, 0);
+const RANGED_BEHAVIOUR: AiBehaviour = AiBehaviour {
+    spawn_time: 0.5,
+    sight_range: 15.0,
+    wander_speed: MONSTER_SPEED * 0.5,
+    chase_speed: MONSTER_SPEED,
+    chase: ChaseStyle::KeepDistance { range: 6.0 },
+    attack_range: 6.5,
+    wind_up_time: 0.4,
+    attack: AttackStyle::Shoot,
+    attack_time: 0.2,
+    attack_cool_down: 2.0,
+    retreat_time: 0.8,
+    retreat_speed: MONSTER_SPEED,
+    stun_time: 0.4,
+    dying_time: 0.3,
+};
+
+// Exploding enemies
+const EXPLODING_SPAWN_CHANCE: f32 = 0.1;
+const EXPLODING_BEHAVIOUR: AiBehaviour = AiBehaviour {
+    spawn_time: 0.5,
+    sight_range: 15.0,
+    wander_speed: MONSTER_SPEED * 0.5,
+    chase_speed: MONSTER_SPEED * 1.2,
+    chase: ChaseStyle::Circle { radius: 2.5 },
+    attack_range: 3.0,
+    wind_up_time: 0.6,
+    attack: AttackStyle::Lunge { speed: 5.0 },
+    attack_time: 0.5,
+    attack_cool_down: 2.0,
+    retreat_time: 0.0,
+    retreat_speed: 0.0,
+    // too heavy to be put off by a bullet
+    stun_time: 0.0,
+    dying_time: 0.2,
+};
+pub const ENEMY_EXPLOSION_RADIUS: f32 = 2.0;
+pub const ENEMY_EXPLOSION_DAMAGE: f32 = 4.0;
+pub const ENEMY_EXPLOSION_KNOCKBACK: f32 = 3.0;
 
 pub struct EnemySystem {
     count_down: f32,
     monster_y: f32,
     enemy_model: Model,
+    animations: AnimationSet,
+    unit_square_vao: i32,
+    pub projectiles: BulletStore,
+    pub boss: BossEncounter,
+    contact_grid: SpatialHash,
+    next_enemy_id: u32,
+    poses: Vec<EnemyPose>,
+}
+
+// An enemy's pose for this frame, kept so drawing it in each pass doesn't play its animations again.
+#[derive(Default)]
+struct EnemyPose {
+    bones: Vec<Mat4>,
+    nodes: Vec<Mat4>,
 }
 
 impl EnemySystem {
-    pub fn new() -> Self {
+    pub fn new(unit_square_vao: i32) -> Self {
         let enemy_model = ModelBuilder::new("enemy", "assets/Models/Eeldog/EelDog.FBX").build().unwrap();
         Self {
             count_down: ENEMY_SPAWN_INTERVAL,
             monster_y: MONSTER_Y,
             enemy_model,
+            animations: AnimationSet::load(ENEMY_ANIMATIONS_PATH),
+            unit_square_vao,
+            projectiles: BulletStore::new(unit_square_vao, Team::Enemy),
+            boss: BossEncounter::new(BossTrigger::default()),
+            contact_grid: SpatialHash::new(CONTACT_GRID_CELL_SIZE),
+            next_enemy_id: 0,
+            poses: vec![],
         }
     }
 
+    /// Back to the start of a game, for a restart. The enemies themselves live in `State::enemies`.
+    pub fn reset(&mut self) {
+        self.count_down = ENEMY_SPAWN_INTERVAL;
+        self.projectiles.clear();
+        self.boss = BossEncounter::new(self.boss.trigger);
+    }
+
     pub fn update(&mut self, state: &mut State) {
         self.count_down -= state.delta_time;
         if self.count_down <= 0.0 {
@@ -49,54 +245,221 @@ impl EnemySystem {
             }
             self.count_down += ENEMY_SPAWN_INTERVAL;
         }
+
+        if let Some(target) = state.players.iter().map(|p| p.borrow()).find(|p| p.is_alive).map(|p| p.position) {
+            if let Some(position) = self.boss.update_trigger(state, vec3(target.x, self.monster_y, target.z)) {
+                self.add_enemy(state, position, EnemyKind::Boss);
+            }
+        }
+
+        let summons = self.boss.update(state, &mut self.projectiles);
+        for position in summons {
+            self.summon(state, position, EnemyKind::Melee);
+        }
+    }
+
+    /// Adds an enemy at a set position rather than around the players, used by the boss to bring in minions.
+    pub fn summon(&mut self, state: &mut State, position: Vec3, kind: EnemyKind) {
+        self.add_enemy(state, vec3(position.x, self.monster_y, position.z), kind);
     }
 
     pub fn spawn_enemy(&mut self, state: &mut State) {
-        let theta = (rand_float() * 360.0).to_radians();
-        // let x = state.player.borrow().position.x + theta.sin() * SPAWN_RADIUS;
-        // let z = state.player.borrow().position.z + theta.cos() * SPAWN_RADIUS;
-        let x = theta.sin().mul_add(SPAWN_RADIUS, state.player.borrow().position.x);
-        let z = theta.cos().mul_add(SPAWN_RADIUS, state.player.borrow().position.z);
-        state.enemies.push(Enemy::new(vec3(x, self.monster_y, z), vec3(0.0, 0.0, 1.0)));
-    }
-
-    pub fn chase_player(&self, state: &mut State) {
-        let mut player = state.player.borrow_mut();
-        let player_collision_position = vec3(player.position.x, MONSTER_Y, player.position.z);
-
-        for enemy in state.enemies.iter_mut() {
-            let mut dir = player.position - enemy.position;
-            dir.y = 0.0;
-            enemy.dir = dir.normalize_or_zero();
-            enemy.position += enemy.dir * state.delta_time * MONSTER_SPEED;
-
-            if player.is_alive {
-                let p1 = enemy.position - enemy.dir * (ENEMY_COLLIDER.height / 2.0);
-                let p2 = enemy.position + enemy.dir * (ENEMY_COLLIDER.height / 2.0);
-                let dist = distance_between_point_and_line_segment(&player_collision_position, &p1, &p2);
-
-                if dist <= (PLAYER_COLLISION_RADIUS + ENEMY_COLLIDER.radius) {
-                    // println!("GOTTEM!");
-                    player.is_alive = false;
-                    player.set_player_death_time(state.frame_time);
-                    player.direction = vec2(0.0, 0.0);
+        // spawn around one of the living players
+        let living_players: Vec<Vec3> = state.players.iter().filter(|p| p.borrow().is_alive).map(|p| p.borrow().position).collect();
+        if living_players.is_empty() {
+            return;
+        }
+        let target = living_players[((state.rng.rand_float() * living_players.len() as f32) as usize).min(living_players.len() - 1)];
+
+        let theta = (state.rng.rand_float() * 360.0).to_radians();
+        // let x = target.x + theta.sin() * SPAWN_RADIUS;
+        // let z = target.z + theta.cos() * SPAWN_RADIUS;
+        let x = theta.sin().mul_add(SPAWN_RADIUS, target.x);
+        let z = theta.cos().mul_add(SPAWN_RADIUS, target.z);
+        let roll = state.rng.rand_float();
+        let kind = if roll < RANGED_SPAWN_CHANCE {
+            EnemyKind::Ranged
+        } else if roll < RANGED_SPAWN_CHANCE + EXPLODING_SPAWN_CHANCE {
+            EnemyKind::Exploding
+        } else {
+            EnemyKind::Melee
+        };
+        self.add_enemy(state, vec3(x, self.monster_y, z), kind);
+    }
+
+    // Each enemy starts its clips at a different point in the cycle.
+    fn add_enemy(&mut self, state: &mut State, position: Vec3, kind: EnemyKind) {
+        let mut enemy = Enemy::with_kind(position, vec3(0.0, 0.0, 1.0), kind);
+        enemy.id = self.next_enemy_id;
+        self.next_enemy_id = self.next_enemy_id.wrapping_add(1);
+        enemy.animation.phase = state.rng.rand_float();
+        state.enemies.push(enemy);
+    }
+
+    /// Runs each enemy's state machine against the nearest living player and moves it.
+    // Finding targets and contacts only reads, so those run across the workers against the players as they were at
+    // the start of the tick. Each enemy draws from its own random stream, so the AI runs across the workers as well
+    // and only the shots are fired afterwards, in enemy order.
+    pub fn update_enemies(&mut self, state: &mut State) {
+        let living_players: Vec<Vec3> = state.players.iter().map(|p| p.borrow()).filter(|p| p.is_alive).map(|p| p.position).collect();
+
+        let enemies = &state.enemies;
+        let targets: Vec<Option<Vec3>> = state.thread_pool.map_chunks(enemies.len(), MIN_ENEMIES_PER_WORKER, |range| {
+            enemies[range]
+                .iter()
+                .map(|enemy| {
+                    // go after the nearest living player
+                    living_players
+                        .iter()
+                        .copied()
+                        .min_by(|a, b| a.distance_squared(enemy.position).total_cmp(&b.distance_squared(enemy.position)))
+                })
+                .collect()
+        });
+
+        let tick_seed = state.rng.next_u64();
+        let shots = steer_enemies(&mut state.enemies, &targets, tick_seed, state.delta_time, state.frame_time, &state.thread_pool);
+        for (nose, direction) in shots {
+            self.projectiles.create_bullet(nose, direction, RANGED_BULLET);
+        }
+
+        let mut players: Vec<_> = state.players.iter().map(|p| p.borrow_mut()).collect();
+
+        let vulnerable_players: Vec<(usize, Vec3)> = players
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| p.is_alive && !p.is_invulnerable(state.frame_time))
+            .map(|(index, p)| (index, vec3(p.position.x, MONSTER_Y, p.position.z)))
+            .collect();
+
+        if vulnerable_players.is_empty() {
+            return;
+        }
+
+        let enemies = &state.enemies;
+        let caught_players = state.thread_pool.map_chunks(enemies.len(), MIN_ENEMIES_PER_WORKER, |range| {
+            let mut caught = vec![];
+
+            for enemy in enemies[range].iter().filter(|e| e.is_alive && e.ai.is_dangerous()) {
+                let collider = enemy.collider();
+
+                for (index, player_collision_position) in vulnerable_players.iter() {
+                    if collider.intersects_sphere(*player_collision_position, PLAYER_COLLISION_RADIUS) {
+                        caught.push(*index);
+                    }
                 }
             }
+
+            caught
+        });
+
+        for index in caught_players {
+            if players[index].is_alive {
+                // println!("GOTTEM!");
+                players[index].die(state.frame_time);
+            }
+        }
+    }
+
+    /// Pushes overlapping enemies apart and out of the living players, so crowds pile up around the players
+    /// instead of sharing the same spot. Heavier enemies give way less.
+    // Each pass works out every enemy's push from the positions at the start of the pass, across the workers,
+    // then moves them all, so the outcome doesn't depend on the enemy order or the number of workers.
+    pub fn resolve_contacts(&mut self, state: &mut State) {
+        let players: Vec<Collider> = state
+            .players
+            .iter()
+            .map(|p| p.borrow())
+            .filter(|p| p.is_alive)
+            .map(|p| Collider::sphere(vec3(p.position.x, self.monster_y, p.position.z), PLAYER_COLLISION_RADIUS))
+            .collect();
+
+        for _ in 0..CONTACT_ITERATIONS {
+            self.contact_grid.rebuild(state.enemies.iter().map(|e| e.position));
+
+            let grid = &self.contact_grid;
+            let enemies = &state.enemies;
+            let pushes: Vec<Vec3> = state.thread_pool.map_chunks(enemies.len(), MIN_ENEMIES_PER_WORKER, |range| {
+                let mut nearby_enemies = vec![];
+
+                range
+                    .map(|index| {
+                        // dying enemies shrink away, so they stop blocking the others
+                        let enemy = &enemies[index];
+                        if !enemy.can_be_hit() {
+                            return Vec3::ZERO;
+                        }
+
+                        let collider = enemy.collider();
+                        let mut push = Vec3::ZERO;
+
+                        grid.query(enemy.position, CONTACT_REACH, &mut nearby_enemies);
+                        for &other_index in nearby_enemies.iter() {
+                            let other = &enemies[other_index];
+                            if other_index == index || !other.can_be_hit() {
+                                continue;
+                            }
+                            // the other enemy takes the rest of the push
+                            let share = other.mass / (enemy.mass + other.mass);
+                            // stacked exactly on top of each other, so split them by index
+                            let fallback = if index < other_index { -enemy.dir } else { enemy.dir };
+                            push += separation(&collider, &other.collider(), fallback) * share;
+                        }
+
+                        // players stand their ground
+                        for player in players.iter() {
+                            push += separation(&collider, player, -enemy.dir);
+                        }
+
+                        push
+                    })
+                    .collect()
+            });
+
+            for (enemy, push) in state.enemies.iter_mut().zip(pushes) {
+                enemy.position += push;
+            }
         }
     }
 
+    /// Plays each enemy's animations on the shared model and keeps the pose for drawing. Called once a frame, before the
+    /// passes that draw the enemies.
+    pub fn pose_enemies(&mut self, state: &State) {
+        if self.animations.is_empty() {
+            return;
+        }
+
+        self.poses.resize_with(state.enemies.len(), EnemyPose::default);
+        for (e, pose) in state.enemies.iter().zip(self.poses.iter_mut()) {
+            let animations = e.animation.weighted_animations(&self.animations, state.frame_time);
+            self.enemy_model.play_weight_animations(animations.as_slice(), state.frame_time);
+
+            let animator = self.enemy_model.animator.borrow();
+            pose.bones.clone_from(&animator.final_bone_matrices.borrow());
+            pose.nodes.clone_from(&animator.final_node_matrices.borrow());
+        }
+    }
+
+    /// Draws every enemy with the one shared model, in the pose `pose_enemies` gave it. Without clips for the model the
+    /// shader wiggles it instead, as hard as the enemy's animation state says.
     pub fn draw_enemies(&self, shader: &Shader, state: &mut State) {
+        let is_skinned = !self.animations.is_empty();
+
         shader.use_shader();
+        shader.set_bool("useWiggle", !is_skinned);
         shader.set_vec3("nosePos", &vec3(1.0, MONSTER_Y, -2.0));
-        shader.set_float("time", state.frame_time);
 
         // TODO optimise (multithreaded, instancing, SOA, etc..)
-        for e in state.enemies.iter_mut() {
+        for (index, e) in state.enemies.iter_mut().enumerate() {
             let monster_theta = (e.dir.x / e.dir.z).atan() + (if e.dir.z < 0.0 { 0.0 } else { PI });
 
+            // dying enemies shrink away before they are removed
+            let kind_scale = if e.kind == EnemyKind::Boss { BOSS_MODEL_SCALE } else { 1.0 };
+            let scale = 0.01 * kind_scale * (1.0 - e.ai.dying_progress(e.kind.behaviour()));
+
             let mut model_transform = Mat4::from_translation(e.position);
 
-            model_transform *= Mat4::from_scale(Vec3::splat(0.01));
+            model_transform *= Mat4::from_scale(Vec3::splat(scale));
             model_transform *= Mat4::from_axis_angle(vec3(0.0, 1.0, 0.0), monster_theta);
             model_transform *= Mat4::from_axis_angle(vec3(0.0, 0.0, 1.0), PI);
             model_transform *= Mat4::from_axis_angle(vec3(1.0, 0.0, 0.0), 90.0f32.to_radians());
@@ -108,7 +471,125 @@ impl EnemySystem {
             shader.set_mat4("aimRot", &rot_only);
             shader.set_mat4("model", &model_transform);
 
+            let (wiggle_time, wiggle_scale) = e.animation.wiggle(state.frame_time);
+            shader.set_float("time", wiggle_time);
+            shader.set_float("wiggleScale", wiggle_scale);
+
+            if let Some(pose) = self.poses.get(index).filter(|_| is_skinned) {
+                let animator = self.enemy_model.animator.borrow();
+                animator.final_bone_matrices.borrow_mut().clone_from(&pose.bones);
+                animator.final_node_matrices.borrow_mut().clone_from(&pose.nodes);
+            }
+
             self.enemy_model.render(shader);
         }
     }
+
+    pub fn draw_boss_health_bar(&self, shader: &Shader, projection_view: &Mat4, state: &State) {
+        self.boss.draw_health_bar(shader, projection_view, self.unit_square_vao as GLuint, state);
+    }
+}
+
+// Runs the AI and movement for each enemy against its target, across the workers. Each enemy's random numbers come
+// from a stream seeded by the tick and its id. Returns the shots the enemies fired, as nose and direction, in enemy order.
+fn steer_enemies(
+    enemies: &mut [Enemy],
+    targets: &[Option<Vec3>],
+    tick_seed: u64,
+    delta_time: f32,
+    frame_time: f32,
+    thread_pool: &ThreadPool,
+) -> Vec<(Vec3, Vec3)> {
+    let chunk_len = thread_pool.chunk_len(enemies.len(), MIN_ENEMIES_PER_WORKER);
+    let mut shots: Vec<Vec<(Vec3, Vec3)>> = enemies.chunks(chunk_len).map(|_| vec![]).collect();
+
+    let tasks: Vec<_> = enemies.chunks_mut(chunk_len).zip(targets.chunks(chunk_len)).zip(shots.iter_mut()).collect();
+    thread_pool.for_each(tasks, |((enemies, targets), shots)| {
+        for (enemy, &target) in enemies.iter_mut().zip(targets) {
+            let behaviour = enemy.kind.behaviour();
+            let mut rng = SimRng::stream(tick_seed, enemy.id as u64);
+
+            let action = enemy.ai.update(behaviour, enemy.position, target, delta_time, &mut rng);
+            enemy.animation.update(enemy.ai.state, frame_time);
+
+            if let Some(facing) = action.facing {
+                if facing != Vec3::ZERO {
+                    enemy.dir = facing;
+                }
+            }
+
+            if action.is_lunging {
+                enemy.velocity = action.steering_velocity;
+            } else {
+                let blend = (STEERING_RECOVERY * delta_time).min(1.0);
+                enemy.velocity = enemy.velocity.lerp(action.steering_velocity, blend);
+            }
+            enemy.position += enemy.velocity * delta_time;
+
+            if enemy.ai.is_dead(behaviour) {
+                enemy.is_alive = false;
+                continue;
+            }
+
+            if let (true, Some(target)) = (action.fire, target) {
+                let target_collision_position = vec3(target.x, MONSTER_Y, target.z);
+                let nose = enemy.position + enemy.dir * (enemy.kind.collider().height / 2.0);
+                shots.push((nose, target_collision_position - nose));
+            }
+        }
+    });
+
+    shots.into_iter().flatten().collect()
+}
+
+// How far to move `a` across the floor to stop it overlapping `b`, along `fallback` when there is no better way out.
+fn separation(a: &Collider, b: &Collider, fallback: Vec3) -> Vec3 {
+    let Some((direction, depth)) = a.penetration(b) else {
+        return Vec3::ZERO;
+    };
+    let across_floor = vec3(direction.x, 0.0, direction.z).try_normalize().unwrap_or(fallback);
+    across_floor * depth
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_steering_matches_single_worker() {
+        let kinds = [EnemyKind::Melee, EnemyKind::Ranged, EnemyKind::Exploding];
+        let count = 4 * MIN_ENEMIES_PER_WORKER;
+        let targets: Vec<Option<Vec3>> = (0..count).map(|i| (i % 7 != 0).then_some(vec3(0.0, MONSTER_Y, 0.0))).collect();
+
+        let run = |workers: usize| {
+            let mut enemies: Vec<Enemy> = (0..count)
+                .map(|i| {
+                    let theta = i as f32 * 0.1;
+                    let distance = 1.0 + (i % 20) as f32;
+                    let position = vec3(theta.sin() * distance, MONSTER_Y, theta.cos() * distance);
+                    let mut enemy = Enemy::with_kind(position, vec3(0.0, 0.0, 1.0), kinds[i % 3]);
+                    enemy.id = i as u32;
+                    enemy
+                })
+                .collect();
+
+            let pool = ThreadPool::new(workers);
+            assert_eq!(
+                count.div_ceil(pool.chunk_len(count, MIN_ENEMIES_PER_WORKER)),
+                workers,
+                "the enemies should be shared by every worker"
+            );
+            let mut shots = vec![];
+            for tick in 0..120 {
+                shots.extend(steer_enemies(&mut enemies, &targets, tick, 1.0 / 60.0, tick as f32 / 60.0, &pool));
+            }
+
+            let enemies: Vec<_> = enemies.iter().map(|e| (e.position, e.dir, e.velocity, e.ai.state, e.is_alive)).collect();
+            (enemies, shots)
+        };
+
+        let single = run(1);
+        assert!(!single.1.is_empty(), "some ranged enemies should have fired");
+        assert_eq!(run(4), single);
+    }
 }
@@ -0,0 +1,420 @@
+use crate::rng::SimRng;
+use glam::{vec3, Vec3};
+
+//
+// Per enemy state machine. Each enemy type has an `AiBehaviour` that sets how long the states last
+// and which way the transitions go, which is enough to get lunging, circling and retreating enemies
+// out of the same set of states.
+//
+
+const WANDER_TURN_TIME: f32 = 2.0;
+// seconds
+const KEEP_DISTANCE_SLACK: f32 = 0.8; // fraction of the range inside which keep distance enemies back off
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AiState {
+    /// Just appeared, doesn't move or hurt yet.
+    Spawning,
+    /// No target in sight, drifts about.
+    Wander,
+    Chase,
+    /// Telegraphs the attack, turning to track the target.
+    WindUp,
+    Attack,
+    /// Backs off from the target after an attack.
+    Retreat,
+    /// Knocked out of whatever it was doing by a hit.
+    Stunned,
+    /// Out of health, removed once the state ends.
+    Dying,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum ChaseStyle {
+    /// Straight at the target.
+    Direct,
+    /// Orbits the target at the radius, closing in from further out.
+    Circle { radius: f32 },
+    /// Closes to the range and holds there, backing off if the target gets too close.
+    KeepDistance { range: f32 },
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum AttackStyle {
+    /// Dashes in the direction the wind-up ended facing.
+    Lunge { speed: f32 },
+    /// Fires one projectile at the target as the attack starts.
+    Shoot,
+}
+
+/// The per enemy type settings for the state machine.
+#[derive(Debug, Clone, Copy)]
+pub struct AiBehaviour {
+    pub spawn_time: f32,
+    /// Targets further away are ignored and the enemy wanders.
+    pub sight_range: f32,
+    pub wander_speed: f32,
+    pub chase_speed: f32,
+    pub chase: ChaseStyle,
+    /// Starts winding up once the target is this close.
+    pub attack_range: f32,
+    pub wind_up_time: f32,
+    pub attack: AttackStyle,
+    pub attack_time: f32,
+    pub attack_cool_down: f32,
+    /// Time spent backing off after an attack, zero goes straight back to chasing.
+    pub retreat_time: f32,
+    pub retreat_speed: f32,
+    /// Time a hit stuns for, zero means hits don't interrupt.
+    pub stun_time: f32,
+    pub dying_time: f32,
+}
+
+/// What the state machine wants the enemy to do this tick.
+#[derive(Debug, Default)]
+pub struct AiAction {
+    pub steering_velocity: Vec3,
+    /// Move at the steering velocity straight away instead of easing into it, for lunges.
+    pub is_lunging: bool,
+    /// Direction to face, `None` keeps the current facing.
+    pub facing: Option<Vec3>,
+    /// Fire a projectile at the target this tick.
+    pub fire: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct EnemyAi {
+    pub state: AiState,
+    pub time_in_state: f32,
+    attack_cool_down: f32,
+    attack_dir: Vec3,
+    wander_dir: Vec3,
+    /// Which way round circling enemies orbit.
+    circle_sign: f32,
+}
+
+impl Default for EnemyAi {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EnemyAi {
+    pub const fn new() -> Self {
+        Self {
+            state: AiState::Spawning,
+            time_in_state: 0.0,
+            attack_cool_down: 0.0,
+            attack_dir: Vec3::ZERO,
+            wander_dir: Vec3::ZERO,
+            circle_sign: 1.0,
+        }
+    }
+
+    pub fn enter(&mut self, state: AiState) {
+        self.state = state;
+        self.time_in_state = 0.0;
+    }
+
+    pub fn is_dying(&self) -> bool {
+        self.state == AiState::Dying
+    }
+
+    /// Whether touching the enemy hurts. Spawning, stunned and dying enemies are harmless.
+    pub fn is_dangerous(&self) -> bool {
+        !matches!(self.state, AiState::Spawning | AiState::Stunned | AiState::Dying)
+    }
+
+    /// True once the dying state has run its course.
+    pub fn is_dead(&self, behaviour: &AiBehaviour) -> bool {
+        self.is_dying() && self.time_in_state >= behaviour.dying_time
+    }
+
+    /// Range 0..1 through the dying state.
+    pub fn dying_progress(&self, behaviour: &AiBehaviour) -> f32 {
+        if !self.is_dying() {
+            return 0.0;
+        }
+        if behaviour.dying_time <= 0.0 {
+            return 1.0;
+        }
+        (self.time_in_state / behaviour.dying_time).min(1.0)
+    }
+
+    /// A hit that didn't kill, interrupts the enemy if its behaviour allows.
+    pub fn on_hit(&mut self, behaviour: &AiBehaviour) {
+        if behaviour.stun_time > 0.0 && !matches!(self.state, AiState::Spawning | AiState::Dying) {
+            self.enter(AiState::Stunned);
+        }
+    }
+
+    pub fn on_killed(&mut self) {
+        if !self.is_dying() {
+            self.enter(AiState::Dying);
+        }
+    }
+
+    /// Runs the state machine for one tick. The target is the position of whoever the enemy is after, if anyone.
+    pub fn update(&mut self, behaviour: &AiBehaviour, position: Vec3, target: Option<Vec3>, delta_time: f32, rng: &mut SimRng) -> AiAction {
+        self.time_in_state += delta_time;
+        self.attack_cool_down -= delta_time;
+
+        let mut to_target = target.map_or(Vec3::ZERO, |target| target - position);
+        to_target.y = 0.0;
+        let distance = if target.is_some() { to_target.length() } else { f32::MAX };
+        let target_dir = to_target.normalize_or_zero();
+        let in_sight = distance <= behaviour.sight_range;
+
+        let mut action = AiAction::default();
+
+        match self.state {
+            AiState::Spawning => {
+                if self.time_in_state >= behaviour.spawn_time {
+                    self.circle_sign = if rng.rand_float() < 0.5 { -1.0 } else { 1.0 };
+                    self.start_wander(rng);
+                }
+            }
+            AiState::Wander => {
+                if in_sight {
+                    self.enter(AiState::Chase);
+                } else {
+                    if self.time_in_state >= WANDER_TURN_TIME {
+                        self.start_wander(rng);
+                    }
+                    action.steering_velocity = self.wander_dir * behaviour.wander_speed;
+                    action.facing = Some(self.wander_dir);
+                }
+            }
+            AiState::Chase => {
+                if !in_sight {
+                    self.start_wander(rng);
+                } else if distance <= behaviour.attack_range && self.attack_cool_down <= 0.0 {
+                    self.enter(AiState::WindUp);
+                    action.facing = Some(target_dir);
+                } else {
+                    action.steering_velocity = self.chase_velocity(behaviour, target_dir, distance);
+                    action.facing = Some(target_dir);
+                }
+            }
+            AiState::WindUp => {
+                self.attack_dir = target_dir;
+                action.facing = Some(target_dir);
+
+                if self.time_in_state >= behaviour.wind_up_time {
+                    self.enter(AiState::Attack);
+                    action.fire = matches!(behaviour.attack, AttackStyle::Shoot);
+                }
+            }
+            AiState::Attack => {
+                if let AttackStyle::Lunge { speed } = behaviour.attack {
+                    action.steering_velocity = self.attack_dir * speed;
+                    action.is_lunging = true;
+                }
+                action.facing = Some(self.attack_dir);
+
+                if self.time_in_state >= behaviour.attack_time {
+                    self.attack_cool_down = behaviour.attack_cool_down;
+                    self.enter(if behaviour.retreat_time > 0.0 { AiState::Retreat } else { AiState::Chase });
+                }
+            }
+            AiState::Retreat => {
+                action.steering_velocity = -target_dir * behaviour.retreat_speed;
+                action.facing = Some(target_dir);
+
+                if self.time_in_state >= behaviour.retreat_time {
+                    self.enter(AiState::Chase);
+                }
+            }
+            AiState::Stunned => {
+                if self.time_in_state >= behaviour.stun_time {
+                    self.enter(AiState::Chase);
+                }
+            }
+            AiState::Dying => {}
+        }
+
+        action
+    }
+
+    fn start_wander(&mut self, rng: &mut SimRng) {
+        let theta = rng.rand_float() * std::f32::consts::TAU;
+        self.wander_dir = vec3(theta.sin(), 0.0, theta.cos());
+        self.enter(AiState::Wander);
+    }
+
+    fn chase_velocity(&self, behaviour: &AiBehaviour, target_dir: Vec3, distance: f32) -> Vec3 {
+        match behaviour.chase {
+            ChaseStyle::Direct => target_dir * behaviour.chase_speed,
+            ChaseStyle::Circle { radius } => {
+                let tangent = vec3(-target_dir.z, 0.0, target_dir.x) * self.circle_sign;
+                // pulled in or pushed out toward the orbit while going round it
+                let radial = ((distance - radius) / radius).clamp(-1.0, 1.0);
+                (tangent + target_dir * radial).normalize_or_zero() * behaviour.chase_speed
+            }
+            ChaseStyle::KeepDistance { range } => {
+                if distance > range {
+                    target_dir * behaviour.chase_speed
+                } else if distance < range * KEEP_DISTANCE_SLACK {
+                    -target_dir * behaviour.chase_speed
+                } else {
+                    Vec3::ZERO
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DELTA_TIME: f32 = 0.25;
+
+    const LUNGER: AiBehaviour = AiBehaviour {
+        spawn_time: 0.5,
+        sight_range: 20.0,
+        wander_speed: 1.0,
+        chase_speed: 2.0,
+        chase: ChaseStyle::Direct,
+        attack_range: 3.0,
+        wind_up_time: 0.5,
+        attack: AttackStyle::Lunge { speed: 8.0 },
+        attack_time: 0.25,
+        attack_cool_down: 1.0,
+        retreat_time: 0.5,
+        retreat_speed: 1.5,
+        stun_time: 0.75,
+        dying_time: 0.5,
+    };
+
+    // Runs the state machine for a number of ticks, returning the last action.
+    fn run(ai: &mut EnemyAi, behaviour: &AiBehaviour, position: Vec3, target: Option<Vec3>, ticks: usize, rng: &mut SimRng) -> AiAction {
+        let mut action = AiAction::default();
+        for _ in 0..ticks {
+            action = ai.update(behaviour, position, target, DELTA_TIME, rng);
+        }
+        action
+    }
+
+    #[test]
+    fn test_spawn_chase_wind_up_attack() {
+        let mut rng = SimRng::new(7);
+        let mut ai = EnemyAi::new();
+        let target = Some(vec3(0.0, 0.0, 10.0));
+
+        // harmless and still until the spawn is over
+        let action = run(&mut ai, &LUNGER, Vec3::ZERO, target, 1, &mut rng);
+        assert_eq!(ai.state, AiState::Spawning);
+        assert!(!ai.is_dangerous());
+        assert_eq!(action.steering_velocity, Vec3::ZERO);
+
+        run(&mut ai, &LUNGER, Vec3::ZERO, target, 1, &mut rng);
+        assert_eq!(ai.state, AiState::Wander);
+
+        // the target is in sight, so straight on to the chase
+        run(&mut ai, &LUNGER, Vec3::ZERO, target, 1, &mut rng);
+        assert_eq!(ai.state, AiState::Chase);
+        let action = run(&mut ai, &LUNGER, Vec3::ZERO, target, 1, &mut rng);
+        assert_eq!(action.steering_velocity, vec3(0.0, 0.0, LUNGER.chase_speed));
+        assert!(ai.is_dangerous());
+
+        // close enough to wind up, tracking the target as it moves
+        let close = vec3(2.0, 0.0, 0.0);
+        let action = run(&mut ai, &LUNGER, Vec3::ZERO, Some(close), 1, &mut rng);
+        assert_eq!(ai.state, AiState::WindUp);
+        assert_eq!(action.facing, Some(Vec3::X));
+        let action = run(&mut ai, &LUNGER, Vec3::ZERO, Some(vec3(0.0, 0.0, -2.0)), 1, &mut rng);
+        assert_eq!((ai.state, action.facing), (AiState::WindUp, Some(Vec3::NEG_Z)));
+
+        // lunges the way the wind-up ended facing
+        let action = run(&mut ai, &LUNGER, Vec3::ZERO, Some(close), 1, &mut rng);
+        assert_eq!(ai.state, AiState::Attack);
+        assert!(!action.fire);
+        let action = run(&mut ai, &LUNGER, Vec3::ZERO, Some(vec3(0.0, 0.0, 2.0)), 1, &mut rng);
+        assert!(action.is_lunging);
+        assert_eq!(action.steering_velocity, Vec3::X * 8.0);
+
+        // backs off, then chases again, but can't attack until the cool down is over
+        assert_eq!(ai.state, AiState::Retreat);
+        let action = run(&mut ai, &LUNGER, Vec3::ZERO, Some(close), 1, &mut rng);
+        assert_eq!(action.steering_velocity, Vec3::NEG_X * LUNGER.retreat_speed);
+        run(&mut ai, &LUNGER, Vec3::ZERO, Some(close), 1, &mut rng);
+        assert_eq!(ai.state, AiState::Chase);
+        run(&mut ai, &LUNGER, Vec3::ZERO, Some(close), 1, &mut rng);
+        assert_eq!(ai.state, AiState::Chase);
+        run(&mut ai, &LUNGER, Vec3::ZERO, Some(close), 1, &mut rng);
+        assert_eq!(ai.state, AiState::WindUp);
+    }
+
+    #[test]
+    fn test_stunned_then_dying() {
+        let mut rng = SimRng::new(7);
+        let mut ai = EnemyAi::new();
+        let target = Some(vec3(0.0, 0.0, 10.0));
+
+        // hits don't interrupt a spawn
+        ai.on_hit(&LUNGER);
+        assert_eq!(ai.state, AiState::Spawning);
+
+        run(&mut ai, &LUNGER, Vec3::ZERO, target, 3, &mut rng);
+        assert_eq!(ai.state, AiState::Chase);
+
+        ai.on_hit(&LUNGER);
+        let action = run(&mut ai, &LUNGER, Vec3::ZERO, target, 2, &mut rng);
+        assert_eq!(ai.state, AiState::Stunned);
+        assert!(!ai.is_dangerous());
+        assert_eq!(action.steering_velocity, Vec3::ZERO);
+        run(&mut ai, &LUNGER, Vec3::ZERO, target, 1, &mut rng);
+        assert_eq!(ai.state, AiState::Chase);
+
+        // behaviours without a stun shrug hits off
+        let heavy = AiBehaviour { stun_time: 0.0, ..LUNGER };
+        ai.on_hit(&heavy);
+        assert_eq!(ai.state, AiState::Chase);
+
+        ai.on_killed();
+        run(&mut ai, &LUNGER, Vec3::ZERO, target, 1, &mut rng);
+        assert!(ai.is_dying() && !ai.is_dead(&LUNGER));
+        assert_eq!(ai.dying_progress(&LUNGER), 0.5);
+
+        // a hit or another kill while dying changes nothing
+        ai.on_hit(&LUNGER);
+        ai.on_killed();
+        run(&mut ai, &LUNGER, Vec3::ZERO, target, 1, &mut rng);
+        assert!(ai.is_dead(&LUNGER));
+        assert_eq!(ai.dying_progress(&LUNGER), 1.0);
+    }
+
+    #[test]
+    fn test_shooter_fires_once_per_attack() {
+        let mut rng = SimRng::new(3);
+        let mut ai = EnemyAi::new();
+        let shooter = AiBehaviour {
+            chase: ChaseStyle::KeepDistance { range: 6.0 },
+            attack_range: 8.0,
+            attack: AttackStyle::Shoot,
+            attack_time: 0.5,
+            retreat_time: 0.0,
+            ..LUNGER
+        };
+
+        // winds up from further out than a lunger would
+        let action = run(&mut ai, &shooter, Vec3::ZERO, Some(vec3(0.0, 0.0, 5.5)), 4, &mut rng);
+        assert_eq!(ai.state, AiState::WindUp);
+        let mut shots = 0;
+        for _ in 0..4 {
+            let action = ai.update(&shooter, Vec3::ZERO, Some(vec3(0.0, 0.0, 5.5)), DELTA_TIME, &mut rng);
+            shots += action.fire as usize;
+            assert!(!action.is_lunging);
+        }
+        assert_eq!(shots, 1);
+        assert_eq!(action.facing, Some(Vec3::Z));
+        assert_eq!(ai.state, AiState::Chase);
+
+        // backs off when the target is too close, and wanders once it is out of sight
+        let action = ai.update(&shooter, Vec3::ZERO, Some(vec3(0.0, 0.0, 2.0)), DELTA_TIME, &mut rng);
+        assert_eq!(action.steering_velocity, Vec3::NEG_Z * shooter.chase_speed);
+        ai.update(&shooter, Vec3::ZERO, None, DELTA_TIME, &mut rng);
+        assert_eq!(ai.state, AiState::Wander);
+    }
+}
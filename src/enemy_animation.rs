@@ -0,0 +1,212 @@
+use crate::animation_set::{AnimationSet, ClipRepeat};
+use crate::enemy_ai::AiState;
+use small_gl_core::animator::WeightedAnimation;
+
+//
+// Per enemy animation. Enemies all share the one model and its clips, so each enemy only keeps which clip it is
+// playing, since when, and a phase so a crowd of them doesn't move in step. The model is posed from these as
+// each enemy is drawn. It is plain data so the enemies can still be handed to the workers.
+//
+
+const TRANSITION_TIME: f32 = 0.15;
+
+// one cycle of the shader wiggle, which runs at wiggleTimeModifier in wiggly_shader.vert
+const WIGGLE_CYCLE: f32 = std::f32::consts::TAU / 9.4;
+
+const WALK: &str = "walk";
+const ATTACK: &str = "attack";
+const HIT: &str = "hit";
+const DEATH: &str = "death";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EnemyClip {
+    Walk,
+    Attack,
+    Hit,
+    Death,
+}
+
+impl EnemyClip {
+    /// The clip that goes with a state of the enemy's state machine.
+    pub const fn for_state(state: AiState) -> Self {
+        match state {
+            AiState::Spawning | AiState::Wander | AiState::Chase | AiState::Retreat => EnemyClip::Walk,
+            AiState::WindUp | AiState::Attack => EnemyClip::Attack,
+            AiState::Stunned => EnemyClip::Hit,
+            AiState::Dying => EnemyClip::Death,
+        }
+    }
+
+    /// How hard the shader wiggles the model when the clip can't be played: harder for an attack, barely while
+    /// stunned, and not at all while dying.
+    pub const fn wiggle_scale(self) -> f32 {
+        match self {
+            EnemyClip::Walk => 1.0,
+            EnemyClip::Attack => 1.8,
+            EnemyClip::Hit => 0.3,
+            EnemyClip::Death => 0.0,
+        }
+    }
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            EnemyClip::Walk => WALK,
+            EnemyClip::Attack => ATTACK,
+            EnemyClip::Hit => HIT,
+            EnemyClip::Death => DEATH,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct EnemyAnimation {
+    /// Fraction of a cycle the looping clips are shifted by.
+    pub phase: f32,
+    clip: EnemyClip,
+    clip_start: f32,
+    previous_clip: EnemyClip,
+    previous_start: f32,
+}
+
+impl EnemyAnimation {
+    pub const fn new(phase: f32) -> Self {
+        Self {
+            phase,
+            clip: EnemyClip::Walk,
+            clip_start: 0.0,
+            previous_clip: EnemyClip::Walk,
+            previous_start: 0.0,
+        }
+    }
+
+    pub fn clip(&self) -> EnemyClip {
+        self.clip
+    }
+
+    /// Follows the state machine, starting the clip for its state when that changes.
+    pub fn update(&mut self, state: AiState, frame_time: f32) {
+        let clip = EnemyClip::for_state(state);
+        if clip == self.clip {
+            return;
+        }
+        self.previous_clip = self.clip;
+        self.previous_start = self.clip_start;
+        self.clip = clip;
+        self.clip_start = frame_time;
+    }
+
+    /// The pose at `frame_time` as animations for the shared model. Clips the animation set doesn't define are left out.
+    pub fn weighted_animations(&self, animations: &AnimationSet, frame_time: f32) -> Vec<WeightedAnimation> {
+        self.clip_weights(frame_time)
+            .into_iter()
+            .filter_map(|(clip, start_time, weight)| {
+                let definition = animations.definition(clip.name())?;
+                Some(match definition.repeat {
+                    ClipRepeat::Forever => definition.weighted_at_phase(weight, self.phase),
+                    ClipRepeat::Once => definition.weighted(weight, start_time),
+                })
+            })
+            .collect()
+    }
+
+    /// The shader wiggle for when the model has no clips, as the time to wiggle at and its scale. The time is shifted
+    /// by the phase and the scale eases between the clips' like their weights would.
+    pub fn wiggle(&self, frame_time: f32) -> (f32, f32) {
+        let scale = self
+            .clip_weights(frame_time)
+            .iter()
+            .map(|&(clip, _, weight)| clip.wiggle_scale() * weight)
+            .sum();
+        (frame_time + self.phase * WIGGLE_CYCLE, scale)
+    }
+
+    // The clips playing with their start times and weights, the previous clip fading out over the transition time.
+    fn clip_weights(&self, frame_time: f32) -> Vec<(EnemyClip, f32, f32)> {
+        let blend = ((frame_time - self.clip_start) / TRANSITION_TIME).clamp(0.0, 1.0);
+        if blend >= 1.0 || self.previous_clip == self.clip {
+            return vec![(self.clip, self.clip_start, 1.0)];
+        }
+        vec![(self.previous_clip, self.previous_start, 1.0 - blend), (self.clip, self.clip_start, blend)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clips_follow_the_state_machine() {
+        let mut animation = EnemyAnimation::new(0.3);
+        assert_eq!(animation.clip_weights(1.0), [(EnemyClip::Walk, 0.0, 1.0)]);
+
+        animation.update(AiState::Chase, 1.0);
+        assert_eq!(animation.clip(), EnemyClip::Walk);
+
+        animation.update(AiState::WindUp, 2.0);
+        animation.update(AiState::Attack, 2.1);
+        assert_eq!(animation.clip(), EnemyClip::Attack);
+
+        // attack fades in over the walk
+        let weights = animation.clip_weights(2.0 + TRANSITION_TIME / 2.0);
+        assert_eq!(weights.len(), 2);
+        assert_eq!((weights[0].0, weights[1].0, weights[1].1), (EnemyClip::Walk, EnemyClip::Attack, 2.0));
+        assert!((weights[0].2 - 0.5).abs() < 1e-4 && (weights[1].2 - 0.5).abs() < 1e-4);
+        assert_eq!(animation.clip_weights(3.0), [(EnemyClip::Attack, 2.0, 1.0)]);
+
+        animation.update(AiState::Stunned, 3.0);
+        animation.update(AiState::Dying, 3.5);
+        assert_eq!(animation.clip_weights(4.0), [(EnemyClip::Death, 3.5, 1.0)]);
+    }
+
+    #[test]
+    fn test_wiggle_follows_the_clips() {
+        let mut animation = EnemyAnimation::new(0.5);
+        let (time, scale) = animation.wiggle(1.0);
+        assert!((time - (1.0 + WIGGLE_CYCLE / 2.0)).abs() < 1e-5);
+        assert_eq!(scale, 1.0);
+
+        // eases up to the attack's wiggle
+        animation.update(AiState::Attack, 2.0);
+        let (_, halfway) = animation.wiggle(2.0 + TRANSITION_TIME / 2.0);
+        assert!((halfway - 1.4).abs() < 1e-4);
+        assert_eq!(animation.wiggle(3.0).1, EnemyClip::Attack.wiggle_scale());
+
+        animation.update(AiState::Dying, 3.0);
+        assert_eq!(animation.wiggle(4.0).1, 0.0);
+    }
+
+    #[test]
+    fn test_each_enemy_samples_its_own_clip() {
+        use crate::animation_set::parse_clip_definitions;
+
+        let animations = AnimationSet::new(parse_clip_definitions("walk = 10 40 forever\nattack = 50 70 once\ndeath = 80 120 once\n"));
+        let layers = |animation: &EnemyAnimation, frame_time: f32| {
+            animation
+                .weighted_animations(&animations, frame_time)
+                .iter()
+                .map(|a| (a.weight, a.start_tick, a.end_tick, a.offset, a.optional_start))
+                .collect::<Vec<_>>()
+        };
+
+        // two walkers share the clip, each shifted on by its own phase of the 30 frame cycle
+        let walker = EnemyAnimation::new(0.25);
+        let other_walker = EnemyAnimation::new(0.5);
+        assert_eq!(layers(&walker, 5.0), [(1.0, 10.0, 40.0, 7.5, 0.0)]);
+        assert_eq!(layers(&other_walker, 5.0), [(1.0, 10.0, 40.0, 15.0, 0.0)]);
+
+        // an attacker plays the attack from when it started, whatever its phase
+        let mut attacker = EnemyAnimation::new(0.25);
+        attacker.update(AiState::Attack, 2.0);
+        assert_eq!(layers(&attacker, 5.0), [(1.0, 50.0, 70.0, 0.0, 2.0)]);
+
+        // and fades it in over its own walk
+        let fading = layers(&attacker, 2.0 + TRANSITION_TIME / 2.0);
+        assert_eq!((fading[0].1, fading[0].3, fading[1].1, fading[1].4), (10.0, 7.5, 50.0, 2.0));
+        assert!((fading[0].0 - 0.5).abs() < 1e-4 && (fading[1].0 - 0.5).abs() < 1e-4);
+
+        // a clip the set doesn't have leaves the enemy unposed rather than borrowing another
+        let mut stunned = EnemyAnimation::new(0.25);
+        stunned.update(AiState::Stunned, 1.0);
+        assert!(layers(&stunned, 5.0).is_empty());
+    }
+}
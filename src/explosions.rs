@@ -0,0 +1,188 @@
+use crate::aabb::Aabb;
+use crate::enemy::LARGEST_ENEMY_COLLIDER;
+use crate::sprite_sheet::{SpriteSheet, SpriteSheetSprite};
+use crate::State;
+use glam::{vec3, Mat4, Vec3};
+use small_gl_core::gl;
+use small_gl_core::gl::GLuint;
+use small_gl_core::shader::Shader;
+use small_gl_core::texture::{bind_texture, Texture, TextureConfig, TextureWrap};
+
+const EXPLOSION_LIGHT_TIME: f32 = 0.15;
+// seconds
+const EXPLOSION_LIGHT_COLOR: Vec3 = vec3(1.0, 0.6, 0.2);
+const EXPLOSION_SPRITE_Y: f32 = 0.1;
+
+/// Area of effect damage. Grenades, exploding enemies and weapon effects queue these on `State::explosions`.
+#[derive(Debug, Clone, Copy)]
+pub struct Explosion {
+    pub position: Vec3,
+    pub radius: f32,
+    /// Damage at the center, falling off to zero at the radius.
+    pub damage: f32,
+    /// Impulse at the center, falling off like the damage.
+    pub knockback: f32,
+}
+
+impl Explosion {
+    pub const fn new(position: Vec3, radius: f32, damage: f32, knockback: f32) -> Self {
+        Self {
+            position,
+            radius,
+            damage,
+            knockback,
+        }
+    }
+}
+
+struct ExplosionSprite {
+    sprite: SpriteSheetSprite,
+    scale: f32,
+}
+
+pub struct Explosions {
+    unit_square_vao: i32,
+    explosion_spritesheet: SpriteSheet,
+    sprites: Vec<ExplosionSprite>,
+    pending: Vec<Explosion>,
+    light_position: Vec3,
+    light_age: f32,
+}
+
+impl Explosions {
+    pub fn new(unit_square_vao: i32) -> Self {
+        let texture_config = TextureConfig::new().set_wrap(TextureWrap::Repeat);
+        let texture = Texture::new("angrygl_assets/bullet/impact_spritesheet_with_00.png", &texture_config).unwrap();
+        let explosion_spritesheet = SpriteSheet::new(texture, 11, 0.05);
+
+        Self {
+            unit_square_vao,
+            explosion_spritesheet,
+            sprites: vec![],
+            pending: vec![],
+            light_position: Vec3::ZERO,
+            light_age: EXPLOSION_LIGHT_TIME,
+        }
+    }
+
+    /// Drops queued explosions and the effects still playing.
+    pub fn clear(&mut self) {
+        self.pending.clear();
+        self.sprites.clear();
+        self.light_age = EXPLOSION_LIGHT_TIME;
+    }
+
+    /// Queues an explosion to go off on the next call to `apply_explosions`.
+    pub fn queue(&mut self, explosion: Explosion) {
+        self.pending.push(explosion);
+    }
+
+    pub fn update(&mut self, delta_time: f32) {
+        self.light_age += delta_time;
+
+        for explosion in self.sprites.iter_mut() {
+            explosion.sprite.age += delta_time;
+        }
+        let sprite_duration = self.explosion_spritesheet.num_columns as f32 * self.explosion_spritesheet.time_per_sprite;
+        self.sprites.retain(|e| e.sprite.age < sprite_duration);
+    }
+
+    /// The point light of the most recent explosion while it is still bright.
+    pub fn get_light(&self) -> Option<(Vec3, Vec3)> {
+        if self.light_age < EXPLOSION_LIGHT_TIME {
+            let fade = 1.0 - self.light_age / EXPLOSION_LIGHT_TIME;
+            Some((self.light_position, EXPLOSION_LIGHT_COLOR * fade))
+        } else {
+            None
+        }
+    }
+
+    fn add_effects(&mut self, explosion: &Explosion) {
+        self.sprites.push(ExplosionSprite {
+            sprite: SpriteSheetSprite::new(vec3(explosion.position.x, EXPLOSION_SPRITE_Y, explosion.position.z)),
+            scale: explosion.radius,
+        });
+        self.light_position = explosion.position;
+        self.light_age = 0.0;
+    }
+
+    pub fn draw(&self, sprite_shader: &Shader, projection_view: &Mat4) {
+        if self.sprites.is_empty() {
+            return;
+        }
+
+        sprite_shader.use_shader();
+        sprite_shader.set_mat4("PV", projection_view);
+
+        sprite_shader.set_int("numCols", self.explosion_spritesheet.num_columns);
+        sprite_shader.set_float("timePerSprite", self.explosion_spritesheet.time_per_sprite);
+
+        bind_texture(sprite_shader, 0, "spritesheet", &self.explosion_spritesheet.texture);
+
+        unsafe {
+            gl::Enable(gl::BLEND);
+            gl::DepthMask(gl::FALSE);
+            gl::Disable(gl::CULL_FACE);
+
+            gl::BindVertexArray(self.unit_square_vao as GLuint);
+        }
+
+        for explosion in &self.sprites {
+            let mut model = Mat4::from_translation(explosion.sprite.world_position);
+            model *= Mat4::from_rotation_x(-90.0f32.to_radians());
+            model *= Mat4::from_scale(Vec3::splat(explosion.scale));
+
+            sprite_shader.set_float("age", explosion.sprite.age);
+            sprite_shader.set_mat4("model", &model);
+
+            unsafe {
+                gl::DrawArrays(gl::TRIANGLES, 0, 6);
+            }
+        }
+
+        unsafe {
+            gl::Disable(gl::BLEND);
+            gl::Enable(gl::CULL_FACE);
+            gl::DepthMask(gl::TRUE);
+        }
+    }
+}
+
+/// Sets off the queued explosions. Explosions queued while these go off, such as
+/// chained exploding enemies, go off on the next tick.
+pub fn apply_explosions(state: &mut State) {
+    let pending = std::mem::take(&mut state.explosions.pending);
+
+    for explosion in pending.iter() {
+        apply_explosion(state, explosion);
+    }
+}
+
+fn apply_explosion(state: &mut State, explosion: &Explosion) {
+    // An aabb around the blast as the broad phase before the exact capsule distance.
+    let mut bounds = Aabb::new();
+    bounds.expand_to_include(explosion.position);
+    bounds.expand_by(explosion.radius + LARGEST_ENEMY_COLLIDER.height / 2.0 + LARGEST_ENEMY_COLLIDER.radius);
+
+    for enemy in state.enemies.iter_mut() {
+        if !enemy.can_be_hit() || !bounds.contains_point(enemy.position) {
+            continue;
+        }
+
+        let distance = enemy.kind.collider().at(enemy.position, enemy.dir).distance_to_point(explosion.position);
+
+        if distance > explosion.radius {
+            continue;
+        }
+
+        let falloff = 1.0 - distance / explosion.radius;
+
+        let mut push_dir = enemy.position - explosion.position;
+        push_dir.y = 0.0;
+        enemy.take_hit(explosion.damage * falloff, push_dir.normalize_or_zero() * explosion.knockback * falloff);
+    }
+
+    state.burn_marks.add_mark(vec3(explosion.position.x, 0.01, explosion.position.z));
+    state.sound_system.play_explosion();
+    state.explosions.add_effects(explosion);
+}
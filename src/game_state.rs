@@ -0,0 +1,239 @@
+use crate::input::GameAction;
+use crate::overlay::Overlay;
+use crate::State;
+use glam::{vec2, vec4, Vec4};
+
+//
+// The top-level states of the game, kept on a stack so menus open over the game and close back to it.
+// Only the state on top handles input, and it draws its overlay over the scene, which stays frozen
+// while anything other than playing is on top.
+//
+
+const GAME_OVER_DELAY: f32 = 0.5; // seconds after the last player's death clip lands
+const GAME_OVER_TIMEOUT: f32 = 4.0; // seconds after the last player dies, in case a death clip never lands
+
+const DIM_COLOR: Vec4 = vec4(0.0, 0.0, 0.0, 0.6);
+const GAME_OVER_DIM_COLOR: Vec4 = vec4(0.25, 0.0, 0.0, 0.6);
+const TITLE_COLOR: Vec4 = vec4(1.0, 1.0, 1.0, 1.0);
+const ITEM_COLOR: Vec4 = vec4(0.6, 0.6, 0.7, 1.0);
+const SELECTED_ITEM_COLOR: Vec4 = vec4(1.0, 0.8, 0.2, 1.0);
+const TITLE_PIXEL_SIZE: f32 = 8.0;
+const ITEM_PIXEL_SIZE: f32 = 5.0;
+const ITEM_SPACING: f32 = 60.0; // screen pixels between menu lines
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MenuItem {
+    Resume,
+    Restart,
+    Settings,
+    Quit,
+    CameraRelative,
+    Back,
+}
+
+const PAUSE_ITEMS: [MenuItem; 4] = [MenuItem::Resume, MenuItem::Restart, MenuItem::Settings, MenuItem::Quit];
+const SETTINGS_ITEMS: [MenuItem; 2] = [MenuItem::CameraRelative, MenuItem::Back];
+const GAME_OVER_ITEMS: [MenuItem; 2] = [MenuItem::Restart, MenuItem::Quit];
+
+impl MenuItem {
+    fn label(self, state: &State) -> String {
+        match self {
+            MenuItem::Resume => "Resume".to_string(),
+            MenuItem::Restart => "Restart".to_string(),
+            MenuItem::Settings => "Settings".to_string(),
+            MenuItem::Quit => "Quit".to_string(),
+            MenuItem::CameraRelative => format!("Camera relative {}", if state.camera_relative_movement { "on" } else { "off" }),
+            MenuItem::Back => "Back".to_string(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Menu {
+    items: &'static [MenuItem],
+    selected: usize,
+}
+
+impl Menu {
+    const fn new(items: &'static [MenuItem]) -> Self {
+        Self { items, selected: 0 }
+    }
+
+    /// Moves the selection with the menu actions, returning the item when it is chosen.
+    fn handle_input(&mut self, state: &State) -> Option<MenuItem> {
+        if state.input.was_pressed(GameAction::MenuUp) {
+            self.selected = (self.selected + self.items.len() - 1) % self.items.len();
+        }
+        if state.input.was_pressed(GameAction::MenuDown) {
+            self.selected = (self.selected + 1) % self.items.len();
+        }
+        if state.input.was_pressed(GameAction::MenuSelect) {
+            return Some(self.items[self.selected]);
+        }
+        None
+    }
+
+    fn draw(&self, overlay: &Overlay, state: &State, title: &str, dim_color: Vec4) {
+        overlay.fill_screen(dim_color);
+
+        let center_x = overlay.width / 2.0;
+        let top = overlay.height / 2.0 + self.items.len() as f32 * ITEM_SPACING / 2.0;
+
+        overlay.draw_text(title, vec2(center_x, top + ITEM_SPACING), TITLE_PIXEL_SIZE, TITLE_COLOR);
+
+        for (index, item) in self.items.iter().enumerate() {
+            let color = if index == self.selected { SELECTED_ITEM_COLOR } else { ITEM_COLOR };
+            let y = top - (index as f32 + 0.5) * ITEM_SPACING;
+            overlay.draw_text(&item.label(state), vec2(center_x, y), ITEM_PIXEL_SIZE, color);
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum GameState {
+    Playing,
+    Paused(Menu),
+    Settings(Menu),
+    GameOver(Menu),
+}
+
+/// What a state asks of the stack after handling input.
+enum Transition {
+    None,
+    Push(GameState),
+    Pop,
+    Command(GameCommand),
+}
+
+/// Requests that reach outside the stack, carried out by the main loop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameCommand {
+    Restart,
+    Quit,
+}
+
+pub struct GameStateStack {
+    states: Vec<GameState>,
+    time_all_dead: f32,
+    time_all_landed: f32,
+}
+
+impl GameStateStack {
+    pub fn new() -> Self {
+        Self {
+            states: vec![GameState::Playing],
+            time_all_dead: 0.0,
+            time_all_landed: 0.0,
+        }
+    }
+
+    /// Whether the game is simulating, anything else on top freezes it.
+    pub fn is_playing(&self) -> bool {
+        matches!(self.states.last(), Some(GameState::Playing))
+    }
+
+    /// Back to a fresh game with nothing on top.
+    pub fn reset(&mut self) {
+        self.states = vec![GameState::Playing];
+        self.time_all_dead = 0.0;
+        self.time_all_landed = 0.0;
+    }
+
+    /// Lets the state on top handle this frame's input.
+    pub fn handle_input(&mut self, state: &mut State) -> Option<GameCommand> {
+        let transition = match self.states.last_mut() {
+            Some(GameState::Playing) => {
+                if state.input.was_pressed(GameAction::Pause) {
+                    Transition::Push(GameState::Paused(Menu::new(&PAUSE_ITEMS)))
+                } else {
+                    Transition::None
+                }
+            }
+            Some(GameState::Paused(menu)) => {
+                if state.input.was_pressed(GameAction::Pause) || state.input.was_pressed(GameAction::MenuBack) {
+                    Transition::Pop
+                } else {
+                    match menu.handle_input(state) {
+                        Some(MenuItem::Resume) => Transition::Pop,
+                        Some(MenuItem::Restart) => Transition::Command(GameCommand::Restart),
+                        Some(MenuItem::Settings) => Transition::Push(GameState::Settings(Menu::new(&SETTINGS_ITEMS))),
+                        Some(MenuItem::Quit) => Transition::Command(GameCommand::Quit),
+                        _ => Transition::None,
+                    }
+                }
+            }
+            Some(GameState::Settings(menu)) => {
+                if state.input.was_pressed(GameAction::MenuBack) {
+                    Transition::Pop
+                } else {
+                    match menu.handle_input(state) {
+                        Some(MenuItem::CameraRelative) => {
+                            state.camera_relative_movement = !state.camera_relative_movement;
+                            Transition::None
+                        }
+                        Some(MenuItem::Back) => Transition::Pop,
+                        _ => Transition::None,
+                    }
+                }
+            }
+            Some(GameState::GameOver(menu)) => match menu.handle_input(state) {
+                Some(MenuItem::Restart) => Transition::Command(GameCommand::Restart),
+                Some(MenuItem::Quit) => Transition::Command(GameCommand::Quit),
+                _ => Transition::None,
+            },
+            None => Transition::None,
+        };
+
+        match transition {
+            Transition::None => None,
+            Transition::Push(game_state) => {
+                self.states.push(game_state);
+                None
+            }
+            Transition::Pop => {
+                self.states.pop();
+                None
+            }
+            Transition::Command(command) => Some(command),
+        }
+    }
+
+    /// Moves to game over a moment after every player's death clip has landed.
+    pub fn update(&mut self, state: &State) {
+        if !self.is_playing() {
+            return;
+        }
+
+        if state.players.iter().any(|p| p.borrow().is_alive) {
+            self.time_all_dead = 0.0;
+            self.time_all_landed = 0.0;
+            return;
+        }
+
+        self.time_all_dead += state.delta_time;
+        if state.players.iter().all(|p| p.borrow().death_landed) {
+            self.time_all_landed += state.delta_time;
+        }
+
+        if self.time_all_landed >= GAME_OVER_DELAY || self.time_all_dead >= GAME_OVER_TIMEOUT {
+            self.states.push(GameState::GameOver(Menu::new(&GAME_OVER_ITEMS)));
+        }
+    }
+
+    pub fn draw(&self, overlay: &Overlay, state: &State) {
+        match self.states.last() {
+            Some(GameState::Paused(menu)) => menu.draw(overlay, state, "Paused", DIM_COLOR),
+            Some(GameState::Settings(menu)) => menu.draw(overlay, state, "Settings", DIM_COLOR),
+            Some(GameState::GameOver(menu)) => {
+                let title = format!("Game over  {} kills", state.kill_count);
+                menu.draw(overlay, state, &title, GAME_OVER_DIM_COLOR);
+            }
+            Some(GameState::Playing) | None => {}
+        }
+    }
+
+    /// Whether the state on top has anything to draw over the scene.
+    pub fn has_overlay(&self) -> bool {
+        !self.is_playing()
+    }
+}
@@ -0,0 +1,191 @@
+use crate::input::{Input, InputBinding, GAMEPAD_BUTTONS, GAMEPAD_TRIGGERS};
+use glam::{vec2, Vec2};
+use glfw::{Action, GamepadAxis, Joystick, JoystickEvent, JoystickId};
+use std::sync::Mutex;
+
+// Connection events are queued by the glfw joystick callback and picked up by the game loop.
+static JOYSTICK_EVENTS: Mutex<Vec<(JoystickId, JoystickEvent)>> = Mutex::new(Vec::new());
+
+const ALL_JOYSTICKS: [JoystickId; 4] = [JoystickId::Joystick1, JoystickId::Joystick2, JoystickId::Joystick3, JoystickId::Joystick4];
+
+pub fn queue_joystick_event(jid: JoystickId, event: JoystickEvent) {
+    if let Ok(mut events) = JOYSTICK_EVENTS.lock() {
+        events.push((jid, event));
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GamepadSettings {
+    /// Stick deflection below this is ignored. Range 0..1.
+    pub move_dead_zone: f32,
+    pub aim_dead_zone: f32,
+    /// Multiplier on stick deflection, above 1 reaches full speed before the stick is at its limit.
+    pub move_sensitivity: f32,
+    /// How fast the aim turns toward the stick, in radians per second at full deflection. Less deflection turns
+    /// slower for fine adjustments. Zero snaps the aim straight to the stick.
+    pub aim_sensitivity: f32,
+    /// Trigger value above which the trigger counts as pulled. Triggers range -1..1.
+    pub trigger_threshold: f32,
+}
+
+impl Default for GamepadSettings {
+    fn default() -> Self {
+        Self {
+            move_dead_zone: 0.2,
+            aim_dead_zone: 0.3,
+            move_sensitivity: 1.0,
+            aim_sensitivity: 0.0,
+            trigger_threshold: 0.0,
+        }
+    }
+}
+
+pub struct Gamepad {
+    glfw: glfw::Glfw,
+    joystick: Option<Joystick>,
+    pub settings: GamepadSettings,
+}
+
+impl Gamepad {
+    pub fn new(glfw: &glfw::Glfw, settings: GamepadSettings) -> Self {
+        let mut gamepad = Self {
+            glfw: glfw.clone(),
+            joystick: None,
+            settings,
+        };
+        gamepad.find_joystick();
+        gamepad
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.joystick.is_some()
+    }
+
+    fn find_joystick(&mut self) {
+        self.joystick = ALL_JOYSTICKS
+            .iter()
+            .map(|jid| self.glfw.get_joystick(*jid))
+            .find(|joy| joy.is_present() && joy.is_gamepad());
+
+        if let Some(joy) = &self.joystick {
+            info!("using gamepad {:?}: {:?}", joy.id, joy.get_gamepad_name());
+        }
+    }
+
+    fn handle_connection_events(&mut self, input: &mut Input) {
+        let events: Vec<(JoystickId, JoystickEvent)> = match JOYSTICK_EVENTS.lock() {
+            Ok(mut events) => events.drain(..).collect(),
+            Err(_) => return,
+        };
+
+        for (jid, event) in events {
+            match event {
+                JoystickEvent::Connected => {
+                    if self.joystick.is_none() {
+                        self.find_joystick();
+                    }
+                }
+                JoystickEvent::Disconnected => {
+                    if self.joystick.as_ref().is_some_and(|joy| joy.id == jid) {
+                        info!("gamepad {:?} disconnected", jid);
+                        // let go of anything the gamepad was holding so the player doesn't keep running or firing
+                        input.release_gamepad();
+                        self.joystick = None;
+                        self.find_joystick();
+                    }
+                }
+            }
+        }
+    }
+
+    /// Feeds the gamepad sticks, buttons and triggers into the input actions.
+    pub fn update(&mut self, input: &mut Input, delta_time: f32) {
+        self.handle_connection_events(input);
+
+        let gamepad_state = match self.joystick.as_ref().and_then(|joy| joy.get_gamepad_state()) {
+            Some(gamepad_state) => gamepad_state,
+            None => return,
+        };
+
+        // Stick up is negative y. Map the sticks onto the same axes as the keyboard, x forward and y right.
+        let left_stick = apply_dead_zone(
+            vec2(gamepad_state.get_axis(GamepadAxis::AxisLeftX), gamepad_state.get_axis(GamepadAxis::AxisLeftY)),
+            self.settings.move_dead_zone,
+        );
+        let right_stick = apply_dead_zone(
+            vec2(gamepad_state.get_axis(GamepadAxis::AxisRightX), gamepad_state.get_axis(GamepadAxis::AxisRightY)),
+            self.settings.aim_dead_zone,
+        );
+
+        input.gamepad_move = (vec2(-left_stick.y, left_stick.x) * self.settings.move_sensitivity).clamp_length_max(1.0);
+
+        // Keep the last stick aim once the stick is released, until the mouse moves again.
+        let aim_vec = vec2(-right_stick.y, right_stick.x);
+        if aim_vec.length_squared() > 0.0 {
+            input.gamepad_aim = Some(turn_aim(input.gamepad_aim, aim_vec, self.settings.aim_sensitivity * delta_time));
+        }
+
+        for button in GAMEPAD_BUTTONS {
+            input.set_binding_state(InputBinding::GamepadButton(button), gamepad_state.get_button_state(button) == Action::Press);
+        }
+
+        for trigger in GAMEPAD_TRIGGERS {
+            input.set_binding_state(
+                InputBinding::GamepadTrigger(trigger),
+                gamepad_state.get_axis(trigger) > self.settings.trigger_threshold,
+            );
+        }
+    }
+}
+
+/// Radial dead zone that rescales the remaining range back to 0..1.
+fn apply_dead_zone(stick: Vec2, dead_zone: f32) -> Vec2 {
+    let length = stick.length();
+    if length <= dead_zone {
+        return Vec2::ZERO;
+    }
+    let scaled = ((length - dead_zone) / (1.0 - dead_zone)).min(1.0);
+    stick / length * scaled
+}
+
+/// Turns the aim toward the stick by at most `max_turn` radians, scaled by how far the stick is pushed.
+/// With no aim yet, or no limit, it takes the stick's direction.
+fn turn_aim(aim: Option<Vec2>, stick: Vec2, max_turn: f32) -> Vec2 {
+    let target = stick.normalize();
+    let Some(aim) = aim.filter(|_| max_turn > 0.0) else {
+        return target;
+    };
+    let angle = aim.angle_between(target);
+    let max_angle = max_turn * stick.length().min(1.0);
+    if angle.abs() <= max_angle {
+        return target;
+    }
+    Vec2::from_angle(max_angle.copysign(angle)).rotate(aim)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_aim_turns_at_the_sensitivity() {
+        let aim = Some(vec2(1.0, 0.0));
+
+        // snaps without a sensitivity
+        assert_eq!(turn_aim(aim, vec2(0.0, 0.5), 0.0), vec2(0.0, 1.0));
+
+        // a full push turns by the whole step, either way round
+        let turned = turn_aim(aim, vec2(0.0, 1.0), 0.5);
+        assert!(turned.abs_diff_eq(Vec2::from_angle(0.5), 1e-5));
+        let turned = turn_aim(aim, vec2(0.0, -1.0), 0.5);
+        assert!(turned.abs_diff_eq(Vec2::from_angle(-0.5), 1e-5));
+
+        // half a push turns half as far, and a small gap is closed
+        let turned = turn_aim(aim, vec2(0.0, 0.5), 0.5);
+        assert!(turned.abs_diff_eq(Vec2::from_angle(0.25), 1e-5));
+        assert_eq!(turn_aim(aim, vec2(1.0, 0.1), 0.5), vec2(1.0, 0.1).normalize());
+
+        // the first aim is taken as is
+        assert_eq!(turn_aim(None, vec2(0.0, 0.5), 0.5), vec2(0.0, 1.0));
+    }
+}
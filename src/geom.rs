@@ -1,4 +1,4 @@
-use glam::{mat3, Vec3};
+use glam::Vec3;
 
 pub fn distance_between_point_and_line_segment(point: &Vec3, a: &Vec3, b: &Vec3) -> f32 {
     let ab = *b - *a;
@@ -13,84 +13,124 @@ pub fn distance_between_point_and_line_segment(point: &Vec3, a: &Vec3, b: &Vec3)
     ab.cross(ap).length() / ab.length()
 }
 
-pub fn distance_between_line_segments(a0: &Vec3, a1: &Vec3, b0: &Vec3, b1: &Vec3) -> f32 {
-    let eps = 0.001f32;
-
-    let a = *a1 - *a0;
-    let b = *b1 - *b0;
-    let mag_a = a.length();
-    let mag_b = b.length();
-
-    let a = a / mag_a;
-    let b = b / mag_b;
-
-    let cross = a.cross(b);
-    let cl = cross.length();
-    let denom = cl * cl;
-
-    // If lines are parallel (denom=0) test if lines overlap.
-    // If they don't overlap then there is a closest point solution.
-    // If they do overlap, there are infinite closest positions, but there is a closest distance
-    if denom < eps {
-        let d0 = a.dot(*b0 - *a0);
-        let d1 = a.dot(*b1 - *a0);
-
-        // Is segment B before A?
-        if d0 <= 0.0 && 0.0 >= d1 {
-            if d0.abs() < d1.abs() {
-                return (*a0 - *b0).length();
-            }
-            return (*a0 - *b1).length();
-        } else if d0 >= mag_a && mag_a <= d1 {
-            if d0.abs() < d1.abs() {
-                return (*a1 - *b0).length();
-            }
-            return (*a1 - *b1).length();
-        }
+/// The point on the segment `a`..`b` nearest to `point`. A zero length segment is the point `a`.
+pub fn closest_point_on_line_segment(point: &Vec3, a: &Vec3, b: &Vec3) -> Vec3 {
+    let ab = *b - *a;
+    let length_squared = ab.length_squared();
+    if length_squared <= f32::EPSILON {
+        return *a;
+    }
+    let t = (ab.dot(*point - *a) / length_squared).clamp(0.0, 1.0);
+    *a + ab * t
+}
 
-        // Segments overlap, return distance between parallel segments
-        return (((d0 * a) + *a0) - *b0).length();
+/// The nearest pair of points on the segments `a0`..`a1` and `b0`..`b1`, one on each.
+/// Zero length segments are treated as points and parallel segments give one of the nearest pairs.
+/// See Ericson, Real-Time Collision Detection, 5.1.9
+pub fn closest_points_between_line_segments(a0: &Vec3, a1: &Vec3, b0: &Vec3, b1: &Vec3) -> (Vec3, Vec3) {
+    let d1 = *a1 - *a0;
+    let d2 = *b1 - *b0;
+    let r = *a0 - *b0;
+    let a = d1.length_squared();
+    let e = d2.length_squared();
+    let f = d2.dot(r);
+
+    if a <= f32::EPSILON && e <= f32::EPSILON {
+        return (*a0, *b0);
     }
 
-    // Lines criss-cross: Calculate the projected closest points
-    let t = *b0 - *a0;
-    let det_a = (mat3(t, b, cross)).determinant();
-    let det_b = (mat3(t, a, cross)).determinant();
+    let (s, t) = if a <= f32::EPSILON {
+        (0.0, (f / e).clamp(0.0, 1.0))
+    } else {
+        let c = d1.dot(r);
+        if e <= f32::EPSILON {
+            ((-c / a).clamp(0.0, 1.0), 0.0)
+        } else {
+            let b = d1.dot(d2);
+            let denom = a * e - b * b;
+
+            // parallel segments have no single nearest pair, so start from a0
+            let s = if denom > f32::EPSILON * a * e {
+                ((b * f - c * e) / denom).clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+            let t = (b * s + f) / e;
+
+            if t < 0.0 {
+                ((-c / a).clamp(0.0, 1.0), 0.0)
+            } else if t > 1.0 {
+                (((b - c) / a).clamp(0.0, 1.0), 1.0)
+            } else {
+                (s, t)
+            }
+        }
+    };
 
-    let t0 = det_a / denom;
-    let t1 = det_b / denom;
+    (*a0 + d1 * s, *b0 + d2 * t)
+}
 
-    let mut p_a = *a0 + (a * t0); // Projected closest point on segment A
-    let mut p_b = *b0 + (b * t1); // Projected closest point on segment B
+pub fn distance_between_line_segments(a0: &Vec3, a1: &Vec3, b0: &Vec3, b1: &Vec3) -> f32 {
+    let (p_a, p_b) = closest_points_between_line_segments(a0, a1, b0, b1);
+    (p_a - p_b).length()
+}
 
-    // Clamp projections
-    if t0 < 0.0 {
-        p_a = *a0;
-    } else if t0 > mag_a {
-        p_a = *a1;
+/// Distance along the ray to where it first touches the sphere, or `None` if it misses.
+/// `direction` must be normalized. A ray starting inside the sphere hits at 0.
+pub fn ray_sphere_intersection(origin: &Vec3, direction: &Vec3, center: &Vec3, radius: f32) -> Option<f32> {
+    let oc = *origin - *center;
+    let c = oc.length_squared() - radius * radius;
+    if c <= 0.0 {
+        return Some(0.0);
     }
-
-    if t1 < 0.0 {
-        p_b = *b0;
-    } else if t1 > mag_b {
-        p_b = *b1;
+    let b = direction.dot(oc);
+    // r^2 less the squared distance from the centre to the line, rather than b^2 - c which cancels badly on grazing rays
+    let h = radius * radius - (oc - *direction * b).length_squared();
+    if b > 0.0 || h < 0.0 {
+        return None;
     }
+    Some(-b - h.sqrt())
+}
 
-    // Clamp projection A
-    if t0 < 0.0 || t0 > mag_a {
-        let mut dot = b.dot(p_a - *b0);
-        dot = dot.clamp(0.0, mag_b);
-        p_b = *b0 + (b * dot);
+/// Distance along the ray to where it first touches the capsule around the segment `a`..`b`, or `None` if it misses.
+/// `direction` must be normalized. A ray starting inside the capsule hits at 0.
+pub fn ray_capsule_intersection(origin: &Vec3, direction: &Vec3, a: &Vec3, b: &Vec3, radius: f32) -> Option<f32> {
+    if distance_between_point_and_line_segment(origin, a, b) <= radius {
+        return Some(0.0);
     }
 
-    // Clamp projection B
-    if t1 < 0.0 || t1 > mag_b {
-        let mut dot = a.dot(p_b - *a0);
-        dot = dot.clamp(0.0, mag_a);
-        p_a = *a0 + (a * dot);
+    let ba = *b - *a;
+    let length = ba.length();
+
+    // the cylinder body, solved in the plane across the axis. Skipped for a sphere, or when the ray runs along
+    // the axis and can only reach a cap.
+    if length > f32::EPSILON {
+        let axis = ba / length;
+        let oa = *origin - *a;
+        let d_across = *direction - axis * direction.dot(axis);
+        let o_across = oa - axis * oa.dot(axis);
+        let k2 = d_across.length_squared();
+
+        if k2 > f32::EPSILON {
+            let k1 = o_across.dot(d_across);
+            // r^2 less the squared distance from the axis to the ray's line, which stays accurate on grazing rays
+            let h = radius * radius - (o_across - d_across * (k1 / k2)).length_squared();
+            if h < 0.0 {
+                return None;
+            }
+            let t = (-k1 - (h * k2).sqrt()) / k2;
+            let along_axis = (oa + *direction * t).dot(axis);
+            if t >= 0.0 && along_axis > 0.0 && along_axis < length {
+                return Some(t);
+            }
+        }
     }
 
-    (p_a - p_b).length()
+    // the end caps
+    [a, b]
+        .into_iter()
+        .filter_map(|center| ray_sphere_intersection(origin, direction, center, radius))
+        .min_by(f32::total_cmp)
 }
 
 /// See https://github.com/icaven/glm/blob/master/glm/gtx/vector_angle.inl
@@ -109,3 +149,30 @@ static inline SIMD_CFUNC simd_float2 simd_mix(simd_float2 x, simd_float2 y, simd
   return x + t*(y - x);
 }
  */
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_segment_distance_degenerate_segments() {
+        use super::distance_between_line_segments;
+        use glam::vec3;
+
+        let a0 = vec3(0.0, 0.0, 0.0);
+        let a1 = vec3(2.0, 0.0, 0.0);
+
+        // zero length segments are points
+        let point = vec3(1.0, 3.0, 0.0);
+        assert_eq!(distance_between_line_segments(&a0, &a1, &point, &point), 3.0);
+        assert_eq!(distance_between_line_segments(&point, &point, &a0, &a1), 3.0);
+        assert_eq!(distance_between_line_segments(&point, &point, &a0, &a0), point.length());
+        assert_eq!(distance_between_line_segments(&a0, &a0, &a0, &a0), 0.0);
+
+        // parallel, overlapping and apart along the shared line
+        assert_eq!(distance_between_line_segments(&a0, &a1, &vec3(1.0, 1.0, 0.0), &vec3(3.0, 1.0, 0.0)), 1.0);
+        assert_eq!(distance_between_line_segments(&a0, &a1, &vec3(3.0, 0.0, 0.0), &vec3(5.0, 0.0, 0.0)), 1.0);
+        assert_eq!(distance_between_line_segments(&a0, &a1, &vec3(5.0, 0.0, 0.0), &vec3(3.0, 0.0, 0.0)), 1.0);
+
+        // crossing
+        assert_eq!(distance_between_line_segments(&a0, &a1, &vec3(1.0, -1.0, 0.5), &vec3(1.0, 1.0, 0.5)), 0.5);
+    }
+}
@@ -0,0 +1,503 @@
+use glam::{vec2, Vec2};
+use glfw::{Action, GamepadAxis, GamepadButton, Key, MouseButton};
+use small_gl_core::hash_map::{HashMap, HashSet};
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+//
+// Keyboard, mouse and gamepad inputs are mapped to game actions through rebindable bindings.
+// Gameplay code only asks about actions.
+//
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum GameAction {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    Fire,
+    Dash,
+    Pause,
+    Quit,
+    CameraGame,
+    CameraFloating,
+    CameraTopDown,
+    CameraSide,
+    CameraNext,
+    FloatingForward,
+    FloatingBackward,
+    FloatingLeft,
+    FloatingRight,
+    FloatingUp,
+    FloatingDown,
+    /// While held with the floating camera in view, the move actions steer it instead of the player.
+    FloatingModifier,
+    ResetViewport,
+    ToggleCameraRelative,
+    Join,
+    MenuUp,
+    MenuDown,
+    MenuSelect,
+    MenuBack,
+}
+
+const ACTION_NAMES: [(GameAction, &str); 27] = [
+    (GameAction::MoveUp, "move_up"),
+    (GameAction::MoveDown, "move_down"),
+    (GameAction::MoveLeft, "move_left"),
+    (GameAction::MoveRight, "move_right"),
+    (GameAction::Fire, "fire"),
+    (GameAction::Dash, "dash"),
+    (GameAction::Pause, "pause"),
+    (GameAction::Quit, "quit"),
+    (GameAction::CameraGame, "camera_game"),
+    (GameAction::CameraFloating, "camera_floating"),
+    (GameAction::CameraTopDown, "camera_top_down"),
+    (GameAction::CameraSide, "camera_side"),
+    (GameAction::CameraNext, "camera_next"),
+    (GameAction::FloatingForward, "floating_forward"),
+    (GameAction::FloatingBackward, "floating_backward"),
+    (GameAction::FloatingLeft, "floating_left"),
+    (GameAction::FloatingRight, "floating_right"),
+    (GameAction::FloatingUp, "floating_up"),
+    (GameAction::FloatingDown, "floating_down"),
+    (GameAction::FloatingModifier, "floating_modifier"),
+    (GameAction::ResetViewport, "reset_viewport"),
+    (GameAction::ToggleCameraRelative, "toggle_camera_relative"),
+    (GameAction::Join, "join"),
+    (GameAction::MenuUp, "menu_up"),
+    (GameAction::MenuDown, "menu_down"),
+    (GameAction::MenuSelect, "menu_select"),
+    (GameAction::MenuBack, "menu_back"),
+];
+
+impl GameAction {
+    pub fn name(&self) -> &'static str {
+        ACTION_NAMES.iter().find(|(action, _)| action == self).map(|(_, name)| *name).unwrap()
+    }
+
+    pub fn from_name(name: &str) -> Option<GameAction> {
+        ACTION_NAMES.iter().find(|(_, n)| *n == name).map(|(action, _)| *action)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum InputBinding {
+    Key(Key),
+    Mouse(MouseButton),
+    GamepadButton(GamepadButton),
+    /// A trigger axis counts as held once it is pulled past the gamepad's trigger threshold.
+    GamepadTrigger(GamepadAxis),
+}
+
+/// The physical device a binding belongs to. With local co-op each player only listens to their own device.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum InputDevice {
+    KeyboardMouse,
+    Gamepad,
+}
+
+// Keys that can be named in the controls file.
+#[rustfmt::skip]
+const BINDABLE_KEYS: [Key; 78] = [
+    Key::A, Key::B, Key::C, Key::D, Key::E, Key::F, Key::G, Key::H, Key::I, Key::J, Key::K, Key::L, Key::M,
+    Key::N, Key::O, Key::P, Key::Q, Key::R, Key::S, Key::T, Key::U, Key::V, Key::W, Key::X, Key::Y, Key::Z,
+    Key::Num0, Key::Num1, Key::Num2, Key::Num3, Key::Num4, Key::Num5, Key::Num6, Key::Num7, Key::Num8, Key::Num9,
+    Key::Kp0, Key::Kp1, Key::Kp2, Key::Kp3, Key::Kp4, Key::Kp5, Key::Kp6, Key::Kp7, Key::Kp8, Key::Kp9,
+    Key::F1, Key::F2, Key::F3, Key::F4, Key::F5, Key::F6, Key::F7, Key::F8, Key::F9, Key::F10, Key::F11, Key::F12,
+    Key::Space, Key::Escape, Key::Enter, Key::Tab, Key::Backspace,
+    Key::Up, Key::Down, Key::Left, Key::Right,
+    Key::LeftShift, Key::RightShift, Key::LeftControl, Key::RightControl, Key::LeftAlt, Key::RightAlt,
+    Key::LeftSuper, Key::RightSuper,
+    Key::Comma, Key::Period, Key::Slash,
+];
+
+// The keys glfw reports as modifiers. Any of them flew the floating camera before there were actions, and still do by
+// default, though only while the floating camera is in view so shift still dashes.
+const MODIFIER_KEYS: [Key; 8] = [
+    Key::LeftShift,
+    Key::RightShift,
+    Key::LeftControl,
+    Key::RightControl,
+    Key::LeftAlt,
+    Key::RightAlt,
+    Key::LeftSuper,
+    Key::RightSuper,
+];
+
+pub const GAMEPAD_BUTTONS: [GamepadButton; 15] = [
+    GamepadButton::ButtonA,
+    GamepadButton::ButtonB,
+    GamepadButton::ButtonX,
+    GamepadButton::ButtonY,
+    GamepadButton::ButtonLeftBumper,
+    GamepadButton::ButtonRightBumper,
+    GamepadButton::ButtonBack,
+    GamepadButton::ButtonStart,
+    GamepadButton::ButtonGuide,
+    GamepadButton::ButtonLeftThumb,
+    GamepadButton::ButtonRightThumb,
+    GamepadButton::ButtonDpadUp,
+    GamepadButton::ButtonDpadRight,
+    GamepadButton::ButtonDpadDown,
+    GamepadButton::ButtonDpadLeft,
+];
+
+pub const GAMEPAD_TRIGGERS: [GamepadAxis; 2] = [GamepadAxis::AxisLeftTrigger, GamepadAxis::AxisRightTrigger];
+
+const MOUSE_BUTTONS: [MouseButton; 8] = [
+    MouseButton::Button1,
+    MouseButton::Button2,
+    MouseButton::Button3,
+    MouseButton::Button4,
+    MouseButton::Button5,
+    MouseButton::Button6,
+    MouseButton::Button7,
+    MouseButton::Button8,
+];
+
+impl InputBinding {
+    pub fn device(&self) -> InputDevice {
+        match self {
+            InputBinding::Key(_) | InputBinding::Mouse(_) => InputDevice::KeyboardMouse,
+            InputBinding::GamepadButton(_) | InputBinding::GamepadTrigger(_) => InputDevice::Gamepad,
+        }
+    }
+
+    /// Formats as `kind:Name`, for example `key:W` or `gamepad:ButtonA`.
+    pub fn to_config_string(&self) -> String {
+        match self {
+            InputBinding::Key(key) => format!("key:{:?}", key),
+            InputBinding::Mouse(button) => format!("mouse:{:?}", button),
+            InputBinding::GamepadButton(button) => format!("gamepad:{:?}", button),
+            InputBinding::GamepadTrigger(axis) => format!("trigger:{:?}", axis),
+        }
+    }
+
+    pub fn from_config_string(value: &str) -> Option<InputBinding> {
+        let (kind, name) = value.trim().split_once(':')?;
+        let name = name.trim();
+        match kind.trim() {
+            "key" => find_by_name(&BINDABLE_KEYS, name).map(InputBinding::Key),
+            "mouse" => find_by_name(&MOUSE_BUTTONS, name).map(InputBinding::Mouse),
+            "gamepad" => find_by_name(&GAMEPAD_BUTTONS, name).map(InputBinding::GamepadButton),
+            "trigger" => find_by_name(&GAMEPAD_TRIGGERS, name).map(InputBinding::GamepadTrigger),
+            _ => None,
+        }
+    }
+}
+
+fn find_by_name<T: Copy + std::fmt::Debug>(values: &[T], name: &str) -> Option<T> {
+    values.iter().find(|v| format!("{:?}", v) == name).copied()
+}
+
+pub fn default_bindings() -> HashMap<GameAction, Vec<InputBinding>> {
+    use InputBinding::{GamepadButton as Pad, GamepadTrigger as Trigger, Key as K, Mouse};
+
+    let mut bindings = HashMap::new();
+    bindings.insert(GameAction::MoveUp, vec![K(Key::W), Pad(GamepadButton::ButtonDpadUp)]);
+    bindings.insert(GameAction::MoveDown, vec![K(Key::S), Pad(GamepadButton::ButtonDpadDown)]);
+    bindings.insert(GameAction::MoveLeft, vec![K(Key::A), Pad(GamepadButton::ButtonDpadLeft)]);
+    bindings.insert(GameAction::MoveRight, vec![K(Key::D), Pad(GamepadButton::ButtonDpadRight)]);
+    bindings.insert(GameAction::Fire, vec![Mouse(MouseButton::Button1), Trigger(GamepadAxis::AxisRightTrigger)]);
+    bindings.insert(GameAction::Dash, vec![K(Key::LeftShift), Pad(GamepadButton::ButtonB)]);
+    bindings.insert(GameAction::Pause, vec![K(Key::Space), Pad(GamepadButton::ButtonStart)]);
+    bindings.insert(GameAction::Quit, vec![K(Key::Escape)]);
+    bindings.insert(GameAction::CameraGame, vec![K(Key::Num1)]);
+    bindings.insert(GameAction::CameraFloating, vec![K(Key::Num2)]);
+    bindings.insert(GameAction::CameraTopDown, vec![K(Key::Num3)]);
+    bindings.insert(GameAction::CameraSide, vec![K(Key::Num4)]);
+    bindings.insert(GameAction::CameraNext, vec![Pad(GamepadButton::ButtonBack)]);
+    bindings.insert(GameAction::FloatingForward, vec![K(Key::Up)]);
+    bindings.insert(GameAction::FloatingBackward, vec![K(Key::Down)]);
+    bindings.insert(GameAction::FloatingLeft, vec![K(Key::Left)]);
+    bindings.insert(GameAction::FloatingRight, vec![K(Key::Right)]);
+    bindings.insert(GameAction::FloatingUp, vec![K(Key::Q)]);
+    bindings.insert(GameAction::FloatingDown, vec![K(Key::Z)]);
+    bindings.insert(GameAction::FloatingModifier, MODIFIER_KEYS.map(K).to_vec());
+    bindings.insert(GameAction::ResetViewport, vec![K(Key::T)]);
+    bindings.insert(GameAction::ToggleCameraRelative, vec![K(Key::C)]);
+    bindings.insert(GameAction::Join, vec![Pad(GamepadButton::ButtonY)]);
+    bindings.insert(GameAction::MenuUp, vec![K(Key::W), K(Key::Up), Pad(GamepadButton::ButtonDpadUp)]);
+    bindings.insert(GameAction::MenuDown, vec![K(Key::S), K(Key::Down), Pad(GamepadButton::ButtonDpadDown)]);
+    bindings.insert(GameAction::MenuSelect, vec![K(Key::Enter), Pad(GamepadButton::ButtonA)]);
+    bindings.insert(GameAction::MenuBack, vec![K(Key::Backspace), Pad(GamepadButton::ButtonB)]);
+    bindings
+}
+
+/// Reads `action = binding, binding` lines. Actions missing from the file keep their default bindings.
+pub fn parse_bindings(contents: &str) -> HashMap<GameAction, Vec<InputBinding>> {
+    let mut bindings = default_bindings();
+
+    for (line_number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let Some((name, values)) = line.split_once('=') else {
+            warn!("controls line {}: expected 'action = binding, ...'", line_number + 1);
+            continue;
+        };
+
+        let Some(action) = GameAction::from_name(name.trim()) else {
+            warn!("controls line {}: unknown action '{}'", line_number + 1, name.trim());
+            continue;
+        };
+
+        let mut action_bindings = vec![];
+        for value in values.split(',').filter(|v| !v.trim().is_empty()) {
+            match InputBinding::from_config_string(value) {
+                Some(binding) => action_bindings.push(binding),
+                None => warn!("controls line {}: unknown binding '{}'", line_number + 1, value.trim()),
+            }
+        }
+        bindings.insert(action, action_bindings);
+    }
+
+    bindings
+}
+
+pub fn format_bindings(bindings: &HashMap<GameAction, Vec<InputBinding>>) -> String {
+    let mut contents = String::from("# action = binding, binding\n# bindings are key:<Key>, mouse:<MouseButton>, gamepad:<GamepadButton> or trigger:<GamepadAxis>\n");
+
+    for (action, _) in ACTION_NAMES.iter() {
+        let values: Vec<String> = bindings
+            .get(action)
+            .map(|b| b.iter().map(InputBinding::to_config_string).collect())
+            .unwrap_or_default();
+        let _ = writeln!(contents, "{} = {}", action.name(), values.join(", "));
+    }
+
+    contents
+}
+
+pub struct Input {
+    bindings: HashMap<GameAction, Vec<InputBinding>>,
+    held_bindings: HashSet<InputBinding>,
+    pressed_actions: HashSet<GameAction>,
+    pressed_bindings: HashSet<InputBinding>,
+    /// Analog movement from the gamepad, x is forward and y is right like `move_vector`.
+    pub gamepad_move: Vec2,
+    /// Analog aim from the gamepad in world x/z. Cleared when the mouse moves.
+    pub gamepad_aim: Option<Vec2>,
+}
+
+impl Input {
+    pub fn new(bindings: HashMap<GameAction, Vec<InputBinding>>) -> Self {
+        Self {
+            bindings,
+            held_bindings: HashSet::new(),
+            pressed_actions: HashSet::new(),
+            pressed_bindings: HashSet::new(),
+            gamepad_move: Vec2::ZERO,
+            gamepad_aim: None,
+        }
+    }
+
+    /// Loads bindings from the controls file, writing out the defaults if there isn't one yet.
+    pub fn from_config(path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref();
+        match fs::read_to_string(path) {
+            Ok(contents) => Self::new(parse_bindings(&contents)),
+            Err(_) => {
+                let input = Self::new(default_bindings());
+                if let Err(e) = input.save_config(path) {
+                    warn!("could not write controls file {:?}: {}", path, e);
+                }
+                input
+            }
+        }
+    }
+
+    pub fn save_config(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, format_bindings(&self.bindings))
+    }
+
+    pub fn bindings(&self, action: GameAction) -> &[InputBinding] {
+        self.bindings.get(&action).map(|b| b.as_slice()).unwrap_or(&[])
+    }
+
+    pub fn rebind(&mut self, action: GameAction, bindings: Vec<InputBinding>) {
+        self.bindings.insert(action, bindings);
+    }
+
+    pub fn handle_key(&mut self, key: Key, action: Action) {
+        match action {
+            Action::Press => self.set_binding_state(InputBinding::Key(key), true),
+            Action::Release => self.set_binding_state(InputBinding::Key(key), false),
+            Action::Repeat => {}
+        }
+    }
+
+    pub fn handle_mouse_button(&mut self, button: MouseButton, action: Action) {
+        match action {
+            Action::Press => self.set_binding_state(InputBinding::Mouse(button), true),
+            Action::Release => self.set_binding_state(InputBinding::Mouse(button), false),
+            Action::Repeat => {}
+        }
+    }
+
+    pub fn set_binding_state(&mut self, binding: InputBinding, is_down: bool) {
+        if !is_down {
+            self.held_bindings.remove(&binding);
+            return;
+        }
+        if self.held_bindings.contains(&binding) {
+            return;
+        }
+
+        let newly_pressed: Vec<GameAction> = self
+            .bindings
+            .iter()
+            .filter(|(action, bindings)| bindings.contains(&binding) && !self.is_held(**action))
+            .map(|(action, _)| *action)
+            .collect();
+
+        self.pressed_actions.extend(newly_pressed);
+        self.pressed_bindings.insert(binding);
+        self.held_bindings.insert(binding);
+    }
+
+    /// Drops all gamepad state, used when the gamepad is unplugged.
+    pub fn release_gamepad(&mut self) {
+        self.held_bindings
+            .retain(|b| !matches!(b, InputBinding::GamepadButton(_) | InputBinding::GamepadTrigger(_)));
+        self.gamepad_move = Vec2::ZERO;
+        self.gamepad_aim = None;
+    }
+
+    pub fn is_held(&self, action: GameAction) -> bool {
+        self.bindings(action).iter().any(|b| self.held_bindings.contains(b))
+    }
+
+    /// True on the frame the action went down.
+    pub fn was_pressed(&self, action: GameAction) -> bool {
+        self.pressed_actions.contains(&action)
+    }
+
+    /// Like `is_held` but only counting bindings on the given device. `None` counts every device.
+    pub fn is_held_on(&self, action: GameAction, device: Option<InputDevice>) -> bool {
+        self.bindings(action)
+            .iter()
+            .any(|b| (device.is_none() || device == Some(b.device())) && self.held_bindings.contains(b))
+    }
+
+    /// True on the frame one of the action's bindings on the given device went down. `None` counts every device.
+    pub fn was_pressed_on(&self, action: GameAction, device: Option<InputDevice>) -> bool {
+        match device {
+            None => self.was_pressed(action),
+            Some(device) => self
+                .bindings(action)
+                .iter()
+                .any(|b| b.device() == device && self.pressed_bindings.contains(b)),
+        }
+    }
+
+    /// Combined keyboard and gamepad movement, x is forward (move up) and y is right (move right).
+    /// With world fixed controls that is world +X and +Z.
+    pub fn move_vector(&self) -> Vec2 {
+        self.move_vector_on(None)
+    }
+
+    /// Movement from a single device, `None` combines every device.
+    pub fn move_vector_on(&self, device: Option<InputDevice>) -> Vec2 {
+        let mut move_vec = self.stick_move_on(device);
+        if self.is_held_on(GameAction::MoveUp, device) {
+            move_vec += vec2(1.0, 0.0);
+        }
+        if self.is_held_on(GameAction::MoveDown, device) {
+            move_vec += vec2(-1.0, 0.0);
+        }
+        if self.is_held_on(GameAction::MoveLeft, device) {
+            move_vec += vec2(0.0, -1.0);
+        }
+        if self.is_held_on(GameAction::MoveRight, device) {
+            move_vec += vec2(0.0, 1.0);
+        }
+        move_vec
+    }
+
+    /// Only the analog part of `move_vector_on`, leaving out the move actions.
+    pub fn stick_move_on(&self, device: Option<InputDevice>) -> Vec2 {
+        if device == Some(InputDevice::KeyboardMouse) {
+            return Vec2::ZERO;
+        }
+        self.gamepad_move
+    }
+
+    pub fn end_frame(&mut self) {
+        self.pressed_actions.clear();
+        self.pressed_bindings.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bindings_round_trip() {
+        let mut bindings = default_bindings();
+        bindings.insert(GameAction::Fire, vec![InputBinding::Key(Key::F), InputBinding::GamepadTrigger(GamepadAxis::AxisLeftTrigger)]);
+        bindings.insert(GameAction::Quit, vec![]);
+
+        let parsed = parse_bindings(&format_bindings(&bindings));
+
+        for (action, _) in ACTION_NAMES.iter() {
+            assert_eq!(parsed.get(action), bindings.get(action), "{:?}", action);
+        }
+    }
+
+    #[test]
+    fn test_pressed_only_on_first_binding() {
+        let mut input = Input::new(default_bindings());
+
+        input.handle_key(Key::W, Action::Press);
+        assert!(input.was_pressed(GameAction::MoveUp));
+        input.end_frame();
+
+        input.set_binding_state(InputBinding::GamepadButton(GamepadButton::ButtonDpadUp), true);
+        assert!(input.is_held(GameAction::MoveUp));
+        assert!(!input.was_pressed(GameAction::MoveUp));
+
+        input.handle_key(Key::W, Action::Release);
+        input.release_gamepad();
+        assert!(!input.is_held(GameAction::MoveUp));
+    }
+
+    #[test]
+    fn test_actions_per_device() {
+        let mut input = Input::new(default_bindings());
+
+        input.handle_key(Key::W, Action::Press);
+        input.set_binding_state(InputBinding::GamepadTrigger(GamepadAxis::AxisRightTrigger), true);
+
+        assert!(input.is_held_on(GameAction::MoveUp, Some(InputDevice::KeyboardMouse)));
+        assert!(!input.is_held_on(GameAction::MoveUp, Some(InputDevice::Gamepad)));
+        assert!(input.is_held_on(GameAction::Fire, Some(InputDevice::Gamepad)));
+        assert!(!input.is_held_on(GameAction::Fire, Some(InputDevice::KeyboardMouse)));
+        assert_eq!(input.move_vector_on(Some(InputDevice::Gamepad)), Vec2::ZERO);
+        assert_eq!(input.move_vector_on(None), vec2(1.0, 0.0));
+    }
+
+    #[test]
+    fn test_any_modifier_is_the_floating_modifier() {
+        let mut input = Input::new(default_bindings());
+
+        for key in [Key::LeftShift, Key::RightControl, Key::LeftAlt, Key::RightSuper] {
+            input.handle_key(key, Action::Press);
+            assert!(input.is_held(GameAction::FloatingModifier), "{:?}", key);
+            input.handle_key(key, Action::Release);
+        }
+        assert!(!input.is_held(GameAction::FloatingModifier));
+
+        input.gamepad_move = vec2(0.0, 0.5);
+        input.handle_key(Key::W, Action::Press);
+        assert_eq!(input.move_vector_on(None), vec2(1.0, 0.5));
+        assert_eq!(input.stick_move_on(None), vec2(0.0, 0.5));
+        assert_eq!(input.stick_move_on(Some(InputDevice::KeyboardMouse)), Vec2::ZERO);
+    }
+}
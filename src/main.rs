@@ -9,39 +9,65 @@
 // #![allow(clippy::assign_op_pattern)]
 
 mod aabb;
+mod animation_set;
+mod blend_space;
+mod boss;
 mod bullets;
 mod burn_marks;
 mod capsule;
 mod enemy;
+mod enemy_ai;
+mod enemy_animation;
+mod explosions;
 mod floor;
 mod framebuffers;
+mod game_state;
+mod gamepad;
 mod geom;
+mod input;
+mod mark_trail;
+mod model_definition;
 mod muzzle_flash;
+mod netplay;
+mod overlay;
 mod player;
+mod pose_layers;
 mod quads;
+mod render_graph;
+mod rng;
+mod sockets;
 mod sound_system;
+mod spatial_hash;
 mod sprite_sheet;
 mod texture_cache;
+mod thread_pool;
 
 extern crate glfw;
 
-use crate::bullets::BulletStore;
+use crate::bullets::{BulletBehaviour, BulletStore, Team};
 use crate::burn_marks::BurnMarks;
 use crate::enemy::{Enemy, EnemySystem};
+use crate::explosions::{apply_explosions, Explosions};
 use crate::floor::Floor;
-use crate::framebuffers::{
-    create_depth_map_fbo, create_emission_fbo, create_horizontal_blur_fbo, create_scene_fbo, create_vertical_blur_fbo, SHADOW_HEIGHT, SHADOW_WIDTH,
-};
+use crate::framebuffers::{DepthBuffer, Edge, TargetFormat, SHADOW_HEIGHT, SHADOW_WIDTH};
+use crate::game_state::{GameCommand, GameStateStack};
+use crate::gamepad::{queue_joystick_event, Gamepad, GamepadSettings};
+use crate::input::{GameAction, Input, InputDevice};
+use crate::mark_trail::{MarkTrail, DASH_TRAIL, FOOTSTEP_DUST};
 use crate::muzzle_flash::MuzzleFlash;
-use crate::player::Player;
+use crate::netplay::{state_checksum, NetMode, NetSession, NET_TICK_TIME};
+use crate::overlay::Overlay;
+use crate::player::{Player, PlayerCommand, FOOTSTEP};
 use crate::quads::{create_more_obnoxious_quad_vao, create_obnoxious_quad_vao, create_unit_square_vao, render_quad};
-use glam::{vec2, vec3, vec4, Mat4, Vec3};
-use glfw::JoystickId::Joystick1;
-use glfw::{Action, Context, Key, MouseButton};
+use crate::render_graph::{Clear, PassDesc, RenderGraph, TargetDesc, TargetSize};
+use crate::rng::SimRng;
+use crate::thread_pool::ThreadPool;
+use glam::{vec2, vec3, vec4, Mat4, Vec2, Vec3, Vec4};
+use glfw::Context;
 use log::error;
 use small_gl_core::camera::{Camera, CameraMovement};
 use small_gl_core::gl;
-use small_gl_core::gl::{GLsizei, GLuint};
+use small_gl_core::gl::GLuint;
 use small_gl_core::math::{get_world_ray_from_mouse, ray_plane_intersection};
 use small_gl_core::shader::Shader;
 use std::cell::RefCell;
@@ -49,13 +75,12 @@ use std::f32::consts::PI;
 use std::rc::Rc;
 // use std::thread::sleep;
 use crate::sound_system::SoundSystem;
-use small_gl_core::hash_map::HashSet;
 
 extern crate pretty_env_logger;
 #[macro_use]
 extern crate log;
 
-const PARALLELISM: i32 = 4;
+const PARALLELISM: usize = 4; // worker threads for the bullet and enemy updates
 
 // Viewport
 const VIEW_PORT_WIDTH: i32 = 1500;
@@ -63,6 +88,10 @@ const VIEW_PORT_HEIGHT: i32 = 1000;
 // const VIEW_PORT_WIDTH: i32 = 800;
 // const VIEW_PORT_HEIGHT: i32 = 500;
 
+// Controls
+const CONTROLS_CONFIG_PATH: &str = "controls.cfg";
+const CAMERA_RELATIVE_MOVEMENT: bool = false;
+
 // Player
 const FIRE_INTERVAL: f32 = 0.1;
 // seconds
@@ -70,6 +99,18 @@ const SPREAD_AMOUNT: i32 = 20;
 
 const PLAYER_COLLISION_RADIUS: f32 = 0.35;
 
+// no pierce or ricochet, a weapon that wants them asks for its own behaviour
+const PLAYER_BULLET: BulletBehaviour = BulletBehaviour::new(0, 0);
+
+// Local co-op
+const MAX_LOCAL_PLAYERS: usize = 2;
+const JOIN_OFFSET: Vec3 = vec3(0.0, 0.0, 1.0);
+const COOP_FRAMING_DISTANCE: f32 = 4.0; // player spread at which the camera has pulled back to twice its distance
+const COOP_MAX_ZOOM_OUT: f32 = 2.5;
+
+// Network co-op
+const MAX_NET_TICKS_BEHIND: f32 = 4.0; // ticks of real time to catch up on before dropping the rest
+
 // Models
 const PLAYER_MODEL_SCALE: f32 = 0.0044;
 //const PLAYER_MODEL_GUN_HEIGHT: f32 = 120.0; // un-scaled
@@ -84,6 +125,8 @@ const LIGHT_FACTOR: f32 = 0.8;
 const NON_BLUE: f32 = 0.9;
 
 const BLUR_SCALE: i32 = 2;
+const DEBUG_VIEW: bool = false; // show one target full screen over the frame
+const DEBUG_VIEW_TARGET: &str = "emission";
 
 const FLOOR_LIGHT_FACTOR: f32 = 0.35;
 const FLOOR_NON_BLUE: f32 = 0.7;
@@ -91,6 +134,17 @@ const FLOOR_NON_BLUE: f32 = 0.7;
 // Enemies
 const MONSTER_SPEED: f32 = 0.6;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FramePass {
+    Shadow,
+    Emission,
+    Scene,
+    HorizontalBlur,
+    VerticalBlur,
+    Merge,
+    DebugView,
+}
+
 enum CameraType {
     Game,
     Floating,
@@ -98,6 +152,46 @@ enum CameraType {
     Side,
 }
 
+// A player in the game with the weapon that goes with them, controlled from this machine or over the network.
+struct PlayerSlot {
+    player: Rc<RefCell<Player>>,
+    /// The device this player listens to, `None` while they are the only player at this machine so every device works.
+    device: Option<InputDevice>,
+    is_remote: bool,
+    /// What the player does on the current tick.
+    command: PlayerCommand,
+    bullet_store: BulletStore,
+    muzzle_flash: MuzzleFlash,
+    aim: Vec2,
+    aim_theta: f32,
+    transform: Mat4,
+    /// Turns the model to face the way the legs do, the upper body twists on round to the aim.
+    body_rot: Mat4,
+    muzzle_transform: Mat4,
+}
+
+impl PlayerSlot {
+    fn new(player: Rc<RefCell<Player>>, device: Option<InputDevice>, unit_square_vao: i32) -> Self {
+        Self {
+            player,
+            device,
+            is_remote: false,
+            command: PlayerCommand::default(),
+            bullet_store: BulletStore::new(unit_square_vao, Team::Player),
+            muzzle_flash: MuzzleFlash::new(unit_square_vao),
+            aim: vec2(0.0, 1.0), // matches an aim_theta of zero
+            aim_theta: 0.0,
+            transform: Mat4::IDENTITY,
+            body_rot: Mat4::IDENTITY,
+            muzzle_transform: Mat4::IDENTITY,
+        }
+    }
+
+    fn uses(&self, device: InputDevice) -> bool {
+        self.device.is_none() || self.device == Some(device)
+    }
+}
+
 struct State {
     run: bool,
     viewport_width: i32,
@@ -105,7 +199,10 @@ struct State {
     scaled_width: i32,
     scaled_height: i32,
     window_scale: (f32, f32),
-    key_presses: HashSet<Key>,
+    input: Input,
+    camera_relative_movement: bool,
+    camera_forward: Vec3,
+    game_view: Mat4,
     game_camera: Camera,
     floating_camera: Camera,
     ortho_camera: Camera,
@@ -118,10 +215,14 @@ struct State {
     first_mouse: bool,
     mouse_x: f32,
     mouse_y: f32,
-    player: Rc<RefCell<Player>>,
+    players: Vec<Rc<RefCell<Player>>>,
     enemies: Vec<Enemy>,
+    kill_count: u32,
+    rng: SimRng,
     burn_marks: BurnMarks,
+    explosions: Explosions,
     sound_system: SoundSystem,
+    thread_pool: ThreadPool,
 }
 
 fn error_callback(err: glfw::Error, description: String) {
@@ -130,6 +231,7 @@ fn error_callback(err: glfw::Error, description: String) {
 
 fn joystick_callback(jid: glfw::JoystickId, event: glfw::JoystickEvent) {
     info!("joystick: {:?}  event: {:?}", jid, event);
+    queue_joystick_event(jid, event);
 }
 
 #[allow(clippy::cognitive_complexity)]
@@ -159,12 +261,7 @@ fn main() {
 
     glfw.set_joystick_callback(joystick_callback);
 
-    let joy = glfw.get_joystick(Joystick1);
-
-    if joy.is_present() {
-        let axes = joy.get_axes();
-        info!("axes: {:?}", axes)
-    }
+    let mut gamepad = Gamepad::new(&glfw, GamepadSettings::default());
 
     info!("Loading assets");
 
@@ -187,6 +284,7 @@ fn main() {
 
     // for debug
     let basicer_shader = Shader::new("shaders/basicer_shader.vert", "shaders/basicer_shader.frag").unwrap();
+    let health_bar_shader = Shader::new("shaders/redshader.vert", "shaders/redshader.frag").unwrap();
     let _depth_shader = Shader::new("shaders/depth_shader.vert", "shaders/depth_shader.frag").unwrap();
     let _debug_depth_shader = Shader::new("shaders/debug_depth_quad.vert", "shaders/debug_depth_quad.frag").unwrap();
 
@@ -217,11 +315,10 @@ fn main() {
 
     // -- Framebuffers ---
 
-    let depth_map_fbo = create_depth_map_fbo();
-    let mut emissions_fbo = create_emission_fbo(viewport_width, viewport_height);
-    let mut scene_fbo = create_scene_fbo(viewport_width, viewport_height);
-    let mut horizontal_blur_fbo = create_horizontal_blur_fbo(viewport_width, viewport_height);
-    let mut vertical_blur_fbo = create_vertical_blur_fbo(viewport_width, viewport_height);
+    let use_framebuffers = true;
+
+    let mut render_graph = frame_graph(use_framebuffers);
+    render_graph.resize(viewport_width, viewport_height);
 
     // --- quads ---
 
@@ -263,9 +360,31 @@ fn main() {
 
     let player = Rc::new(RefCell::new(Player::new()));
     let floor = Floor::new();
-    let mut enemies = EnemySystem::new();
-    let mut muzzle_flash = MuzzleFlash::new(unit_square_quad);
-    let mut bullet_store = BulletStore::new(unit_square_quad);
+    let mut enemies = EnemySystem::new(unit_square_quad);
+    let mut dash_trail = MarkTrail::new(unit_square_quad, &DASH_TRAIL);
+    let mut footstep_dust = MarkTrail::new(unit_square_quad, &FOOTSTEP_DUST);
+    let mut overlay = Overlay::new(unit_square_quad);
+    let mut game_states = GameStateStack::new();
+
+    // the second player joins from the gamepad, or plays from another machine
+    let mut player_slots = vec![PlayerSlot::new(player.clone(), None, unit_square_quad)];
+
+    let mut net_session = match NetMode::from_args(std::env::args()) {
+        NetMode::Local => None,
+        NetMode::Host(port) => Some(NetSession::host(port, SimRng::from_time().next_u64()).expect("could not open the host port")),
+        NetMode::Join(address) => Some(NetSession::join(address).expect("could not open a network socket")),
+    };
+
+    if let Some(session) = &net_session {
+        let second_player = Rc::new(RefCell::new(Player::new()));
+        second_player.borrow_mut().position = player.borrow().position + JOIN_OFFSET;
+        player_slots.push(PlayerSlot::new(second_player, None, unit_square_quad));
+
+        // the host plays the first player and the client the second
+        for (index, slot) in player_slots.iter_mut().enumerate() {
+            slot.is_remote = index != session.local_player();
+        }
+    }
 
     // the state
 
@@ -276,7 +395,10 @@ fn main() {
         scaled_width,
         scaled_height,
         window_scale,
-        key_presses: HashSet::new(),
+        input: Input::from_config(CONTROLS_CONFIG_PATH),
+        camera_relative_movement: CAMERA_RELATIVE_MOVEMENT,
+        camera_forward: vec3(1.0, 0.0, 0.0),
+        game_view: Mat4::IDENTITY,
         game_camera,
         floating_camera,
         ortho_camera,
@@ -289,32 +411,28 @@ fn main() {
         first_mouse: true,
         mouse_x: scaled_width as f32 / 2.0,
         mouse_y: scaled_height as f32 / 2.0,
-        player: player.clone(),
+        players: player_slots.iter().map(|slot| slot.player.clone()).collect(),
         enemies: vec![],
+        kill_count: 0,
+        rng: SimRng::from_time(),
         burn_marks: BurnMarks::new(unit_square_quad),
+        explosions: Explosions::new(unit_square_quad),
         sound_system: SoundSystem::new(),
+        thread_pool: ThreadPool::new(PARALLELISM),
     };
 
     // Set fixed shader uniforms
 
-    let shadow_texture_unit = 10;
-
     player_shader.use_shader();
     player_shader.set_vec3("directionLight.dir", &player_light_dir);
     player_shader.set_vec3("directionLight.color", &light_color);
     player_shader.set_vec3("ambient", &ambient_color);
 
-    player_shader.set_int("shadow_map", shadow_texture_unit as i32);
-    player_shader.set_texture_unit(shadow_texture_unit, depth_map_fbo.texture_id);
-
     floor_shader.use_shader();
     floor_shader.set_vec3("directionLight.dir", &light_dir);
     floor_shader.set_vec3("directionLight.color", &floor_light_color);
     floor_shader.set_vec3("ambient", &floor_ambient_color);
 
-    floor_shader.set_int("shadow_map", shadow_texture_unit as i32);
-    floor_shader.set_texture_unit(shadow_texture_unit, depth_map_fbo.texture_id);
-
     wiggly_shader.use_shader();
     wiggly_shader.set_vec3("directionLight.dir", &player_light_dir);
     wiggly_shader.set_vec3("directionLight.color", &light_color);
@@ -322,42 +440,92 @@ fn main() {
 
     // --------------------------------
 
-    let use_framebuffers = true;
-
     let mut buffer_ready = false;
-    let mut aim_theta = 0.0f32;
     let mut quad_vao: GLuint = 0;
 
-    let emission_texture_unit = 0;
-    let horizontal_texture_unit = 1;
-    let vertical_texture_unit = 2;
-    let scene_texture_unit = 3;
-
     let clock = quanta::Clock::new();
 
+    let mut last_frame_time = 0.0f32;
+    let mut net_time_behind = 0.0f32;
+    let mut net_command = PlayerCommand::default();
+
     info!("Assets loaded. Starting loop.");
 
     while !window.should_close() {
         let frame_start = clock.now();
 
         let current_time = glfw.get_time() as f32;
-        if state.run {
-            state.delta_time = current_time - state.frame_time;
-        } else {
-            state.delta_time = 0.0;
-        }
-        state.frame_time = current_time;
+        let frame_delta_time = current_time - last_frame_time;
+        last_frame_time = current_time;
 
         glfw.poll_events();
         for (_, event) in glfw::flush_messages(&events) {
             handle_window_event(&mut window, event, &mut state);
         }
 
-        if joy.is_present() {
-            let axes = joy.get_axes();
-            info!("axes: {:?}", axes)
+        gamepad.update(&mut state.input, frame_delta_time);
+        handle_input_actions(&mut window, &mut state);
+
+        match game_states.handle_input(&mut state) {
+            Some(GameCommand::Restart) if net_session.is_some() => warn!("restart is not available in a network game"),
+            Some(GameCommand::Restart) => {
+                restart_game(&mut state, &mut player_slots, &mut enemies, &mut dash_trail, &mut footstep_dust);
+                game_states.reset();
+            }
+            Some(GameCommand::Quit) => window.set_should_close(true),
+            None => {}
+        }
+        state.run = game_states.is_playing();
+
+        if net_session.is_none() {
+            handle_join(&mut state, &mut player_slots, unit_square_quad);
+        }
+
+        for slot in player_slots.iter_mut().filter(|slot| !slot.is_remote) {
+            let command = read_player_command(&state, slot, buffer_ready);
+            if net_session.is_some() {
+                net_command.merge(&command);
+            } else {
+                slot.command = command;
+            }
+        }
+        state.input.end_frame();
+
+        // A local game steps every frame. A network game steps in fixed ticks, and only once the commands
+        // for the tick have arrived from the other player.
+        let mut net_tick = None;
+
+        if let Some(session) = net_session.as_mut() {
+            session.poll();
+
+            if state.run {
+                net_time_behind = (net_time_behind + frame_delta_time).min(MAX_NET_TICKS_BEHIND * NET_TICK_TIME);
+            }
+
+            if state.run && net_time_behind >= NET_TICK_TIME {
+                if let Some(tick_commands) = session.next_tick(&net_command) {
+                    if tick_commands.tick == 0 {
+                        state.rng = SimRng::new(session.seed().unwrap_or_default());
+                    }
+                    for (slot, command) in player_slots.iter_mut().zip(tick_commands.commands) {
+                        slot.command = command;
+                    }
+                    net_command = PlayerCommand::default();
+                    net_time_behind -= NET_TICK_TIME;
+                    net_tick = Some(tick_commands.tick);
+                }
+            }
+
+            state.frame_time = net_tick.map_or(state.frame_time, |tick| tick as f32 * NET_TICK_TIME);
+            state.delta_time = if net_tick.is_some() { NET_TICK_TIME } else { 0.0 };
+        } else {
+            state.delta_time = if state.run { frame_delta_time } else { 0.0 };
+            // simulation time, like the network tick time, so dash timers and animations stand still while paused
+            state.frame_time += state.delta_time;
         }
 
+        let simulate = state.run && (net_session.is_none() || net_tick.is_some());
+
         unsafe {
             gl::ClearColor(0.0, 0.02, 0.25, 1.0);
             gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
@@ -370,12 +538,7 @@ fn main() {
             scaled_width = state.scaled_width;
             scaled_height = state.scaled_height;
 
-            if use_framebuffers {
-                emissions_fbo = create_emission_fbo(viewport_width, viewport_height);
-                scene_fbo = create_scene_fbo(viewport_width, viewport_height);
-                horizontal_blur_fbo = create_horizontal_blur_fbo(viewport_width, viewport_height);
-                vertical_blur_fbo = create_vertical_blur_fbo(viewport_width, viewport_height);
-            }
+            render_graph.resize(viewport_width, viewport_height);
 
             info!(
                 "view port size: {}, {}  scaled size: {}, {}",
@@ -383,208 +546,302 @@ fn main() {
             );
         }
 
-        state.game_camera.position = player.borrow().position + camera_follow_vec;
-        let game_view = Mat4::look_at_rh(state.game_camera.position, player.borrow().position, state.game_camera.up);
+        if simulate {
+            for slot in player_slots.iter() {
+                let mut player = slot.player.borrow_mut();
+                player.is_trying_to_fire = slot.command.fire;
+
+                if slot.command.dash {
+                    player.try_dash(state.frame_time);
+                }
+
+                player.update_movement(slot.command.move_vec, state.delta_time);
+                player.update_dash(state.delta_time);
+
+                if player.is_dashing() {
+                    dash_trail.add_mark(player.position);
+                }
+            }
+            dash_trail.update(state.delta_time);
+        }
+
+        // frame all the players, pulling the camera back as they spread out
+        let (camera_target, player_spread) = players_center(&state);
+        let camera_zoom_out = (1.0 + player_spread / COOP_FRAMING_DISTANCE).min(COOP_MAX_ZOOM_OUT);
+
+        state.game_camera.position = camera_target + camera_follow_vec * camera_zoom_out;
+        let game_view = Mat4::look_at_rh(state.game_camera.position, camera_target, state.game_camera.up);
+        state.game_view = game_view;
 
         let (projection, camera_view) = match state.active_camera {
             CameraType::Game => (state.game_projection, game_view),
             CameraType::Floating => {
-                let view = Mat4::look_at_rh(state.floating_camera.position, player.borrow().position, state.floating_camera.up);
+                let view = Mat4::look_at_rh(state.floating_camera.position, camera_target, state.floating_camera.up);
                 (state.floating_projection, view)
             }
             CameraType::TopDown => {
-                let view = Mat4::look_at_rh(
-                    vec3(player.borrow().position.x, 1.0, player.borrow().position.z),
-                    player.borrow().position,
-                    vec3(0.0, 0.0, -1.0),
-                );
+                let view = Mat4::look_at_rh(vec3(camera_target.x, 1.0, camera_target.z), camera_target, vec3(0.0, 0.0, -1.0));
                 (state.orthographic_projection, view)
             }
             CameraType::Side => {
-                let view = Mat4::look_at_rh(vec3(0.0, 0.0, -3.0), player.borrow().position, vec3(0.0, 1.0, 0.0));
+                let view = Mat4::look_at_rh(vec3(0.0, 0.0, -3.0), camera_target, vec3(0.0, 1.0, 0.0));
                 (state.orthographic_projection, view)
             }
         };
 
         let projection_view = projection * camera_view;
+        state.camera_forward = camera_forward_on_floor(&camera_view);
 
-        let mut dx: f32 = 0.0;
-        let mut dz: f32 = 0.0;
-
-        if player.borrow().is_alive && buffer_ready {
-            let world_ray = get_world_ray_from_mouse(
-                state.mouse_x,
-                state.mouse_y,
-                state.scaled_width as f32,
-                state.scaled_height as f32,
-                &game_view,
-                &state.game_projection,
-            );
+        for slot in player_slots.iter_mut() {
+            if simulate {
+                if let Some(aim) = slot.command.aim {
+                    slot.aim = aim;
+                    slot.aim_theta = (aim.x / aim.y).atan() + if aim.y < 0.0 { PI } else { 0.0 };
+                }
+            }
 
-            let xz_plane_point = vec3(0.0, 0.0, 0.0);
-            let xz_plane_normal = vec3(0.0, 1.0, 0.0);
+            slot.body_rot = Mat4::from_axis_angle(vec3(0.0, 1.0, 0.0), slot.player.borrow().body_theta);
 
-            let world_point = ray_plane_intersection(state.game_camera.position, world_ray, xz_plane_point, xz_plane_normal).unwrap();
+            let mut player_transform = Mat4::from_translation(slot.player.borrow().position);
+            player_transform *= Mat4::from_scale(Vec3::splat(PLAYER_MODEL_SCALE));
+            player_transform *= slot.body_rot;
+
+            slot.transform = player_transform;
+            slot.muzzle_transform = slot.player.borrow().get_muzzle_position(&player_transform);
+
+            if !simulate {
+                continue;
+            }
 
-            dx = world_point.x - player.borrow().position.x;
-            dz = world_point.z - player.borrow().position.z;
-            aim_theta = (dx / dz).atan() + if dz < 0.0 { PI } else { 0.0 };
+            let mut player = slot.player.borrow_mut();
+
+            if player.is_alive && player.is_trying_to_fire && (player.last_fire_time + FIRE_INTERVAL) < state.frame_time {
+                slot.bullet_store.create_bullets(
+                    slot.aim.x,
+                    slot.aim.y,
+                    &slot.muzzle_transform,
+                    SPREAD_AMOUNT,
+                    PLAYER_BULLET,
+                    &mut state.rng,
+                    &state.thread_pool,
+                );
+                player.last_fire_time = state.frame_time;
+                slot.muzzle_flash.add_flash();
 
-            if state.mouse_x.abs() < 0.005 && state.mouse_y.abs() < 0.005 {
-                aim_theta = 0.0;
+                state.sound_system.play_player_shooting();
             }
         }
 
-        let aim_rot = Mat4::from_axis_angle(vec3(0.0, 1.0, 0.0), aim_theta);
+        for slot in player_slots.iter_mut() {
+            slot.muzzle_flash.update(state.delta_time);
+        }
 
-        let mut player_transform = Mat4::from_translation(player.borrow().position);
-        player_transform *= Mat4::from_scale(Vec3::splat(PLAYER_MODEL_SCALE));
-        player_transform *= aim_rot;
+        if simulate {
+            for slot in player_slots.iter_mut() {
+                slot.bullet_store.update_bullets(&mut state);
+            }
+            enemies.projectiles.update_bullets(&mut state);
 
-        let muzzle_transform = player.borrow().get_muzzle_position(&player_transform);
+            if state.players.iter().any(|p| p.borrow().is_alive) {
+                enemies.update(&mut state);
+                enemies.update_enemies(&mut state);
+                enemies.resolve_contacts(&mut state);
+            }
 
-        if player.borrow().is_alive && player.borrow().is_trying_to_fire && (player.borrow().last_fire_time + FIRE_INTERVAL) < state.frame_time {
-            bullet_store.create_bullets(dx, dz, &muzzle_transform, SPREAD_AMOUNT);
-            player.borrow_mut().last_fire_time = state.frame_time;
-            muzzle_flash.add_flash();
+            apply_explosions(&mut state);
+            state.explosions.update(state.delta_time);
 
-            state.sound_system.play_player_shooting();
-        }
+            // Update Players
+            for slot in player_slots.iter() {
+                let mut player = slot.player.borrow_mut();
+                player.update(&state, slot.aim_theta);
+
+                for event in player.animation_events.iter() {
+                    if event.as_ref() == FOOTSTEP {
+                        state.sound_system.play_footstep();
+                        footstep_dust.add_mark(player.position);
+                    }
+                }
+            }
+            footstep_dust.update(state.delta_time);
 
-        muzzle_flash.update(state.delta_time);
-        bullet_store.update_bullets(&mut state);
+            if let (Some(session), Some(tick)) = (net_session.as_mut(), net_tick) {
+                session.record_checksum(tick, state_checksum(&state));
+            }
 
-        if player.borrow().is_alive {
-            enemies.update(&mut state);
-            enemies.chase_player(&mut state);
+            game_states.update(&state);
         }
 
-        // Update Player
-        player.borrow_mut().update(&state, aim_theta);
-
         let mut use_point_light = false;
-        let mut muzzle_world_position = Vec3::default();
+        let mut point_light_position = Vec3::default();
+        let mut point_light_color = muzzle_point_light_color;
 
-        if !muzzle_flash.muzzle_flash_sprites_age.is_empty() {
-            let min_age = muzzle_flash.get_min_age();
-            let muzzle_world_position_vec4 = muzzle_transform * vec4(0.0, 0.0, 0.0, 1.0);
+        // the most recent muzzle flash lights the scene
+        let mut min_flash_age = f32::MAX;
+        for slot in player_slots.iter() {
+            if slot.muzzle_flash.muzzle_flash_sprites_age.is_empty() {
+                continue;
+            }
+            let min_age = slot.muzzle_flash.get_min_age();
+            if min_age < min_flash_age {
+                min_flash_age = min_age;
+                let muzzle_world_position_vec4 = slot.muzzle_transform * vec4(0.0, 0.0, 0.0, 1.0);
+
+                point_light_position = vec3(
+                    muzzle_world_position_vec4.x / muzzle_world_position_vec4.w,
+                    muzzle_world_position_vec4.y / muzzle_world_position_vec4.w,
+                    muzzle_world_position_vec4.z / muzzle_world_position_vec4.w,
+                );
 
-            muzzle_world_position = vec3(
-                muzzle_world_position_vec4.x / muzzle_world_position_vec4.w,
-                muzzle_world_position_vec4.y / muzzle_world_position_vec4.w,
-                muzzle_world_position_vec4.z / muzzle_world_position_vec4.w,
-            );
+                use_point_light = min_age < 0.03;
+            }
+        }
 
-            use_point_light = min_age < 0.03;
+        // an explosion outshines the muzzle flash
+        if let Some((light_position, light_color)) = state.explosions.get_light() {
+            use_point_light = true;
+            point_light_position = light_position;
+            point_light_color = light_color;
         }
 
         let near_plane: f32 = 1.0;
         let far_plane: f32 = 50.0;
         let ortho_size: f32 = 10.0;
-        let player_position = player.borrow().position;
 
         let light_projection = Mat4::orthographic_rh_gl(-ortho_size, ortho_size, -ortho_size, ortho_size, near_plane, far_plane);
-        let light_view = Mat4::look_at_rh(player_position - 20.0 * player_light_dir, player_position, vec3(0.0, 1.0, 0.0));
+        let light_view = Mat4::look_at_rh(camera_target - 20.0 * player_light_dir, camera_target, vec3(0.0, 1.0, 0.0));
         let light_space_matrix = light_projection * light_view;
 
         player_shader.use_shader();
         player_shader.set_mat4("projectionView", &projection_view);
-        player_shader.set_mat4("model", &player_transform);
-        player_shader.set_mat4("aimRot", &aim_rot);
         player_shader.set_vec3("viewPos", &state.game_camera.position);
         player_shader.set_mat4("lightSpaceMatrix", &light_space_matrix);
         player_shader.set_bool("usePointLight", use_point_light);
-        player_shader.set_vec3("pointLight.color", &muzzle_point_light_color);
-        player_shader.set_vec3("pointLight.worldPos", &muzzle_world_position);
+        player_shader.set_vec3("pointLight.color", &point_light_color);
+        player_shader.set_vec3("pointLight.worldPos", &point_light_position);
 
         floor_shader.use_shader();
         floor_shader.set_vec3("viewPos", &state.game_camera.position);
         floor_shader.set_mat4("lightSpaceMatrix", &light_space_matrix);
         floor_shader.set_bool("usePointLight", use_point_light);
-        floor_shader.set_vec3("pointLight.color", &muzzle_point_light_color);
-        floor_shader.set_vec3("pointLight.worldPos", &muzzle_world_position);
-
-        // shadows start - render to depth fbo
-
-        unsafe {
-            gl::BindFramebuffer(gl::FRAMEBUFFER, depth_map_fbo.framebuffer_id);
-            gl::Viewport(0, 0, SHADOW_WIDTH, SHADOW_HEIGHT);
-            gl::Clear(gl::DEPTH_BUFFER_BIT);
-        }
+        floor_shader.set_vec3("pointLight.color", &point_light_color);
+        floor_shader.set_vec3("pointLight.worldPos", &point_light_position);
 
-        player_shader.use_shader();
-        player_shader.set_bool("depth_mode", true);
-        player_shader.set_bool("useLight", false);
-
-        player.borrow_mut().render(&player_shader);
+        enemies.pose_enemies(&state);
 
-        wiggly_shader.use_shader();
-        wiggly_shader.set_mat4("projectionView", &projection_view);
-        wiggly_shader.set_mat4("lightSpaceMatrix", &light_space_matrix);
-        wiggly_shader.set_bool("depth_mode", true);
+        for pass in render_graph.passes() {
+            let inputs = render_graph.begin_pass(pass);
 
-        enemies.draw_enemies(&wiggly_shader, &mut state);
+            match pass {
+                FramePass::Shadow => {
+                    player_shader.use_shader();
+                    player_shader.set_bool("depth_mode", true);
+                    player_shader.set_bool("useLight", false);
 
-        // shadows end
+                    for slot in player_slots.iter() {
+                        render_player(&player_shader, slot);
+                    }
 
-        if use_framebuffers {
-            // render to emission buffer
+                    wiggly_shader.use_shader();
+                    wiggly_shader.set_mat4("projectionView", &projection_view);
+                    wiggly_shader.set_mat4("lightSpaceMatrix", &light_space_matrix);
+                    wiggly_shader.set_bool("depth_mode", true);
 
-            unsafe {
-                gl::BindFramebuffer(gl::FRAMEBUFFER, emissions_fbo.framebuffer_id);
-                gl::Viewport(0, 0, viewport_width as GLsizei, viewport_height as GLsizei);
-                gl::ClearColor(0.0, 0.0, 0.0, 0.0);
-                gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
-            }
+                    enemies.draw_enemies(&wiggly_shader, &mut state);
+                }
+                FramePass::Emission => {
+                    player_emissive_shader.use_shader();
+                    player_emissive_shader.set_mat4("projectionView", &projection_view);
+
+                    for slot in player_slots.iter() {
+                        render_player(&player_emissive_shader, slot);
+                    }
+
+                    for slot in player_slots.iter() {
+                        slot.bullet_store.draw_bullets(&instanced_texture_shader, &projection_view);
+                    }
+                    enemies.projectiles.draw_bullets(&instanced_texture_shader, &projection_view);
+                }
+                FramePass::Scene => {
+                    let shadow_map_unit = inputs.unit("shadow_map");
+
+                    floor_shader.use_shader();
+                    floor_shader.set_int("shadow_map", shadow_map_unit);
+                    floor_shader.set_bool("useLight", true);
+                    floor_shader.set_bool("useSpec", true);
+
+                    floor.draw(&floor_shader, &projection_view);
+
+                    dash_trail.draw(&basic_texture_shader, &projection_view);
+                    footstep_dust.draw(&basic_texture_shader, &projection_view);
+
+                    player_shader.use_shader();
+                    player_shader.set_int("shadow_map", shadow_map_unit);
+                    player_shader.set_bool("useLight", true);
+                    player_shader.set_bool("useEmissive", true);
+                    player_shader.set_bool("depth_mode", false);
+
+                    for slot in player_slots.iter() {
+                        player_shader.use_shader();
+                        render_player(&player_shader, slot);
+
+                        slot.muzzle_flash.draw(&sprite_shader, &projection_view, &slot.muzzle_transform);
+                    }
+
+                    wiggly_shader.use_shader();
+                    wiggly_shader.set_bool("useLight", true);
+                    wiggly_shader.set_bool("useEmissive", false);
+                    wiggly_shader.set_bool("depth_mode", false);
+
+                    enemies.draw_enemies(&wiggly_shader, &mut state);
+
+                    state.burn_marks.draw_marks(&basic_texture_shader, &projection_view, state.delta_time);
+                    for slot in player_slots.iter() {
+                        slot.bullet_store.draw_bullet_impacts(&sprite_shader, &projection_view);
+                    }
+                    enemies.projectiles.draw_bullet_impacts(&sprite_shader, &projection_view);
+                    state.explosions.draw(&sprite_shader, &projection_view);
+                    enemies.draw_boss_health_bar(&health_bar_shader, &projection_view, &state);
+
+                    // without the emission pass the bullets are drawn with everything else
+                    if !render_graph.is_enabled(FramePass::Emission) {
+                        for slot in player_slots.iter() {
+                            slot.bullet_store.draw_bullets(&instanced_texture_shader, &projection_view);
+                        }
+                        enemies.projectiles.draw_bullets(&instanced_texture_shader, &projection_view);
+                    }
+                }
+                FramePass::HorizontalBlur | FramePass::VerticalBlur => {
+                    let image = if pass == FramePass::HorizontalBlur { "emission" } else { "horizontal_blur" };
 
-            player_emissive_shader.use_shader();
-            player_emissive_shader.set_mat4("projectionView", &projection_view);
-            player_emissive_shader.set_mat4("model", &player_transform);
-
-            player.borrow_mut().render(&player_emissive_shader);
-
-            // doesn't seem to do anything
-            // {
-            //     unsafe {
-            //         gl::ColorMask(gl::FALSE, gl::FALSE, gl::FALSE, gl::FALSE);
-            //     }
-            //
-            //     floor_shader.use_shader();
-            //     floor_shader.set_bool("usePointLight", true);
-            //     floor_shader.set_bool("useLight", true);
-            //     floor_shader.set_bool("useSpec", true);
-            //
-            //     // floor.draw(&floor_shader, &projection_view);
-            //
-            //     unsafe {
-            //         gl::ColorMask(gl::TRUE, gl::TRUE, gl::TRUE, gl::TRUE);
-            //     }
-            // }
-
-            bullet_store.draw_bullets(&instanced_texture_shader, &projection_view);
-
-            let debug_emission = false;
-            if debug_emission {
-                unsafe {
-                    let texture_unit = 0;
-                    gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
-
-                    gl::Viewport(0, 0, viewport_width as GLsizei, viewport_height as GLsizei);
-                    gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
-
-                    gl::ActiveTexture(gl::TEXTURE0 + texture_unit as u32);
-                    gl::BindTexture(gl::TEXTURE_2D, emissions_fbo.texture_id);
+                    blur_shader.use_shader();
+                    blur_shader.set_int("image", inputs.unit(image));
+                    blur_shader.set_bool("horizontal", pass == FramePass::HorizontalBlur);
 
+                    unsafe {
+                        gl::BindVertexArray(more_obnoxious_quad_vao as GLuint);
+                        gl::DrawArrays(gl::TRIANGLES, 0, 6);
+                    }
+                }
+                FramePass::Merge => {
+                    // combine the blur with the emission and scene for the final draw
+                    scene_draw_shader.use_shader();
+                    scene_draw_shader.set_int("base_texture", inputs.unit("scene"));
+                    scene_draw_shader.set_int("emission_texture", inputs.unit("vertical_blur"));
+                    scene_draw_shader.set_int("bright_texture", inputs.unit("emission"));
+
+                    unsafe {
+                        gl::BindVertexArray(more_obnoxious_quad_vao as GLuint);
+                        gl::DrawArrays(gl::TRIANGLES, 0, 6);
+                    }
+                }
+                FramePass::DebugView => {
                     basicer_shader.use_shader();
                     basicer_shader.set_bool("greyscale", false);
-                    basicer_shader.set_int("tex", texture_unit);
+                    basicer_shader.set_int("tex", inputs.unit(DEBUG_VIEW_TARGET));
 
                     render_quad(&mut quad_vao);
                 }
-
-                buffer_ready = true;
-                window.swap_buffers();
-                continue;
             }
         }
 
@@ -592,7 +849,7 @@ fn main() {
         // if debug_depth {
         //     unsafe {
         //         gl::ActiveTexture(gl::TEXTURE0);
-        //         gl::BindTexture(gl::TEXTURE_2D, depth_map_fbo.texture_id);
+        //         gl::BindTexture(gl::TEXTURE_2D, render_graph.texture_id("shadow_map").unwrap_or(0));
         //         gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
         //     }
         //     debug_depth_shader.use_shader();
@@ -601,246 +858,287 @@ fn main() {
         //     render_quad(&mut quad_vao);
         // }
 
-        // render to scene buffer for base texture
-        unsafe {
-            if use_framebuffers {
-                gl::BindFramebuffer(gl::FRAMEBUFFER, scene_fbo.framebuffer_id);
-                gl::Viewport(0, 0, viewport_width as GLsizei, viewport_height as GLsizei);
-                gl::ClearColor(0.0, 0.02, 0.25, 1.0);
-                gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
-            } else {
-                gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
-                gl::Viewport(0, 0, viewport_width as GLsizei, viewport_height as GLsizei);
-            }
+        if game_states.has_overlay() {
+            overlay.begin(viewport_width, viewport_height);
+            game_states.draw(&overlay, &state);
+            overlay.end();
         }
 
-        floor_shader.use_shader();
-        floor_shader.set_bool("useLight", true);
-        floor_shader.set_bool("useSpec", true);
-
-        floor.draw(&floor_shader, &projection_view);
-
-        player_shader.use_shader();
-        player_shader.set_bool("useLight", true);
-        player_shader.set_bool("useEmissive", true);
-        player_shader.set_bool("depth_mode", false);
-
-        player.borrow_mut().render(&player_shader);
-
-        muzzle_flash.draw(&sprite_shader, &projection_view, &muzzle_transform);
+        buffer_ready = true;
+        window.swap_buffers();
 
-        wiggly_shader.use_shader();
-        wiggly_shader.set_bool("useLight", true);
-        wiggly_shader.set_bool("useEmissive", false);
-        wiggly_shader.set_bool("depth_mode", false);
+        let elapsed = frame_start.elapsed();
+        let frames_per_second = 1000_u128 / elapsed.as_millis();
 
-        enemies.draw_enemies(&wiggly_shader, &mut state);
+        info!("frame completed. Elapsed: {:?}  Frames per second: {:?}", &elapsed, frames_per_second);
+    }
+}
 
-        state.burn_marks.draw_marks(&basic_texture_shader, &projection_view, state.delta_time);
-        bullet_store.draw_bullet_impacts(&sprite_shader, &projection_view);
+//
+// The frame's passes and the targets they draw, in the order they run. Without framebuffers the scene
+// draws straight to the screen.
+//
+fn frame_graph(use_framebuffers: bool) -> RenderGraph<FramePass> {
+    let color = |edge, depth| TargetFormat::Color { edge, depth };
+    let full_size = TargetSize::Viewport { divisor: 1 };
+    let blur_size = TargetSize::Viewport { divisor: BLUR_SCALE };
+
+    let targets = vec![
+        TargetDesc {
+            name: "shadow_map",
+            format: TargetFormat::Depth,
+            size: TargetSize::Fixed(SHADOW_WIDTH, SHADOW_HEIGHT),
+        },
+        TargetDesc {
+            name: "emission",
+            format: color(Edge::Black, DepthBuffer::Depth16),
+            size: full_size,
+        },
+        TargetDesc {
+            name: "scene",
+            format: color(Edge::Clamp, DepthBuffer::Depth24Stencil8),
+            size: full_size,
+        },
+        TargetDesc {
+            name: "horizontal_blur",
+            format: color(Edge::Clamp, DepthBuffer::None),
+            size: blur_size,
+        },
+        TargetDesc {
+            name: "vertical_blur",
+            format: color(Edge::Clamp, DepthBuffer::None),
+            size: blur_size,
+        },
+    ];
+
+    let scene_output = if use_framebuffers { Some("scene") } else { None };
+    let background = vec4(0.0, 0.02, 0.25, 1.0);
+
+    let passes = vec![
+        PassDesc::new(FramePass::Shadow, &[], Some("shadow_map"), Clear::Depth),
+        PassDesc::new(FramePass::Emission, &[], Some("emission"), Clear::ColorAndDepth(Vec4::ZERO)).enabled(use_framebuffers),
+        PassDesc::new(FramePass::Scene, &["shadow_map"], scene_output, Clear::ColorAndDepth(background)),
+        PassDesc::new(FramePass::HorizontalBlur, &["emission"], Some("horizontal_blur"), Clear::None).enabled(use_framebuffers),
+        PassDesc::new(FramePass::VerticalBlur, &["horizontal_blur"], Some("vertical_blur"), Clear::None).enabled(use_framebuffers),
+        PassDesc::new(FramePass::Merge, &["scene", "vertical_blur", "emission"], None, Clear::None).enabled(use_framebuffers),
+        PassDesc::new(FramePass::DebugView, &[DEBUG_VIEW_TARGET], None, Clear::ColorAndDepth(Vec4::ZERO)).enabled(DEBUG_VIEW),
+    ];
+
+    RenderGraph::new(targets, passes)
+}
 
-        if !use_framebuffers {
-            bullet_store.draw_bullets(&instanced_texture_shader, &projection_view);
+//
+// GLFW maps callbacks to events.
+//
+fn handle_window_event(window: &mut glfw::Window, event: glfw::WindowEvent, state: &mut State) {
+    // info!("WindowEvent: {:?}", &event);
+    match event {
+        glfw::WindowEvent::FramebufferSize(width, height) => {
+            framebuffer_size_event(window, state, width, height);
         }
+        glfw::WindowEvent::Key(key, _, action, _) => state.input.handle_key(key, action),
+        glfw::WindowEvent::MouseButton(button, action, _) => state.input.handle_mouse_button(button, action),
+        glfw::WindowEvent::CursorPos(xpos, ypos) => mouse_handler(state, xpos, ypos),
+        glfw::WindowEvent::Scroll(xoffset, ysoffset) => scroll_handler(state, xoffset, ysoffset),
+        _evt => {
+            // info!("WindowEvent: {:?}", _evt);
+        }
+    }
+}
 
-        if use_framebuffers {
-            // generated blur and combine with emission and scene for final draw to framebuffer 0
-            unsafe {
-                // gl::Disable(gl::DEPTH_TEST);
-
-                // view port for blur effect
-                gl::Viewport(0, 0, viewport_width / BLUR_SCALE, viewport_height / BLUR_SCALE);
+//
+// Gameplay reads the input actions, never keys or buttons directly.
+//
+fn handle_input_actions(window: &mut glfw::Window, state: &mut State) {
+    if state.input.was_pressed(GameAction::Quit) {
+        window.set_should_close(true);
+    }
+    if state.input.was_pressed(GameAction::ToggleCameraRelative) {
+        state.camera_relative_movement = !state.camera_relative_movement;
+    }
+    if state.input.was_pressed(GameAction::ResetViewport) {
+        let width = state.viewport_width;
+        let height = state.viewport_height;
+        set_view_port(state, width, height)
+    }
 
-                // Draw horizontal blur
-                gl::BindFramebuffer(gl::FRAMEBUFFER, horizontal_blur_fbo.framebuffer_id);
-                gl::BindVertexArray(more_obnoxious_quad_vao as GLuint);
+    if state.input.was_pressed(GameAction::CameraGame) {
+        state.active_camera = CameraType::Game;
+    }
+    if state.input.was_pressed(GameAction::CameraFloating) {
+        state.active_camera = CameraType::Floating;
+    }
+    if state.input.was_pressed(GameAction::CameraTopDown) {
+        state.active_camera = CameraType::TopDown;
+    }
+    if state.input.was_pressed(GameAction::CameraSide) {
+        state.active_camera = CameraType::Side;
+    }
+    if state.input.was_pressed(GameAction::CameraNext) {
+        state.active_camera = match state.active_camera {
+            CameraType::Game => CameraType::Floating,
+            CameraType::Floating => CameraType::TopDown,
+            CameraType::TopDown => CameraType::Side,
+            CameraType::Side => CameraType::Game,
+        };
+    }
 
-                gl::ActiveTexture(gl::TEXTURE0 + emission_texture_unit as u32);
-                gl::BindTexture(gl::TEXTURE_2D, emissions_fbo.texture_id as GLuint);
+    let floating_moves = [
+        (GameAction::FloatingForward, CameraMovement::Forward),
+        (GameAction::FloatingBackward, CameraMovement::Backward),
+        (GameAction::FloatingLeft, CameraMovement::Left),
+        (GameAction::FloatingRight, CameraMovement::Right),
+        (GameAction::FloatingUp, CameraMovement::Up),
+        (GameAction::FloatingDown, CameraMovement::Down),
+    ];
+    for (action, movement) in floating_moves {
+        if state.input.is_held(action) {
+            state.floating_camera.process_keyboard(movement, state.delta_time);
+        }
+    }
 
-                blur_shader.use_shader();
-                blur_shader.set_int("image", emission_texture_unit);
-                blur_shader.set_bool("horizontal", true);
+    if is_flying_floating_camera(state) {
+        let modified_moves = [
+            (GameAction::MoveUp, CameraMovement::Forward),
+            (GameAction::MoveDown, CameraMovement::Backward),
+            (GameAction::MoveLeft, CameraMovement::Left),
+            (GameAction::MoveRight, CameraMovement::Right),
+        ];
+        for (action, movement) in modified_moves {
+            if state.input.is_held(action) {
+                state.floating_camera.process_keyboard(movement, state.delta_time);
+            }
+        }
+    }
+}
 
-                gl::DrawArrays(gl::TRIANGLES, 0, 6);
+// What the player at this machine asks for this frame, from their input device.
+fn read_player_command(state: &State, slot: &PlayerSlot, buffer_ready: bool) -> PlayerCommand {
+    let mut command = PlayerCommand {
+        move_vec: player_move_vector(state, slot.device),
+        aim: None,
+        fire: state.input.is_held_on(GameAction::Fire, slot.device),
+        dash: state.input.was_pressed_on(GameAction::Dash, slot.device),
+    };
 
-                // Draw vertical blur
-                gl::BindFramebuffer(gl::FRAMEBUFFER, vertical_blur_fbo.framebuffer_id);
-                gl::BindVertexArray(more_obnoxious_quad_vao as GLuint);
+    let player = slot.player.borrow();
+    if !player.is_alive {
+        return command;
+    }
 
-                gl::ActiveTexture(gl::TEXTURE0 + horizontal_texture_unit as u32);
-                gl::BindTexture(gl::TEXTURE_2D, horizontal_blur_fbo.texture_id as GLuint);
+    if slot.uses(InputDevice::Gamepad) {
+        command.aim = state.input.gamepad_aim;
+    }
 
-                blur_shader.use_shader();
-                blur_shader.set_int("image", horizontal_texture_unit);
-                blur_shader.set_bool("horizontal", false);
+    // no cursor position yet while the mouse is still at the corner
+    let has_mouse_position = state.mouse_x.abs() >= 0.005 || state.mouse_y.abs() >= 0.005;
 
-                gl::DrawArrays(gl::TRIANGLES, 0, 6);
+    if command.aim.is_none() && buffer_ready && has_mouse_position && slot.uses(InputDevice::KeyboardMouse) {
+        let world_ray = get_world_ray_from_mouse(
+            state.mouse_x,
+            state.mouse_y,
+            state.scaled_width as f32,
+            state.scaled_height as f32,
+            &state.game_view,
+            &state.game_projection,
+        );
 
-                // view port for final draw combining everything
-                gl::Viewport(0, 0, viewport_width as GLsizei, viewport_height as GLsizei);
+        let xz_plane_point = vec3(0.0, 0.0, 0.0);
+        let xz_plane_normal = vec3(0.0, 1.0, 0.0);
 
-                gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
-                gl::BindVertexArray(more_obnoxious_quad_vao as GLuint);
+        let world_point = ray_plane_intersection(state.game_camera.position, world_ray, xz_plane_point, xz_plane_normal).unwrap();
 
-                gl::ActiveTexture(gl::TEXTURE0 + vertical_texture_unit as u32);
-                gl::BindTexture(gl::TEXTURE_2D, vertical_blur_fbo.texture_id as GLuint);
+        command.aim = Some(vec2(world_point.x - player.position.x, world_point.z - player.position.z));
+    }
 
-                gl::ActiveTexture(gl::TEXTURE0 + emission_texture_unit as u32);
-                gl::BindTexture(gl::TEXTURE_2D, emissions_fbo.texture_id as GLuint);
+    command
+}
 
-                gl::ActiveTexture(gl::TEXTURE0 + scene_texture_unit as u32);
-                gl::BindTexture(gl::TEXTURE_2D, scene_fbo.texture_id as GLuint);
+// A second player joins from the gamepad. From then on the first player only listens to the keyboard and mouse.
+fn handle_join(state: &mut State, player_slots: &mut Vec<PlayerSlot>, unit_square_vao: i32) {
+    if player_slots.len() >= MAX_LOCAL_PLAYERS || !state.input.was_pressed_on(GameAction::Join, Some(InputDevice::Gamepad)) {
+        return;
+    }
 
-                scene_draw_shader.use_shader();
-                scene_draw_shader.set_int("base_texture", scene_texture_unit);
-                scene_draw_shader.set_int("emission_texture", vertical_texture_unit);
-                scene_draw_shader.set_int("bright_texture", emission_texture_unit);
+    let player = Rc::new(RefCell::new(Player::new()));
+    player.borrow_mut().position = player_slots[0].player.borrow().position + JOIN_OFFSET;
 
-                gl::DrawArrays(gl::TRIANGLES, 0, 6);
+    player_slots[0].device = Some(InputDevice::KeyboardMouse);
+    player_slots.push(PlayerSlot::new(player.clone(), Some(InputDevice::Gamepad), unit_square_vao));
+    state.players.push(player);
 
-                // gl::Enable(gl::DEPTH_TEST);
-            }
+    info!("player {} joined", player_slots.len());
+}
 
-            let debug_blur = false;
-            if debug_blur {
-                unsafe {
-                    let texture_unit = 0;
-                    gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+// Starts a fresh game with the same players and loaded assets.
+fn restart_game(state: &mut State, player_slots: &mut [PlayerSlot], enemies: &mut EnemySystem, dash_trail: &mut MarkTrail, footstep_dust: &mut MarkTrail) {
+    for (index, slot) in player_slots.iter_mut().enumerate() {
+        slot.player.borrow_mut().respawn(JOIN_OFFSET * index as f32);
+        slot.command = PlayerCommand::default();
+        slot.bullet_store.clear();
+        slot.muzzle_flash.muzzle_flash_sprites_age.clear();
+    }
 
-                    gl::Viewport(0, 0, viewport_width as GLsizei, viewport_height as GLsizei);
-                    gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+    state.enemies.clear();
+    state.kill_count = 0;
+    state.burn_marks.clear();
+    state.explosions.clear();
+    enemies.reset();
+    dash_trail.clear();
+    footstep_dust.clear();
 
-                    gl::ActiveTexture(gl::TEXTURE0 + texture_unit as u32);
-                    gl::BindTexture(gl::TEXTURE_2D, scene_fbo.texture_id);
+    info!("game restarted");
+}
 
-                    basicer_shader.use_shader();
-                    basicer_shader.set_bool("greyscale", false);
-                    basicer_shader.set_int("tex", texture_unit);
+fn render_player(shader: &Shader, slot: &PlayerSlot) {
+    shader.set_mat4("model", &slot.transform);
+    shader.set_mat4("aimRot", &slot.body_rot);
+    slot.player.borrow_mut().render(shader);
+}
 
-                    render_quad(&mut quad_vao);
-                }
+// The middle of the living players, or of everyone once they are all dead, and how far out the furthest one is.
+fn players_center(state: &State) -> (Vec3, f32) {
+    let mut positions: Vec<Vec3> = state.players.iter().filter(|p| p.borrow().is_alive).map(|p| p.borrow().position).collect();
+    if positions.is_empty() {
+        positions = state.players.iter().map(|p| p.borrow().position).collect();
+    }
 
-                buffer_ready = true;
-                window.swap_buffers();
-                continue;
-            }
-        }
+    let center = positions.iter().sum::<Vec3>() / positions.len() as f32;
+    let spread = positions.iter().map(|p| p.distance(center)).fold(0.0, f32::max);
+    (center, spread)
+}
 
-        buffer_ready = true;
-        window.swap_buffers();
+// With the floating camera in view, holding the modifier hands the move keys over to flying it, so only the sticks
+// move the player.
+fn is_flying_floating_camera(state: &State) -> bool {
+    matches!(state.active_camera, CameraType::Floating) && state.input.is_held(GameAction::FloatingModifier)
+}
 
-        let elapsed = frame_start.elapsed();
-        let frames_per_second = 1000_u128 / elapsed.as_millis();
+// Movement input in world x/z. Either fixed to the world axes or relative to the active camera.
+fn player_move_vector(state: &State, device: Option<InputDevice>) -> Vec3 {
+    let move_vec = if is_flying_floating_camera(state) {
+        state.input.stick_move_on(device)
+    } else {
+        state.input.move_vector_on(device)
+    };
 
-        info!("frame completed. Elapsed: {:?}  Frames per second: {:?}", &elapsed, frames_per_second);
+    if state.camera_relative_movement && state.camera_forward.length_squared() > 0.0 {
+        let right = state.camera_forward.cross(Vec3::Y);
+        return state.camera_forward * move_vec.x + right * move_vec.y;
     }
-}
 
-//
-// GLFW maps callbacks to events.
-//
-fn handle_window_event(window: &mut glfw::Window, event: glfw::WindowEvent, state: &mut State) {
-    // info!("WindowEvent: {:?}", &event);
-    match event {
-        glfw::WindowEvent::Key(Key::Escape, _, Action::Press, _) => window.set_should_close(true),
-        glfw::WindowEvent::FramebufferSize(width, height) => {
-            framebuffer_size_event(window, state, width, height);
-        }
-        glfw::WindowEvent::Key(Key::Num1, _, _, _) => {
-            state.active_camera = CameraType::Game;
-        }
-        glfw::WindowEvent::Key(Key::Num2, _, _, _) => {
-            state.active_camera = CameraType::Floating;
-        }
-        glfw::WindowEvent::Key(Key::Num3, _, _, _) => {
-            state.active_camera = CameraType::TopDown;
-        }
-        glfw::WindowEvent::Key(Key::Num4, _, _, _) => {
-            state.active_camera = CameraType::Side;
-        }
-        glfw::WindowEvent::Key(Key::Space, _, Action::Press, _) => {
-            state.run = !state.run;
-        }
-        glfw::WindowEvent::Key(Key::T, _, Action::Press, _) => {
-            let width = state.viewport_width;
-            let height = state.viewport_height;
-            set_view_port(state, width, height)
-        }
-        glfw::WindowEvent::Key(Key::W, _, action, modifier) => {
-            if modifier.is_empty() {
-                handle_key_press(state, action, Key::W);
-            } else {
-                state.floating_camera.process_keyboard(CameraMovement::Forward, state.delta_time);
-            }
-        }
-        glfw::WindowEvent::Key(Key::S, _, action, modifier) => {
-            if modifier.is_empty() {
-                handle_key_press(state, action, Key::S);
-            } else {
-                state.floating_camera.process_keyboard(CameraMovement::Backward, state.delta_time);
-            }
-        }
-        glfw::WindowEvent::Key(Key::A, _, action, modifier) => {
-            if modifier.is_empty() {
-                handle_key_press(state, action, Key::A);
-            } else {
-                state.floating_camera.process_keyboard(CameraMovement::Left, state.delta_time);
-            }
-        }
-        glfw::WindowEvent::Key(Key::D, _, action, modifier) => {
-            if modifier.is_empty() {
-                handle_key_press(state, action, Key::D);
-            } else {
-                state.floating_camera.process_keyboard(CameraMovement::Right, state.delta_time);
-            }
-        }
-        glfw::WindowEvent::Key(Key::Q, _, _, _) => {
-            state.floating_camera.process_keyboard(CameraMovement::Up, state.delta_time);
-        }
-        glfw::WindowEvent::Key(Key::Z, _, _, _) => {
-            state.floating_camera.process_keyboard(CameraMovement::Down, state.delta_time);
-        }
-        glfw::WindowEvent::CursorPos(xpos, ypos) => mouse_handler(state, xpos, ypos),
-        glfw::WindowEvent::Scroll(xoffset, ysoffset) => scroll_handler(state, xoffset, ysoffset),
-        glfw::WindowEvent::MouseButton(MouseButton::Button1, Action::Press, _) => state.player.borrow_mut().is_trying_to_fire = true,
-        glfw::WindowEvent::MouseButton(MouseButton::Button1, Action::Release, _) => state.player.borrow_mut().is_trying_to_fire = false,
-        _evt => {
-            // info!("WindowEvent: {:?}", _evt);
-        }
-    }
+    vec3(move_vec.x, 0.0, move_vec.y)
 }
 
-fn handle_key_press(state: &mut State, action: Action, key: Key) {
-    match action {
-        Action::Release => state.key_presses.remove(&key),
-        Action::Press => state.key_presses.insert(key),
-        _ => false,
-    };
-
-    if state.player.borrow().is_alive {
-        let player_speed = state.player.borrow().speed;
-        let mut player = state.player.borrow_mut();
-
-        let mut direction_vec = Vec3::splat(0.0);
-        for key in &state.key_presses {
-            match key {
-                Key::A => direction_vec += vec3(0.0, 0.0, -1.0),
-                Key::D => direction_vec += vec3(0.0, 0.0, 1.0),
-                Key::S => direction_vec += vec3(-1.0, 0.0, 0.0),
-                Key::W => direction_vec += vec3(1.0, 0.0, 0.0),
-                _ => {}
-            }
-        }
+// The direction that is 'up' on screen, flattened onto the floor.
+fn camera_forward_on_floor(view: &Mat4) -> Vec3 {
+    let forward = -vec3(view.x_axis.z, view.y_axis.z, view.z_axis.z);
+    let up = vec3(view.x_axis.y, view.y_axis.y, view.z_axis.y);
 
-        if direction_vec.length_squared() > 0.01 {
-            player.position += direction_vec.normalize() * player_speed * state.delta_time;
-        }
-        player.direction = vec2(direction_vec.x, direction_vec.z);
+    // looking straight down, screen up is the camera's up vector
+    let direction = if forward.y.abs() > 0.99 { up } else { forward };
 
-        // info!("key presses: {:?}", &state.key_presses);
-        // info!("direction: {:?}  player.direction: {:?}  delta_time: {:?}", direction_vec, player.direction, state.frame_time);
-    }
+    vec3(direction.x, 0.0, direction.z).normalize_or_zero()
 }
 
 fn framebuffer_size_event(_window: &mut glfw::Window, state: &mut State, width: i32, height: i32) {
@@ -882,6 +1180,7 @@ fn mouse_handler(state: &mut State, xpos_in: f64, ypos_in: f64) {
 
     state.mouse_x = xpos;
     state.mouse_y = ypos;
+    state.input.gamepad_aim = None;
 
     // info!("mouse: {}, {}", xpos, ypos);
 
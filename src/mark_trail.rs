@@ -0,0 +1,130 @@
+use glam::{vec3, Mat4, Vec3};
+use small_gl_core::gl;
+use small_gl_core::gl::GLuint;
+use small_gl_core::shader::Shader;
+use small_gl_core::texture::{bind_texture, Texture, TextureConfig, TextureWrap};
+
+const TRAIL_MARK_Y: f32 = 0.02;
+
+/// How the marks of a trail look and how long they last.
+pub struct TrailStyle {
+    pub texture_path: &'static str,
+    /// Seconds a mark takes to shrink away.
+    pub mark_time: f32,
+    /// Closest a new mark is put down to the last one.
+    pub mark_spacing: f32,
+    pub mark_scale: f32,
+}
+
+/// The player's path while dashing.
+pub const DASH_TRAIL: TrailStyle = TrailStyle {
+    texture_path: "angrygl_assets/bullet/burn_mark.png",
+    mark_time: 0.3,
+    mark_spacing: 0.3,
+    mark_scale: 0.4,
+};
+
+/// A small puff where a foot lands, one for every step.
+pub const FOOTSTEP_DUST: TrailStyle = TrailStyle {
+    texture_path: "angrygl_assets/bullet/burn_mark.png",
+    mark_time: 0.2,
+    mark_spacing: 0.0,
+    mark_scale: 0.15,
+};
+
+pub struct TrailMark {
+    position: Vec3,
+    time_left: f32,
+}
+
+/// Marks left on the floor that shrink away, such as the dash trail and footstep dust.
+pub struct MarkTrail {
+    unit_square_vao: i32,
+    style: &'static TrailStyle,
+    trail_texture: Texture,
+    marks: Vec<TrailMark>,
+}
+
+impl MarkTrail {
+    pub fn new(unit_square_vao: i32, style: &'static TrailStyle) -> Self {
+        let texture_config = TextureConfig::new().set_wrap(TextureWrap::Repeat);
+        let trail_texture = Texture::new(style.texture_path, &texture_config).unwrap();
+
+        Self {
+            unit_square_vao,
+            style,
+            trail_texture,
+            marks: vec![],
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.marks.clear();
+    }
+
+    /// Drops a mark at the position unless the last one is still close by.
+    pub fn add_mark(&mut self, position: Vec3) {
+        let position = vec3(position.x, TRAIL_MARK_Y, position.z);
+
+        if let Some(last) = self.marks.last() {
+            if last.position.distance(position) < self.style.mark_spacing {
+                return;
+            }
+        }
+
+        self.marks.push(TrailMark {
+            position,
+            time_left: self.style.mark_time,
+        });
+    }
+
+    pub fn update(&mut self, delta_time: f32) {
+        for mark in self.marks.iter_mut() {
+            mark.time_left -= delta_time;
+        }
+        self.marks.retain(|m| m.time_left > 0.0);
+    }
+
+    pub fn draw(&self, shader: &Shader, projection_view: &Mat4) {
+        if self.marks.is_empty() {
+            return;
+        }
+
+        shader.use_shader();
+        shader.set_mat4("PV", projection_view);
+        shader.set_bool("useLight", false);
+
+        bind_texture(shader, 0, "texture_diffuse", &self.trail_texture);
+        bind_texture(shader, 1, "texture_normal", &self.trail_texture);
+
+        unsafe {
+            gl::Enable(gl::BLEND);
+            gl::BlendFunc(gl::SRC_ALPHA, gl::ONE);
+            gl::DepthMask(gl::FALSE);
+            gl::Disable(gl::CULL_FACE);
+
+            gl::BindVertexArray(self.unit_square_vao as GLuint);
+        }
+
+        for mark in self.marks.iter() {
+            let scale = self.style.mark_scale * mark.time_left / self.style.mark_time;
+
+            let mut model = Mat4::from_translation(mark.position);
+            model *= Mat4::from_rotation_x(-90.0f32.to_radians());
+            model *= Mat4::from_scale(vec3(scale, scale, scale));
+
+            shader.set_mat4("model", &model);
+
+            unsafe {
+                gl::DrawArrays(gl::TRIANGLES, 0, 6);
+            }
+        }
+
+        unsafe {
+            gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+            gl::Disable(gl::BLEND);
+            gl::DepthMask(gl::TRUE);
+            gl::Enable(gl::CULL_FACE);
+        }
+    }
+}
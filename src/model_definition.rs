@@ -0,0 +1,171 @@
+use glam::{vec3, Vec3};
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+
+//
+// What a model file doesn't say about how the game uses the model, read from the model's definition file.
+// The lines are
+//
+//     upper_body name = weight
+//     upper_body_pivot = x y z
+//     socket name = bone|mesh parent x y z
+//
+// where the upper body lines mask the bones, or meshes, that follow the aim rather than the legs, and how much
+// of the aim each takes. The pivot is where the upper body turns about, in model space. Sockets are named
+// attachment points carried along by a bone or mesh, at a point in the parent's space, which for a bone is
+// where the point is in the model's bind pose.
+//
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SocketParent {
+    Bone,
+    Mesh,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SocketDefinition {
+    pub name: Rc<str>,
+    pub parent: SocketParent,
+    pub parent_name: Rc<str>,
+    pub offset: Vec3,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ModelDefinition {
+    pub upper_body: Vec<(Rc<str>, f32)>,
+    pub upper_body_pivot: Vec3,
+    pub sockets: Vec<SocketDefinition>,
+}
+
+impl ModelDefinition {
+    /// Reads the definition lines, skipping bad ones with a warning.
+    pub fn parse(contents: &str) -> Self {
+        let mut definition = Self::default();
+
+        for (line_number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let Some((key, values)) = line.split_once('=') else {
+                warn!("model definition line {}: expected 'key = values'", line_number + 1);
+                continue;
+            };
+            let key = key.trim();
+
+            if let Some(name) = key.strip_prefix("socket ") {
+                match parse_socket(name.trim(), values) {
+                    Some(socket) => definition.sockets.push(socket),
+                    None => warn!("model definition line {}: expected 'socket name = bone|mesh parent x y z'", line_number + 1),
+                }
+                continue;
+            }
+
+            let values: Vec<f32> = match values.split_whitespace().map(|v| v.parse::<f32>()).collect() {
+                Ok(values) => values,
+                Err(_) => {
+                    warn!("model definition line {}: bad number in '{}'", line_number + 1, values.trim());
+                    continue;
+                }
+            };
+
+            if let Some(name) = key.strip_prefix("upper_body ") {
+                match values[..] {
+                    [weight] => definition.upper_body.push((Rc::from(name.trim()), weight.clamp(0.0, 1.0))),
+                    _ => warn!("model definition line {}: expected 'upper_body name = weight'", line_number + 1),
+                }
+            } else if key == "upper_body_pivot" {
+                match values[..] {
+                    [x, y, z] => definition.upper_body_pivot = vec3(x, y, z),
+                    _ => warn!("model definition line {}: expected 'upper_body_pivot = x y z'", line_number + 1),
+                }
+            } else {
+                warn!("model definition line {}: unknown key '{}'", line_number + 1, key);
+            }
+        }
+
+        definition
+    }
+
+    /// Loads the definition file. A missing or unreadable file leaves the model with nothing defined.
+    pub fn load(path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref();
+        match fs::read_to_string(path) {
+            Ok(contents) => Self::parse(&contents),
+            Err(e) => {
+                error!("could not read model definition {:?}: {}", path, e);
+                Self::default()
+            }
+        }
+    }
+}
+
+fn parse_socket(name: &str, values: &str) -> Option<SocketDefinition> {
+    let values: Vec<&str> = values.split_whitespace().collect();
+    let [parent, parent_name, x, y, z] = values[..] else {
+        return None;
+    };
+    let parent = match parent {
+        "bone" => SocketParent::Bone,
+        "mesh" => SocketParent::Mesh,
+        _ => return None,
+    };
+    let (Ok(x), Ok(y), Ok(z)) = (x.parse(), y.parse(), z.parse()) else {
+        return None;
+    };
+    Some(SocketDefinition {
+        name: Rc::from(name),
+        parent,
+        parent_name: Rc::from(parent_name),
+        offset: vec3(x, y, z),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_model_definition() {
+        let definition = ModelDefinition::parse(
+            "\
+# comment
+upper_body Spine = 0.5
+upper_body Gun = 1
+upper_body Loud = 3
+upper_body_pivot = 0 100 0
+upper_body Bad = one
+upper_body_pivot = 1 2
+wings = 2
+socket muzzle = mesh Gun 191.04 79.231 -3.4651
+socket head = bone Head 0 160 0
+socket tail = wing Tail 0 0 0
+socket back = bone Spine2 0 120
+",
+        );
+
+        let upper_body: Vec<(&str, f32)> = definition.upper_body.iter().map(|(name, w)| (name.as_ref(), *w)).collect();
+        assert_eq!(upper_body, [("Spine", 0.5), ("Gun", 1.0), ("Loud", 1.0)]);
+        assert_eq!(definition.upper_body_pivot, vec3(0.0, 100.0, 0.0));
+
+        assert_eq!(
+            definition.sockets,
+            [
+                SocketDefinition {
+                    name: Rc::from("muzzle"),
+                    parent: SocketParent::Mesh,
+                    parent_name: Rc::from("Gun"),
+                    offset: vec3(191.04, 79.231, -3.4651),
+                },
+                SocketDefinition {
+                    name: Rc::from("head"),
+                    parent: SocketParent::Bone,
+                    parent_name: Rc::from("Head"),
+                    offset: vec3(0.0, 160.0, 0.0),
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,560 @@
+use crate::player::PlayerCommand;
+use crate::State;
+use glam::{vec2, vec3, Vec3};
+use std::collections::BTreeMap;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+
+//
+// Co-op over UDP in lockstep. Each peer sends its player's commands a few ticks ahead and only
+// steps the simulation once it has both players' commands for the tick, so every peer runs the
+// same ticks with the same commands. Checksums of the simulated state are exchanged to catch desyncs.
+//
+
+pub const NET_TICK_TIME: f32 = 1.0 / 60.0;
+// seconds
+const INPUT_DELAY: u32 = 3;
+// ticks, commands resent in every packet to cover packet loss
+const REDUNDANT_COMMANDS: u32 = 16;
+const HISTORY_TICKS: u32 = 120;
+const MAX_PACKET_SIZE: usize = 1024;
+const PROTOCOL_MAGIC: u32 = 0x4147_4231;
+
+const HELLO: u8 = 0;
+const WELCOME: u8 = 1;
+const COMMANDS: u8 = 2;
+
+const FIRE_BIT: u8 = 1;
+const DASH_BIT: u8 = 2;
+const AIM_BIT: u8 = 4;
+
+pub const HOST_PLAYER: usize = 0;
+pub const CLIENT_PLAYER: usize = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetMode {
+    Local,
+    Host(u16),
+    Join(SocketAddr),
+}
+
+impl NetMode {
+    /// `--host <port>` or `--join <host:port>`, otherwise a local game.
+    pub fn from_args(args: impl Iterator<Item = String>) -> NetMode {
+        let args: Vec<String> = args.collect();
+        let value_after = |flag: &str| args.iter().position(|a| a == flag).and_then(|i| args.get(i + 1));
+
+        if let Some(port) = value_after("--host") {
+            match port.parse() {
+                Ok(port) => return NetMode::Host(port),
+                Err(_) => warn!("bad port for --host: {}", port),
+            }
+        }
+        if let Some(address) = value_after("--join") {
+            match address.to_socket_addrs().ok().and_then(|mut addresses| addresses.next()) {
+                Some(address) => return NetMode::Join(address),
+                None => warn!("bad address for --join: {}", address),
+            }
+        }
+        NetMode::Local
+    }
+}
+
+/// Both players' commands for one tick, indexed by player.
+#[derive(Debug)]
+pub struct TickCommands {
+    pub tick: u32,
+    pub commands: [PlayerCommand; 2],
+}
+
+#[derive(Debug, PartialEq)]
+enum Packet {
+    Hello,
+    Welcome {
+        seed: u64,
+    },
+    Commands {
+        first_tick: u32,
+        commands: Vec<PlayerCommand>,
+        checksum: Option<(u32, u64)>,
+    },
+}
+
+pub struct NetSession {
+    socket: UdpSocket,
+    peer: Option<SocketAddr>,
+    local_player: usize,
+    seed: Option<u64>,
+    /// The next tick to simulate.
+    tick: u32,
+    local_commands: BTreeMap<u32, PlayerCommand>,
+    remote_commands: BTreeMap<u32, PlayerCommand>,
+    local_checksums: BTreeMap<u32, u64>,
+    remote_checksums: BTreeMap<u32, u64>,
+    last_checksum: Option<(u32, u64)>,
+    desync_tick: Option<u32>,
+}
+
+impl NetSession {
+    /// Waits for a client on the port. The host is the first player and picks the random seed.
+    pub fn host(port: u16, seed: u64) -> io::Result<Self> {
+        let socket = UdpSocket::bind(("0.0.0.0", port))?;
+        info!("hosting on {:?}", socket.local_addr()?);
+        Self::new(socket, None, HOST_PLAYER, Some(seed))
+    }
+
+    /// Connects to a host as the second player.
+    pub fn join(host: SocketAddr) -> io::Result<Self> {
+        let socket = if host.is_ipv4() {
+            UdpSocket::bind(("0.0.0.0", 0))?
+        } else {
+            UdpSocket::bind(("::", 0))?
+        };
+        info!("joining {}", host);
+        Self::new(socket, Some(host), CLIENT_PLAYER, None)
+    }
+
+    fn new(socket: UdpSocket, peer: Option<SocketAddr>, local_player: usize, seed: Option<u64>) -> io::Result<Self> {
+        socket.set_nonblocking(true)?;
+
+        // nobody has input for the first few ticks
+        let mut local_commands = BTreeMap::new();
+        let mut remote_commands = BTreeMap::new();
+        for tick in 0..INPUT_DELAY {
+            local_commands.insert(tick, PlayerCommand::default());
+            remote_commands.insert(tick, PlayerCommand::default());
+        }
+
+        Ok(Self {
+            socket,
+            peer,
+            local_player,
+            seed,
+            tick: 0,
+            local_commands,
+            remote_commands,
+            local_checksums: BTreeMap::new(),
+            remote_checksums: BTreeMap::new(),
+            last_checksum: None,
+            desync_tick: None,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Index of the player controlled from this machine.
+    pub fn local_player(&self) -> usize {
+        self.local_player
+    }
+
+    /// The shared random seed, known once the host and client have found each other.
+    pub fn seed(&self) -> Option<u64> {
+        self.seed
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.peer.is_some() && self.seed.is_some()
+    }
+
+    /// The first tick where the peers' checksums differed.
+    pub fn desync_tick(&self) -> Option<u32> {
+        self.desync_tick
+    }
+
+    /// Reads everything the peer has sent since the last call.
+    pub fn poll(&mut self) {
+        let mut buffer = [0u8; MAX_PACKET_SIZE];
+        loop {
+            match self.socket.recv_from(&mut buffer) {
+                Ok((size, from)) => self.handle_packet(&buffer[..size], from),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    warn!("network receive error: {}", e);
+                    break;
+                }
+            }
+        }
+
+        // keep saying hello until the host answers
+        if self.local_player == CLIENT_PLAYER && self.seed.is_none() {
+            self.send(&Packet::Hello);
+        }
+    }
+
+    /// Schedules the local command a few ticks ahead and returns both players' commands for the next tick,
+    /// or `None` while the peer's command for it hasn't arrived. The simulation only steps on `Some`.
+    pub fn next_tick(&mut self, local_command: &PlayerCommand) -> Option<TickCommands> {
+        if !self.is_connected() {
+            return None;
+        }
+
+        let remote = match self.remote_commands.remove(&self.tick) {
+            Some(remote) => remote,
+            None => {
+                // resend in case our last packet was lost and the peer is waiting on us
+                self.send_commands();
+                return None;
+            }
+        };
+        let local = self.local_commands.get(&self.tick).copied().unwrap_or_default();
+
+        let tick = self.tick;
+        self.local_commands.insert(tick + INPUT_DELAY, *local_command);
+        self.tick += 1;
+        self.send_commands();
+        self.prune_history();
+
+        let mut commands = [PlayerCommand::default(); 2];
+        commands[self.local_player] = local;
+        commands[1 - self.local_player] = remote;
+
+        Some(TickCommands { tick, commands })
+    }
+
+    /// Records the checksum of the state after simulating a tick, it goes out with the next packet.
+    pub fn record_checksum(&mut self, tick: u32, checksum: u64) {
+        self.local_checksums.insert(tick, checksum);
+        self.last_checksum = Some((tick, checksum));
+        self.compare_checksums();
+    }
+
+    fn handle_packet(&mut self, bytes: &[u8], from: SocketAddr) {
+        match decode_packet(bytes) {
+            Some(Packet::Hello) if self.local_player == HOST_PLAYER => {
+                if self.peer.is_none() {
+                    info!("client joined from {}", from);
+                    self.peer = Some(from);
+                }
+                if self.peer == Some(from) {
+                    self.send(&Packet::Welcome {
+                        seed: self.seed.unwrap_or_default(),
+                    });
+                }
+            }
+            Some(Packet::Welcome { seed }) if self.peer == Some(from) && self.seed.is_none() => {
+                info!("connected to {}", from);
+                self.seed = Some(seed);
+            }
+            Some(Packet::Commands {
+                first_tick,
+                commands,
+                checksum,
+            }) if self.peer == Some(from) => {
+                for (tick, command) in (first_tick..).zip(commands) {
+                    if tick >= self.tick {
+                        self.remote_commands.entry(tick).or_insert(command);
+                    }
+                }
+                if let Some((tick, checksum)) = checksum {
+                    self.remote_checksums.insert(tick, checksum);
+                    self.compare_checksums();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn send_commands(&self) {
+        // the newest scheduled command is for tick + INPUT_DELAY - 1
+        let newest = self.tick + INPUT_DELAY - 1;
+        let first = (newest + 1).saturating_sub(REDUNDANT_COMMANDS);
+
+        let commands: Vec<PlayerCommand> = self.local_commands.range(first..=newest).map(|(_, command)| *command).collect();
+        let first_tick = newest + 1 - commands.len() as u32;
+
+        self.send(&Packet::Commands {
+            first_tick,
+            commands,
+            checksum: self.last_checksum,
+        });
+    }
+
+    fn send(&self, packet: &Packet) {
+        if let Some(peer) = self.peer {
+            if let Err(e) = self.socket.send_to(&encode_packet(packet), peer) {
+                warn!("network send error: {}", e);
+            }
+        }
+    }
+
+    fn compare_checksums(&mut self) {
+        let local_checksums = &self.local_checksums;
+        let mut desync_tick = self.desync_tick;
+
+        self.remote_checksums.retain(|tick, remote| match local_checksums.get(tick) {
+            Some(local) => {
+                if local != remote && desync_tick.is_none() {
+                    error!("desync at tick {}: local checksum {:016x} remote {:016x}", tick, local, remote);
+                    desync_tick = Some(*tick);
+                }
+                false
+            }
+            None => true,
+        });
+
+        self.desync_tick = desync_tick;
+    }
+
+    fn prune_history(&mut self) {
+        let oldest = self.tick.saturating_sub(HISTORY_TICKS);
+        self.local_commands.retain(|tick, _| *tick >= oldest);
+        self.local_checksums.retain(|tick, _| *tick >= oldest);
+        self.remote_checksums.retain(|tick, _| *tick >= oldest);
+    }
+}
+
+/// FNV-1a over the bits of the simulated state.
+struct Checksum(u64);
+
+impl Checksum {
+    const fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn add_u32(&mut self, value: u32) {
+        for byte in value.to_le_bytes() {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    fn add_f32(&mut self, value: f32) {
+        self.add_u32(value.to_bits());
+    }
+
+    fn add_vec3(&mut self, value: Vec3) {
+        self.add_f32(value.x);
+        self.add_f32(value.y);
+        self.add_f32(value.z);
+    }
+
+    const fn value(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Checksum of the players and enemies, bullets show up in these soon enough.
+pub fn state_checksum(state: &State) -> u64 {
+    let mut checksum = Checksum::new();
+
+    for player in state.players.iter() {
+        let player = player.borrow();
+        checksum.add_vec3(player.position);
+        checksum.add_vec3(player.velocity);
+        checksum.add_u32(player.is_alive as u32);
+    }
+
+    checksum.add_u32(state.enemies.len() as u32);
+    for enemy in state.enemies.iter() {
+        checksum.add_vec3(enemy.position);
+        checksum.add_vec3(enemy.velocity);
+        checksum.add_f32(enemy.health);
+        checksum.add_u32(enemy.ai.state as u32);
+    }
+
+    checksum.value()
+}
+
+// Packets are little endian: magic, kind, then the body for the kind.
+fn encode_packet(packet: &Packet) -> Vec<u8> {
+    let mut bytes = PROTOCOL_MAGIC.to_le_bytes().to_vec();
+
+    match packet {
+        Packet::Hello => bytes.push(HELLO),
+        Packet::Welcome { seed } => {
+            bytes.push(WELCOME);
+            bytes.extend(seed.to_le_bytes());
+        }
+        Packet::Commands {
+            first_tick,
+            commands,
+            checksum,
+        } => {
+            bytes.push(COMMANDS);
+            bytes.extend(first_tick.to_le_bytes());
+            bytes.push(commands.len() as u8);
+            for command in commands {
+                encode_command(command, &mut bytes);
+            }
+            match checksum {
+                Some((tick, value)) => {
+                    bytes.push(1);
+                    bytes.extend(tick.to_le_bytes());
+                    bytes.extend(value.to_le_bytes());
+                }
+                None => bytes.push(0),
+            }
+        }
+    }
+    bytes
+}
+
+fn encode_command(command: &PlayerCommand, bytes: &mut Vec<u8>) {
+    let mut flags = 0;
+    if command.fire {
+        flags |= FIRE_BIT;
+    }
+    if command.dash {
+        flags |= DASH_BIT;
+    }
+    if command.aim.is_some() {
+        flags |= AIM_BIT;
+    }
+    bytes.push(flags);
+    bytes.extend(command.move_vec.x.to_le_bytes());
+    bytes.extend(command.move_vec.z.to_le_bytes());
+    if let Some(aim) = command.aim {
+        bytes.extend(aim.x.to_le_bytes());
+        bytes.extend(aim.y.to_le_bytes());
+    }
+}
+
+fn decode_packet(bytes: &[u8]) -> Option<Packet> {
+    let mut reader = PacketReader { bytes };
+
+    if reader.u32()? != PROTOCOL_MAGIC {
+        return None;
+    }
+
+    match reader.u8()? {
+        HELLO => Some(Packet::Hello),
+        WELCOME => Some(Packet::Welcome { seed: reader.u64()? }),
+        COMMANDS => {
+            let first_tick = reader.u32()?;
+            let count = reader.u8()?;
+            let commands = (0..count).map(|_| decode_command(&mut reader)).collect::<Option<Vec<_>>>()?;
+            let checksum = match reader.u8()? {
+                0 => None,
+                _ => Some((reader.u32()?, reader.u64()?)),
+            };
+            Some(Packet::Commands {
+                first_tick,
+                commands,
+                checksum,
+            })
+        }
+        _ => None,
+    }
+}
+
+fn decode_command(reader: &mut PacketReader) -> Option<PlayerCommand> {
+    let flags = reader.u8()?;
+    let move_vec = vec3(reader.f32()?, 0.0, reader.f32()?);
+    let aim = if flags & AIM_BIT != 0 {
+        Some(vec2(reader.f32()?, reader.f32()?))
+    } else {
+        None
+    };
+
+    Some(PlayerCommand {
+        move_vec,
+        aim,
+        fire: flags & FIRE_BIT != 0,
+        dash: flags & DASH_BIT != 0,
+    })
+}
+
+struct PacketReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl PacketReader<'_> {
+    fn take<const N: usize>(&mut self) -> Option<[u8; N]> {
+        if self.bytes.len() < N {
+            return None;
+        }
+        let (head, rest) = self.bytes.split_at(N);
+        self.bytes = rest;
+        head.try_into().ok()
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.take::<1>().map(|b| b[0])
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.take().map(u32::from_le_bytes)
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        self.take().map(u64::from_le_bytes)
+    }
+
+    fn f32(&mut self) -> Option<f32> {
+        self.take().map(f32::from_le_bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread::sleep;
+    use std::time::Duration;
+
+    #[test]
+    fn test_commands_packet_round_trip() {
+        let packet = Packet::Commands {
+            first_tick: 42,
+            commands: vec![
+                PlayerCommand::default(),
+                PlayerCommand {
+                    move_vec: vec3(0.5, 0.0, -1.0),
+                    aim: Some(vec2(0.25, 0.75)),
+                    fire: true,
+                    dash: true,
+                },
+            ],
+            checksum: Some((40, 0x1234_5678_9abc_def0)),
+        };
+
+        assert_eq!(decode_packet(&encode_packet(&packet)), Some(packet));
+        assert_eq!(decode_packet(&[1, 2, 3]), None);
+    }
+
+    #[test]
+    fn test_host_and_client_agree_on_commands() {
+        let mut host = NetSession::host(0, 7).unwrap();
+        let host_address = SocketAddr::from(([127, 0, 0, 1], host.local_addr().unwrap().port()));
+        let mut client = NetSession::join(host_address).unwrap();
+
+        let host_command = PlayerCommand {
+            fire: true,
+            ..PlayerCommand::default()
+        };
+        let client_command = PlayerCommand {
+            dash: true,
+            ..PlayerCommand::default()
+        };
+
+        let mut host_ticks = vec![];
+        let mut client_ticks = vec![];
+
+        for _ in 0..1000 {
+            host.poll();
+            client.poll();
+
+            if let Some(tick) = host.next_tick(&host_command) {
+                host.record_checksum(tick.tick, tick.tick as u64);
+                host_ticks.push(tick.commands);
+            }
+            if let Some(tick) = client.next_tick(&client_command) {
+                client.record_checksum(tick.tick, tick.tick as u64);
+                client_ticks.push(tick.commands);
+            }
+
+            if host_ticks.len() >= 10 && client_ticks.len() >= 10 {
+                break;
+            }
+            sleep(Duration::from_millis(1));
+        }
+
+        assert_eq!(client.seed(), Some(7));
+        assert!(host_ticks.len() >= 10 && client_ticks.len() >= 10);
+        assert_eq!(host_ticks[..10], client_ticks[..10]);
+        assert_eq!(host_ticks[INPUT_DELAY as usize], [host_command, client_command]);
+        assert_eq!(host.desync_tick(), None);
+        assert_eq!(client.desync_tick(), None);
+    }
+}
@@ -0,0 +1,159 @@
+use glam::{vec2, vec3, Mat4, Vec2, Vec4};
+use small_gl_core::gl;
+use small_gl_core::gl::GLuint;
+use small_gl_core::shader::Shader;
+
+//
+// Flat colored shapes and blocky text drawn over the finished frame, in screen pixels with the origin at the bottom left.
+// Enough for the menus without a font texture.
+//
+
+const GLYPH_WIDTH: usize = 5;
+const GLYPH_HEIGHT: usize = 7;
+const GLYPH_SPACING: usize = 1; // font pixels between characters
+
+pub struct Overlay {
+    shader: Shader,
+    unit_square_vao: GLuint,
+    projection: Mat4,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Overlay {
+    pub fn new(unit_square_vao: i32) -> Self {
+        Self {
+            shader: Shader::new("shaders/overlay_shader.vert", "shaders/overlay_shader.frag").unwrap(),
+            unit_square_vao: unit_square_vao as GLuint,
+            projection: Mat4::IDENTITY,
+            width: 1.0,
+            height: 1.0,
+        }
+    }
+
+    /// Sets up blending and the pixel projection for the viewport, call before drawing.
+    pub fn begin(&mut self, viewport_width: i32, viewport_height: i32) {
+        self.width = viewport_width as f32;
+        self.height = viewport_height as f32;
+        self.projection = Mat4::orthographic_rh_gl(0.0, self.width, 0.0, self.height, -1.0, 1.0);
+
+        self.shader.use_shader();
+        self.shader.set_mat4("projection", &self.projection);
+
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+            gl::Viewport(0, 0, viewport_width, viewport_height);
+            gl::Enable(gl::BLEND);
+            gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+            gl::Disable(gl::DEPTH_TEST);
+            gl::Disable(gl::CULL_FACE);
+            gl::BindVertexArray(self.unit_square_vao);
+        }
+    }
+
+    pub fn end(&self) {
+        unsafe {
+            gl::Disable(gl::BLEND);
+            gl::Enable(gl::DEPTH_TEST);
+            gl::Enable(gl::CULL_FACE);
+        }
+    }
+
+    /// Covers the whole screen, a translucent color dims the scene behind.
+    pub fn fill_screen(&self, color: Vec4) {
+        self.draw_rect(vec2(self.width / 2.0, self.height / 2.0), vec2(self.width, self.height), color);
+    }
+
+    pub fn draw_rect(&self, center: Vec2, size: Vec2, color: Vec4) {
+        let mut model = Mat4::from_translation(center.extend(0.0));
+        // the unit square runs from -1 to 1
+        model *= Mat4::from_scale(vec3(size.x / 2.0, size.y / 2.0, 1.0));
+
+        self.shader.set_mat4("model", &model);
+        self.shader.set_vec3("color", &color.truncate());
+        self.shader.set_float("alpha", color.w);
+
+        unsafe {
+            gl::DrawArrays(gl::TRIANGLES, 0, 6);
+        }
+    }
+
+    /// Draws the text centered on the point, `pixel_size` is the size in screen pixels of one font pixel.
+    /// Characters without a glyph are drawn as spaces.
+    pub fn draw_text(&self, text: &str, center: Vec2, pixel_size: f32, color: Vec4) {
+        let advance = (GLYPH_WIDTH + GLYPH_SPACING) as f32 * pixel_size;
+        let width = text.chars().count() as f32 * advance - GLYPH_SPACING as f32 * pixel_size;
+        let left = center.x - width / 2.0;
+        let top = center.y + GLYPH_HEIGHT as f32 * pixel_size / 2.0;
+
+        for (index, c) in text.chars().enumerate() {
+            let Some(rows) = glyph(c) else {
+                continue;
+            };
+            let glyph_left = left + index as f32 * advance;
+
+            for (row, bits) in rows.iter().enumerate() {
+                for column in 0..GLYPH_WIDTH {
+                    if bits & (1 << (GLYPH_WIDTH - 1 - column)) == 0 {
+                        continue;
+                    }
+                    let pixel_center = vec2(
+                        glyph_left + (column as f32 + 0.5) * pixel_size,
+                        top - (row as f32 + 0.5) * pixel_size,
+                    );
+                    self.draw_rect(pixel_center, Vec2::splat(pixel_size), color);
+                }
+            }
+        }
+    }
+}
+
+// 5x7 glyphs, one byte per row from the top with the leftmost pixel in bit 4.
+fn glyph(c: char) -> Option<&'static [u8; GLYPH_HEIGHT]> {
+    let index = match c.to_ascii_uppercase() {
+        c @ 'A'..='Z' => c as usize - 'A' as usize,
+        c @ '0'..='9' => 26 + c as usize - '0' as usize,
+        _ => return None,
+    };
+    Some(&GLYPHS[index])
+}
+
+#[rustfmt::skip]
+static GLYPHS: [[u8; GLYPH_HEIGHT]; 36] = [
+    [0b01110, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001], // A
+    [0b11110, 0b10001, 0b10001, 0b11110, 0b10001, 0b10001, 0b11110], // B
+    [0b01110, 0b10001, 0b10000, 0b10000, 0b10000, 0b10001, 0b01110], // C
+    [0b11110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b11110], // D
+    [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b11111], // E
+    [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b10000], // F
+    [0b01110, 0b10001, 0b10000, 0b10111, 0b10001, 0b10001, 0b01111], // G
+    [0b10001, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001], // H
+    [0b01110, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110], // I
+    [0b00111, 0b00010, 0b00010, 0b00010, 0b00010, 0b10010, 0b01100], // J
+    [0b10001, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010, 0b10001], // K
+    [0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b11111], // L
+    [0b10001, 0b11011, 0b10101, 0b10101, 0b10001, 0b10001, 0b10001], // M
+    [0b10001, 0b10001, 0b11001, 0b10101, 0b10011, 0b10001, 0b10001], // N
+    [0b01110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110], // O
+    [0b11110, 0b10001, 0b10001, 0b11110, 0b10000, 0b10000, 0b10000], // P
+    [0b01110, 0b10001, 0b10001, 0b10001, 0b10101, 0b10010, 0b01101], // Q
+    [0b11110, 0b10001, 0b10001, 0b11110, 0b10100, 0b10010, 0b10001], // R
+    [0b01111, 0b10000, 0b10000, 0b01110, 0b00001, 0b00001, 0b11110], // S
+    [0b11111, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100], // T
+    [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110], // U
+    [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100], // V
+    [0b10001, 0b10001, 0b10001, 0b10101, 0b10101, 0b10101, 0b01010], // W
+    [0b10001, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001, 0b10001], // X
+    [0b10001, 0b10001, 0b01010, 0b00100, 0b00100, 0b00100, 0b00100], // Y
+    [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b11111], // Z
+    [0b01110, 0b10001, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110], // 0
+    [0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110], // 1
+    [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111], // 2
+    [0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110], // 3
+    [0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010], // 4
+    [0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110], // 5
+    [0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110], // 6
+    [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000], // 7
+    [0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110], // 8
+    [0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100], // 9
+];
@@ -1,18 +1,52 @@
+use crate::animation_set::AnimationSet;
+use crate::blend_space::{BlendClip, BlendSpace};
+use crate::model_definition::ModelDefinition;
+use crate::pose_layers::{turn_body, upper_body_transform, wrap_angle, AdditivePose};
+use crate::sockets::{find_bone, find_mesh, Sockets};
 use crate::State;
-use glam::{vec2, vec3, Mat4, Vec2, Vec3};
-use small_gl_core::animator::{AnimationClip, AnimationRepeat, WeightedAnimation};
-use small_gl_core::hash_map::HashMap;
+use glam::{vec2, vec3, Mat4, Quat, Vec2, Vec3};
+use small_gl_core::animator::WeightedAnimation;
 use small_gl_core::model::{Model, ModelBuilder};
 use small_gl_core::shader::Shader;
 use small_gl_core::texture::TextureType;
-use std::f32::consts::PI;
-use std::ops::Deref;
 use std::rc::Rc;
 use std::time::Duration;
 
 const PLAYER_SPEED: f32 = 5.0;
 // 1.5;
+const PLAYER_ACCELERATION: f32 = 40.0;
+// units per second squared
+const PLAYER_DECELERATION: f32 = 30.0;
 const ANIM_TRANSITION_TIME: f32 = 0.2;
+const ANIMATIONS_PATH: &str = "angrygl_assets/Player/player_animations.cfg";
+const MODEL_DEFINITION_PATH: &str = "angrygl_assets/Player/player_model.cfg";
+
+// Upper body layers
+const MAX_UPPER_BODY_TWIST: f32 = 1.2; // radians the upper body turns from the legs to aim
+const BODY_TURN_SPEED: f32 = 10.0; // radians per second the legs turn towards the movement
+
+// over before the next shot
+const RECOIL: AdditivePose = AdditivePose {
+    pitch: 0.08,
+    yaw: 0.0,
+    attack_time: 0.02,
+    recover_time: 0.07,
+};
+// the player has no health, every hit kills, so this only plays as the player goes down
+const FLINCH: AdditivePose = AdditivePose {
+    pitch: 0.3,
+    yaw: 0.15,
+    attack_time: 0.05,
+    recover_time: 0.35,
+};
+
+// Dash
+const DASH_SPEED: f32 = 20.0;
+const DASH_DURATION: f32 = 0.15;
+// seconds
+const DASH_COOLDOWN: f32 = 1.0;
+// seconds, enemies can't kill the player for this long after the dash starts
+const DASH_INVULNERABILITY_TIME: f32 = 0.25;
 
 const IDLE: &str = "idle";
 const RIGHT: &str = "right";
@@ -21,78 +55,66 @@ const BACK: &str = "back";
 const LEFT: &str = "left";
 const DEAD: &str = "dead";
 
+// animation events
+pub const FOOTSTEP: &str = "footstep";
+pub const DEATH_HIT_GROUND: &str = "death_hit_ground";
+
+// sockets
+pub const MUZZLE_SOCKET: &str = "muzzle";
+
+/// What a player asks to do on one simulation tick. Local input and the network both produce these.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PlayerCommand {
+    /// Movement in world x/z, up to unit length.
+    pub move_vec: Vec3,
+    /// New aim in world x/z, `None` keeps the current aim.
+    pub aim: Option<Vec2>,
+    pub fire: bool,
+    pub dash: bool,
+}
+
+impl PlayerCommand {
+    /// Folds a newer command into this one, keeping a dash press that hasn't been used yet.
+    pub fn merge(&mut self, newer: &PlayerCommand) {
+        self.move_vec = newer.move_vec;
+        self.aim = newer.aim.or(self.aim);
+        self.fire = newer.fire;
+        self.dash |= newer.dash;
+    }
+}
+
 pub struct Player {
     pub model: Model,
     pub position: Vec3,
     pub direction: Vec2,
+    pub velocity: Vec3,
     pub speed: f32,
     pub aim_theta: f32,
+    /// Which way the legs face, the upper body twists from here to the aim.
+    pub body_theta: f32,
+    /// Negative until the first shot, so there's no recoil at spawn.
     pub last_fire_time: f32,
+    /// When the killing hit landed, negative while alive. Every hit is lethal, so the flinch only plays on death.
+    pub last_hit_time: f32,
     pub is_trying_to_fire: bool,
     pub is_alive: bool,
     pub death_time: f32,
+    pub last_dash_time: f32,
+    pub dash_time_left: f32,
+    pub dash_direction: Vec3,
     pub animation_name: Rc<str>,
-    pub animations: PlayerAnimations,
-    pub anim_weights: AnimationWeights,
-    pub anim_hash: HashMap<Rc<str>, Rc<AnimationClip>>,
-}
-
-pub struct PlayerAnimations {
-    idle: Rc<AnimationClip>,
-    right: Rc<AnimationClip>,
-    forward: Rc<AnimationClip>,
-    back: Rc<AnimationClip>,
-    left: Rc<AnimationClip>,
-    dead: Rc<AnimationClip>,
-}
-
-impl PlayerAnimations {
-    pub fn new() -> Self {
-        Self {
-            idle: Rc::new(AnimationClip::new(55.0, 130.0, AnimationRepeat::Forever)),
-            right: Rc::new(AnimationClip::new(184.0, 204.0, AnimationRepeat::Forever)),
-            forward: Rc::new(AnimationClip::new(134.0, 154.0, AnimationRepeat::Forever)),
-            back: Rc::new(AnimationClip::new(159.0, 179.0, AnimationRepeat::Forever)),
-            left: Rc::new(AnimationClip::new(209.0, 229.0, AnimationRepeat::Forever)),
-            dead: Rc::new(AnimationClip::new(234.0, 293.0, AnimationRepeat::Once)),
-        }
-    }
-
-    pub fn get(&self, name: &str) -> &Rc<AnimationClip> {
-        match name {
-            "idle" => &self.idle,
-            "right" => &self.right,
-            "forward" => &self.forward,
-            "back" => &self.back,
-            "left" => &self.left,
-            "dead" => &self.dead,
-            _ => panic!("animation not found"),
-        }
-    }
-}
-
-#[derive(Debug)]
-pub struct AnimationWeights {
-    // Previous animation weights
+    pub animations: AnimationSet,
+    pub locomotion: BlendSpace,
     last_anim_time: f32,
-    prev_idle_weight: f32,
-    prev_right_weight: f32,
-    prev_forward_weight: f32,
-    prev_back_weight: f32,
-    prev_left_weight: f32,
-}
-
-impl Default for AnimationWeights {
-    fn default() -> Self {
-        Self {
-            last_anim_time: 0.0,
-            prev_idle_weight: 0.0,
-            prev_right_weight: 0.0,
-            prev_forward_weight: 0.0,
-            prev_back_weight: 0.0,
-            prev_left_weight: 0.0,
-        }
-    }
+    /// Events the animation played past on the last update.
+    pub animation_events: Vec<Rc<str>>,
+    /// Whether the death clip has reached the point the body lands.
+    pub death_landed: bool,
+    // bone and mesh indices of the upper body with their mask weights
+    upper_body_bones: Vec<(usize, f32)>,
+    upper_body_meshes: Vec<(usize, f32)>,
+    upper_body_pivot: Vec3,
+    pub sockets: Sockets,
 }
 
 impl Player {
@@ -109,61 +131,104 @@ impl Player {
             .build()
             .unwrap();
 
-        let mut anim_hash: HashMap<Rc<str>, Rc<AnimationClip>> = HashMap::new();
-        anim_hash.insert(Rc::from(IDLE), Rc::new(AnimationClip::new(55.0, 130.0, AnimationRepeat::Forever)));
-        anim_hash.insert(Rc::from(FORWARD), Rc::new(AnimationClip::new(134.0, 154.0, AnimationRepeat::Forever)));
-        anim_hash.insert(Rc::from(BACK), Rc::new(AnimationClip::new(159.0, 179.0, AnimationRepeat::Forever)));
-        anim_hash.insert(Rc::from(RIGHT), Rc::new(AnimationClip::new(184.0, 204.0, AnimationRepeat::Forever)));
-        anim_hash.insert(Rc::from(LEFT), Rc::new(AnimationClip::new(209.0, 229.0, AnimationRepeat::Forever)));
-        anim_hash.insert(Rc::from(DEAD), Rc::new(AnimationClip::new(234.0, 293.0, AnimationRepeat::Once)));
+        let animation_name = Rc::from(IDLE);
 
-        let animation_name = Rc::from("idle");
+        let definition = ModelDefinition::load(MODEL_DEFINITION_PATH);
+        let (upper_body_bones, upper_body_meshes) = resolve_upper_body(&player_model, &definition);
+        let sockets = Sockets::new(&player_model, &definition.sockets);
+        // bullets and the muzzle flash come from here, so the player can't be played without it
+        assert!(sockets.contains(MUZZLE_SOCKET), "player model has no '{}' socket", MUZZLE_SOCKET);
 
         let player = Self {
             model: player_model,
-            last_fire_time: 0.0,
+            last_fire_time: -1.0,
+            last_hit_time: -1.0,
             is_trying_to_fire: false,
             is_alive: true,
             aim_theta: 0.0,
+            body_theta: 0.0,
             position: vec3(0.0, 0.0, 0.0),
             direction: vec2(0.0, 0.0),
+            velocity: Vec3::ZERO,
             death_time: -1.0,
+            last_dash_time: -DASH_COOLDOWN,
+            dash_time_left: 0.0,
+            dash_direction: Vec3::ZERO,
             animation_name,
             speed: PLAYER_SPEED,
-            animations: PlayerAnimations::new(),
-            anim_weights: AnimationWeights::default(),
-            anim_hash,
+            animations: AnimationSet::load(ANIMATIONS_PATH),
+            locomotion: locomotion_blend_space(),
+            last_anim_time: 0.0,
+            animation_events: vec![],
+            death_landed: false,
+            upper_body_bones,
+            upper_body_meshes,
+            upper_body_pivot: definition.upper_body_pivot,
+            sockets,
         };
 
-        player.model.play_clip(&player.animations.idle);
+        if let Some(idle) = player.animations.get(IDLE) {
+            player.model.play_clip(idle);
+        }
 
         player
     }
 
+    /// Back to how `new` left the player at the position, keeping the loaded model. Used on restart.
+    pub fn respawn(&mut self, position: Vec3) {
+        self.position = position;
+        self.direction = vec2(0.0, 0.0);
+        self.velocity = Vec3::ZERO;
+        self.aim_theta = 0.0;
+        self.body_theta = 0.0;
+        self.last_fire_time = -1.0;
+        self.last_hit_time = -1.0;
+        self.is_trying_to_fire = false;
+        self.is_alive = true;
+        self.death_time = -1.0;
+        self.last_dash_time = -DASH_COOLDOWN;
+        self.dash_time_left = 0.0;
+        self.dash_direction = Vec3::ZERO;
+        self.locomotion.reset();
+        self.last_anim_time = 0.0;
+        self.animation_events.clear();
+        self.death_landed = false;
+        self.animation_name = Rc::from(IDLE);
+        if let Some(idle) = self.animations.get(IDLE) {
+            self.model.play_clip(idle);
+        }
+    }
+
     pub fn set_animation(&mut self, animation_name: &Rc<str>, seconds: u32) {
-        if !self.animation_name.eq(animation_name) {
-            self.animation_name = animation_name.clone();
-            self.model
-                .play_clip_with_transition(self.animations.get(self.animation_name.deref()), Duration::from_secs(seconds as u64));
+        if self.animation_name.eq(animation_name) {
+            return;
         }
+        let Some(clip) = self.animations.get(animation_name) else {
+            warn!("player has no animation '{}'", animation_name);
+            return;
+        };
+        self.animation_name = animation_name.clone();
+        self.model.play_clip_with_transition(clip, Duration::from_secs(seconds as u64));
     }
 
     pub fn get_muzzle_position(&self, player_model_transform: &Mat4) -> Mat4 {
-        // Position in original model of gun muzzle
-        // let point_vec = vec3(197.0, 76.143, -3.054);
-        let point_vec = vec3(191.04, 79.231, -3.4651); // center of muzzle
-
-        let animator = self.model.animator.borrow();
-        let gun_mesh = self.model.meshes.iter().find(|m| m.name.as_str() == "Gun").unwrap();
-
-        let final_node_matrices = animator.final_node_matrices.borrow();
-
-        let gun_transform = final_node_matrices.get(gun_mesh.id as usize).unwrap();
+        self.socket_transform(MUZZLE_SOCKET, player_model_transform)
+            .expect("the muzzle socket is checked when the player loads")
+    }
 
-        let muzzle = *gun_transform * Mat4::from_translation(point_vec);
+    /// One of the player's named attachment points in world space, for the player drawn with `player_model_transform`.
+    pub fn socket_transform(&self, name: &str, player_model_transform: &Mat4) -> Option<Mat4> {
+        self.sockets.transform(&self.model, name, player_model_transform)
+    }
 
-        // muzzle_transform
-        *player_model_transform * muzzle
+    pub fn die(&mut self, frame_time: f32) {
+        if self.is_alive {
+            self.last_hit_time = frame_time;
+        }
+        self.is_alive = false;
+        self.set_player_death_time(frame_time);
+        self.direction = vec2(0.0, 0.0);
+        self.velocity = Vec3::ZERO;
     }
 
     pub fn set_player_death_time(&mut self, time: f32) {
@@ -172,77 +237,192 @@ impl Player {
         }
     }
 
+    /// Moves toward the input direction, accelerating up to `speed` and slowing down when there is no input.
+    /// The move vector is in world x/z and is clamped to unit length.
+    pub fn update_movement(&mut self, move_vec: Vec3, delta_time: f32) {
+        let move_vec = if self.is_alive { move_vec.clamp_length_max(1.0) } else { Vec3::ZERO };
+        self.direction = vec2(move_vec.x, move_vec.z);
+
+        let target_velocity = move_vec * self.speed;
+        let rate = if move_vec.length_squared() > 0.0 {
+            PLAYER_ACCELERATION
+        } else {
+            PLAYER_DECELERATION
+        };
+
+        let delta = target_velocity - self.velocity;
+        let max_change = rate * delta_time;
+
+        if delta.length() <= max_change {
+            self.velocity = target_velocity;
+        } else {
+            self.velocity += delta.normalize() * max_change;
+        }
+
+        self.position += self.velocity * delta_time;
+    }
+
+    /// Starts a dash in the movement direction, or the aim direction when standing still.
+    /// Returns false if the dash is still cooling down.
+    pub fn try_dash(&mut self, frame_time: f32) -> bool {
+        if !self.is_alive || (self.last_dash_time + DASH_COOLDOWN) > frame_time {
+            return false;
+        }
+
+        let move_dir = vec3(self.direction.x, 0.0, self.direction.y);
+
+        self.dash_direction = if move_dir.length_squared() > 0.01 {
+            move_dir.normalize()
+        } else {
+            vec3(self.aim_theta.sin(), 0.0, self.aim_theta.cos())
+        };
+
+        self.last_dash_time = frame_time;
+        self.dash_time_left = DASH_DURATION;
+        true
+    }
+
+    pub fn is_dashing(&self) -> bool {
+        self.dash_time_left > 0.0
+    }
+
+    pub fn is_invulnerable(&self, frame_time: f32) -> bool {
+        frame_time < self.last_dash_time + DASH_INVULNERABILITY_TIME
+    }
+
+    pub fn update_dash(&mut self, delta_time: f32) {
+        if !self.is_dashing() {
+            return;
+        }
+        let dash_time = delta_time.min(self.dash_time_left);
+        self.position += self.dash_direction * DASH_SPEED * dash_time;
+        self.dash_time_left -= dash_time;
+    }
+
     pub fn render(&self, shader: &Shader) {
         self.model.render(shader);
     }
 
     pub fn update(&mut self, state: &State, aim_theta: f32) {
-        let weight_animations = self.update_animation_weights(self.direction, aim_theta, state.frame_time);
+        self.aim_theta = aim_theta;
+        // blend on the actual velocity so the animation eases in and out with the movement
+        let move_vec = vec2(self.velocity.x, self.velocity.z) / self.speed;
+        if self.is_alive {
+            self.body_theta = turn_body(self.body_theta, aim_theta, move_vec, MAX_UPPER_BODY_TWIST, BODY_TURN_SPEED * state.delta_time);
+        }
+        let weight_animations = self.update_animation_weights(move_vec, self.body_theta, state.frame_time);
         self.model.play_weight_animations(weight_animations.as_slice(), state.frame_time);
+        self.apply_upper_body_layers(state.frame_time);
     }
 
-    fn update_animation_weights(&mut self, move_vec: Vec2, aim_theta: f32, frame_time: f32) -> [WeightedAnimation; 6] {
-        let is_moving = move_vec.length_squared() > 0.1;
+    /// Turns the upper body from the legs to the aim and leans it with recoil and flinch, on top of the pose the
+    /// clips gave. The model is drawn facing `body_theta`.
+    fn apply_upper_body_layers(&self, frame_time: f32) {
+        let twist = wrap_angle(self.aim_theta - self.body_theta);
+        let mut additive = Quat::IDENTITY;
+        if self.last_fire_time >= 0.0 {
+            additive = RECOIL.rotation(frame_time - self.last_fire_time);
+        }
+        if self.last_hit_time >= 0.0 {
+            additive = FLINCH.rotation(frame_time - self.last_hit_time) * additive;
+        }
 
-        let move_theta = (move_vec.x / move_vec.y).atan() + if move_vec.y < 0.0 { PI } else { 0.0 };
-        let theta_delta = move_theta - aim_theta;
-        let anim_move = vec2(theta_delta.sin(), theta_delta.cos());
+        let animator = self.model.animator.borrow();
+        let mut final_bone_matrices = animator.final_bone_matrices.borrow_mut();
+        let mut final_node_matrices = animator.final_node_matrices.borrow_mut();
+
+        let layered = [
+            (&mut *final_bone_matrices, &self.upper_body_bones),
+            (&mut *final_node_matrices, &self.upper_body_meshes),
+        ];
+        for (matrices, mask) in layered {
+            for &(index, weight) in mask.iter() {
+                if let Some(matrix) = matrices.get_mut(index) {
+                    *matrix = upper_body_transform(self.upper_body_pivot, twist, additive, weight) * *matrix;
+                }
+            }
+        }
+    }
 
-        let anim_delta_time = frame_time - self.anim_weights.last_anim_time;
-        self.anim_weights.last_anim_time = frame_time;
+    fn update_animation_weights(&mut self, move_vec: Vec2, body_theta: f32, frame_time: f32) -> Vec<WeightedAnimation> {
+        let previous_anim_time = self.last_anim_time;
+        let anim_delta_time = frame_time - self.last_anim_time;
+        self.last_anim_time = frame_time;
 
         let is_dead = self.death_time >= 0.0;
+        let facing = vec2(body_theta.sin(), body_theta.cos());
+        let move_vec = if is_dead { Vec2::ZERO } else { move_vec };
+        self.locomotion.update(move_vec, facing, anim_delta_time);
+
+        // the death clip takes over from the locomotion over the transition time
+        let dead_weight = if is_dead {
+            ((frame_time - self.death_time) / ANIM_TRANSITION_TIME).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+
+        // nothing has played yet on the first update after a start
+        if previous_anim_time > 0.0 {
+            self.update_animation_events(previous_anim_time, frame_time, dead_weight);
+        } else {
+            self.animation_events.clear();
+        }
 
-        self.anim_weights.prev_idle_weight = max(0.0, self.anim_weights.prev_idle_weight - anim_delta_time / ANIM_TRANSITION_TIME);
-        self.anim_weights.prev_right_weight = max(0.0, self.anim_weights.prev_right_weight - anim_delta_time / ANIM_TRANSITION_TIME);
-        self.anim_weights.prev_forward_weight = max(0.0, self.anim_weights.prev_forward_weight - anim_delta_time / ANIM_TRANSITION_TIME);
-        self.anim_weights.prev_back_weight = max(0.0, self.anim_weights.prev_back_weight - anim_delta_time / ANIM_TRANSITION_TIME);
-        self.anim_weights.prev_left_weight = max(0.0, self.anim_weights.prev_left_weight - anim_delta_time / ANIM_TRANSITION_TIME);
-
-        let mut dead_weight = if is_dead { 1.0 } else { 0.0 };
-        let mut idle_weight = self.anim_weights.prev_idle_weight + if is_moving || is_dead { 0.0f32 } else { 1.0 };
-        let mut right_weight = self.anim_weights.prev_right_weight + if is_moving { clamp0(-anim_move.x) } else { 0.0 };
-        let mut forward_weight = self.anim_weights.prev_forward_weight + if is_moving { clamp0(anim_move.y) } else { 0.0 };
-        let mut back_weight = self.anim_weights.prev_back_weight + if is_moving { clamp0(-anim_move.y) } else { 0.0 };
-        let mut left_weight = self.anim_weights.prev_left_weight + if is_moving { clamp0(anim_move.x) } else { 0.0 };
-
-        let weight_sum = dead_weight + idle_weight + forward_weight + back_weight + right_weight + left_weight;
-        dead_weight /= weight_sum;
-        idle_weight /= weight_sum;
-        forward_weight /= weight_sum;
-        back_weight /= weight_sum;
-        right_weight /= weight_sum;
-        left_weight /= weight_sum;
-
-        self.anim_weights.prev_idle_weight = max(self.anim_weights.prev_idle_weight, idle_weight);
-        self.anim_weights.prev_right_weight = max(self.anim_weights.prev_right_weight, right_weight);
-        self.anim_weights.prev_forward_weight = max(self.anim_weights.prev_forward_weight, forward_weight);
-        self.anim_weights.prev_back_weight = max(self.anim_weights.prev_back_weight, back_weight);
-        self.anim_weights.prev_left_weight = max(self.anim_weights.prev_left_weight, left_weight);
-
-        // weighted animations
-        [
-            WeightedAnimation::new(idle_weight, 55.0, 130.0, 0.0, 0.0),
-            WeightedAnimation::new(forward_weight, 134.0, 154.0, 0.0, 0.0),
-            WeightedAnimation::new(back_weight, 159.0, 179.0, 10.0, 0.0),
-            WeightedAnimation::new(right_weight, 184.0, 204.0, 10.0, 0.0),
-            WeightedAnimation::new(left_weight, 209.0, 229.0, 0.0, 0.0),
-            WeightedAnimation::new(dead_weight, 234.0, 293.0, 0.0, self.death_time),
-        ]
+        let mut animations = self.locomotion.weighted_animations(&self.animations, 1.0 - dead_weight);
+        if let Some(dead) = self.animations.definition(DEAD) {
+            animations.push(dead.weighted(dead_weight, self.death_time));
+        }
+        animations
     }
-}
 
-fn clamp0(value: f32) -> f32 {
-    if value < 0.0001 {
-        return 0.0;
+    // Collects the events of whichever clip the pose is mostly made of, so blended clips don't double up on footsteps.
+    fn update_animation_events(&mut self, previous_time: f32, frame_time: f32, dead_weight: f32) {
+        self.animation_events = if dead_weight >= 0.5 {
+            let since_death = (previous_time - self.death_time, frame_time - self.death_time);
+            self.animations
+                .definition(DEAD)
+                .map(|dead| dead.events_between(since_death.0, since_death.1))
+                .unwrap_or_default()
+        } else {
+            self.locomotion
+                .dominant_clip()
+                .and_then(|name| self.animations.definition(name))
+                .map(|clip| clip.events_between(previous_time, frame_time))
+                .unwrap_or_default()
+        };
+
+        if self.animation_events.iter().any(|event| event.as_ref() == DEATH_HIT_GROUND) {
+            self.death_landed = true;
+        }
     }
-    value
 }
 
-fn max(a: f32, b: f32) -> f32 {
-    if a > b {
-        a
-    } else {
-        b
+/// The player's movement clips, placed by direction relative to the legs.
+fn locomotion_blend_space() -> BlendSpace {
+    BlendSpace::new(
+        vec![
+            BlendClip::new(IDLE, Vec2::ZERO),
+            BlendClip::new(FORWARD, vec2(0.0, 1.0)),
+            BlendClip::new(BACK, vec2(0.0, -1.0)),
+            BlendClip::new(RIGHT, vec2(-1.0, 0.0)),
+            BlendClip::new(LEFT, vec2(1.0, 0.0)),
+        ],
+        ANIM_TRANSITION_TIME,
+    )
+}
+
+// Finds the model's bones and meshes named in the upper body mask, meshes first.
+fn resolve_upper_body(model: &Model, definition: &ModelDefinition) -> (Vec<(usize, f32)>, Vec<(usize, f32)>) {
+    let mut bones = vec![];
+    let mut meshes = vec![];
+    for (name, weight) in definition.upper_body.iter() {
+        if let Some(mesh) = find_mesh(model, name) {
+            meshes.push((mesh, *weight));
+        } else if let Some(bone) = find_bone(model, name) {
+            bones.push((bone, *weight));
+        } else {
+            warn!("player model has no bone or mesh '{}' for the upper body", name);
+        }
     }
+    (bones, meshes)
 }
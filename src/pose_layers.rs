@@ -0,0 +1,153 @@
+use glam::{Mat4, Quat, Vec2, Vec3};
+use std::f32::consts::{FRAC_PI_2, PI, TAU};
+
+//
+// Layers over the blended pose. The legs turn to follow the movement while the upper body twists back round
+// to the aim, and short additive poses, like recoil and flinch, lean it on top. The upper body is whatever the
+// model's bone mask covers, each bone taking its mask weight of the layers.
+//
+
+const MOVING_THRESHOLD: f32 = 0.1; // squared movement below which the legs stay put
+
+/// An additive pose that kicks in quickly and eases off, started by something like firing or being hit.
+#[derive(Debug, Clone, Copy)]
+pub struct AdditivePose {
+    /// Lean back at full strength, in radians.
+    pub pitch: f32,
+    /// Turn at full strength, in radians.
+    pub yaw: f32,
+    pub attack_time: f32,
+    pub recover_time: f32,
+}
+
+impl AdditivePose {
+    /// Range 0..1, `time` seconds after the pose started. Before it started is 0.
+    pub fn strength(&self, time: f32) -> f32 {
+        if time < 0.0 {
+            return 0.0;
+        }
+        if time < self.attack_time {
+            return time / self.attack_time;
+        }
+        if self.recover_time <= 0.0 {
+            return 0.0;
+        }
+        (1.0 - (time - self.attack_time) / self.recover_time).clamp(0.0, 1.0)
+    }
+
+    pub fn rotation(&self, time: f32) -> Quat {
+        let strength = self.strength(time);
+        Quat::from_rotation_y(self.yaw * strength) * Quat::from_rotation_x(-self.pitch * strength)
+    }
+}
+
+/// Takes a bone with `weight` of the upper body from the blended pose to the layered one. The additive rotation
+/// leans it about `pivot`, then `twist` turns it about the vertical through `pivot`, all in model space.
+pub fn upper_body_transform(pivot: Vec3, twist: f32, additive: Quat, weight: f32) -> Mat4 {
+    let rotation = Quat::from_rotation_y(twist * weight) * Quat::IDENTITY.slerp(additive, weight);
+    Mat4::from_translation(pivot) * Mat4::from_quat(rotation) * Mat4::from_translation(-pivot)
+}
+
+/// Turns the legs, facing `body`, towards the way they should face for `movement` in world x/z, by no more than
+/// `max_turn`. They face the movement, or away from it when backing off, but never further than `max_twist` from
+/// the aim. Angles are yaw in radians from +z towards +x.
+pub fn turn_body(body: f32, aim: f32, movement: Vec2, max_twist: f32, max_turn: f32) -> f32 {
+    let mut target = body;
+    if movement.length_squared() > MOVING_THRESHOLD {
+        let move_theta = movement.x.atan2(movement.y);
+        // walk backwards rather than turn the legs right round
+        target = if wrap_angle(move_theta - aim).abs() > FRAC_PI_2 {
+            move_theta + PI
+        } else {
+            move_theta
+        };
+    }
+
+    let target = aim + wrap_angle(target - aim).clamp(-max_twist, max_twist);
+    wrap_angle(body + wrap_angle(target - body).clamp(-max_turn, max_turn))
+}
+
+/// The same angle in -PI..PI.
+pub fn wrap_angle(angle: f32) -> f32 {
+    (angle + PI).rem_euclid(TAU) - PI
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::{vec2, vec3};
+
+    const MAX_TWIST: f32 = 1.0;
+
+    fn assert_angle(actual: f32, expected: f32) {
+        assert!(wrap_angle(actual - expected).abs() < 1e-4, "{} != {}", actual, expected);
+    }
+
+    #[test]
+    fn test_legs_follow_movement_within_the_twist() {
+        // standing still, the legs stay put until the aim drags them round
+        assert_angle(turn_body(0.5, 0.0, Vec2::ZERO, MAX_TWIST, 10.0), 0.5);
+        assert_angle(turn_body(0.0, 2.5, Vec2::ZERO, MAX_TWIST, 10.0), 1.5);
+
+        // moving forward of the aim, the legs face the movement, and no faster than the turn rate
+        assert_angle(turn_body(0.0, 0.0, vec2(0.5, 0.5).normalize(), MAX_TWIST, 10.0), PI / 4.0);
+        assert_angle(turn_body(0.0, 0.0, vec2(0.5, 0.5).normalize(), MAX_TWIST, 0.1), 0.1);
+
+        // backing off, they face away from the movement
+        assert_angle(turn_body(0.0, 0.0, vec2(-0.3, -1.0), MAX_TWIST, 10.0), (-0.3f32).atan2(-1.0) + PI);
+
+        // straight sideways is as far as the twist allows
+        assert_angle(turn_body(0.0, 0.0, vec2(1.0, 0.0), MAX_TWIST, 10.0), MAX_TWIST);
+
+        // across the wrap round
+        assert_angle(turn_body(3.0, -3.0, vec2(-0.01, -1.0), MAX_TWIST, 10.0), (-0.01f32).atan2(-1.0));
+    }
+
+    #[test]
+    fn test_additive_pose_strength() {
+        let recoil = AdditivePose {
+            pitch: 0.2,
+            yaw: 0.0,
+            attack_time: 0.05,
+            recover_time: 0.2,
+        };
+        assert_eq!(recoil.strength(-1.0), 0.0);
+        assert!((recoil.strength(0.025) - 0.5).abs() < 1e-5);
+        assert!((recoil.strength(0.05) - 1.0).abs() < 1e-5);
+        assert!((recoil.strength(0.15) - 0.5).abs() < 1e-5);
+        assert_eq!(recoil.strength(1.0), 0.0);
+        assert_eq!(recoil.rotation(1.0), Quat::IDENTITY);
+
+        let instant = AdditivePose {
+            attack_time: 0.0,
+            recover_time: 0.0,
+            ..recoil
+        };
+        assert_eq!(instant.strength(0.0), 0.0);
+    }
+
+    #[test]
+    fn test_upper_body_transform() {
+        let pivot = vec3(0.0, 100.0, 0.0);
+        let lean = Quat::from_rotation_x(-0.3);
+
+        // the pivot stays put and unmasked bones are left alone
+        let transform = upper_body_transform(pivot, 1.2, lean, 1.0);
+        assert!(transform.transform_point3(pivot).distance(pivot) < 1e-3);
+        assert!(upper_body_transform(pivot, 1.2, lean, 0.0).abs_diff_eq(Mat4::IDENTITY, 1e-5));
+
+        // a point ahead of the pivot turns to the aim
+        let ahead = transform.transform_point3(pivot + vec3(0.0, 0.0, 10.0)) - pivot;
+        let no_lean = upper_body_transform(pivot, 1.2, Quat::IDENTITY, 1.0).transform_point3(pivot + vec3(0.0, 0.0, 10.0)) - pivot;
+        assert_angle(no_lean.x.atan2(no_lean.z), 1.2);
+        assert!(ahead.distance(no_lean) > 1.0);
+
+        // above the pivot, the lean goes back from the aim
+        let head = transform.transform_point3(pivot + vec3(0.0, 50.0, 0.0)) - pivot;
+        assert!(head.dot(vec3(1.2f32.sin(), 0.0, 1.2f32.cos())) < 0.0);
+
+        // half weight turns half as far
+        let half = upper_body_transform(pivot, 1.2, Quat::IDENTITY, 0.5).transform_point3(pivot + vec3(0.0, 0.0, 10.0)) - pivot;
+        assert_angle(half.x.atan2(half.z), 0.6);
+    }
+}
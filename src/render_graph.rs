@@ -0,0 +1,375 @@
+use crate::framebuffers::{create_framebuffer, delete_framebuffer, FrameBuffer, TargetFormat};
+use glam::Vec4;
+use small_gl_core::gl;
+use small_gl_core::gl::GLsizei;
+use std::fmt::Debug;
+
+//
+// The frame as a list of passes, each drawing into a named target, or the screen, from the targets earlier
+// passes drew. The graph owns the targets, making them at the sizes they're declared at and remaking them
+// when the viewport changes, and for each pass binds its target, clears it and puts its inputs on texture
+// units. The drawing itself stays with the caller, which runs the enabled passes in order.
+//
+
+/// Texture units for pass inputs count up from here, clear of the units the models' materials use.
+pub const FIRST_INPUT_TEXTURE_UNIT: i32 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargetSize {
+    Fixed(i32, i32),
+    /// The viewport divided by `divisor`.
+    Viewport {
+        divisor: i32,
+    },
+}
+
+impl TargetSize {
+    pub fn resolve(self, viewport_width: i32, viewport_height: i32) -> (i32, i32) {
+        match self {
+            TargetSize::Fixed(width, height) => (width, height),
+            TargetSize::Viewport { divisor } => {
+                let divisor = divisor.max(1);
+                ((viewport_width / divisor).max(1), (viewport_height / divisor).max(1))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TargetDesc {
+    pub name: &'static str,
+    pub format: TargetFormat,
+    pub size: TargetSize,
+}
+
+/// What a pass clears its target to before drawing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Clear {
+    None,
+    Depth,
+    ColorAndDepth(Vec4),
+}
+
+#[derive(Debug, Clone)]
+pub struct PassDesc<P> {
+    pub pass: P,
+    pub inputs: Vec<&'static str>,
+    /// The target drawn into, `None` for the screen.
+    pub output: Option<&'static str>,
+    pub clear: Clear,
+    pub enabled: bool,
+}
+
+impl<P> PassDesc<P> {
+    pub fn new(pass: P, inputs: &[&'static str], output: Option<&'static str>, clear: Clear) -> Self {
+        Self {
+            pass,
+            inputs: inputs.to_vec(),
+            output,
+            clear,
+            enabled: true,
+        }
+    }
+
+    pub fn enabled(mut self, enabled: bool) -> Self {
+        self.enabled = enabled;
+        self
+    }
+}
+
+/// The texture units a pass's inputs were bound to.
+#[derive(Debug, Clone, Default)]
+pub struct PassInputs {
+    units: Vec<(&'static str, i32)>,
+}
+
+impl PassInputs {
+    /// The unit `input` is on, to set as a shader's sampler. Panics when the pass doesn't read `input`.
+    pub fn unit(&self, input: &str) -> i32 {
+        match self.units.iter().find(|(name, _)| *name == input) {
+            Some((_, unit)) => *unit,
+            None => panic!("the pass doesn't read '{}'", input),
+        }
+    }
+}
+
+struct Target {
+    desc: TargetDesc,
+    framebuffer: Option<FrameBuffer>,
+    size: (i32, i32),
+}
+
+pub struct RenderGraph<P> {
+    targets: Vec<Target>,
+    passes: Vec<PassDesc<P>>,
+    viewport: (i32, i32),
+}
+
+impl<P: Copy + PartialEq + Debug> RenderGraph<P> {
+    /// Passes run in the order given. Nothing is made until the first `resize`.
+    pub fn new(targets: Vec<TargetDesc>, passes: Vec<PassDesc<P>>) -> Self {
+        let graph = Self {
+            targets: targets
+                .into_iter()
+                .map(|desc| Target {
+                    desc,
+                    framebuffer: None,
+                    size: (0, 0),
+                })
+                .collect(),
+            passes,
+            viewport: (0, 0),
+        };
+        graph.warn_problems();
+        graph
+    }
+
+    /// The enabled passes, in the order to run them.
+    pub fn passes(&self) -> Vec<P> {
+        self.passes.iter().filter(|p| p.enabled).map(|p| p.pass).collect()
+    }
+
+    pub fn is_enabled(&self, pass: P) -> bool {
+        self.pass_desc(pass).is_some_and(|p| p.enabled)
+    }
+
+    /// Once the graph has been sized, the targets the pass now needs are made straight away and the ones only
+    /// disabled passes use are let go.
+    pub fn set_enabled(&mut self, pass: P, enabled: bool) {
+        for desc in self.passes.iter_mut().filter(|p| p.pass == pass) {
+            desc.enabled = enabled;
+        }
+        self.warn_problems();
+
+        if self.viewport != (0, 0) {
+            self.resize(self.viewport.0, self.viewport.1);
+        }
+    }
+
+    /// Makes the targets the enabled passes use at their sizes for the viewport, remaking any that have changed
+    /// size and letting go of any no longer used. Cheap when nothing has changed, so it can be called every frame.
+    pub fn resize(&mut self, viewport_width: i32, viewport_height: i32) {
+        self.viewport = (viewport_width, viewport_height);
+
+        let used: Vec<bool> = self.targets.iter().map(|t| self.is_target_used(t.desc.name)).collect();
+
+        for (target, used) in self.targets.iter_mut().zip(used) {
+            let size = target.desc.size.resolve(viewport_width, viewport_height);
+            if used && target.framebuffer.is_some() && target.size == size {
+                continue;
+            }
+            if let Some(framebuffer) = target.framebuffer.take() {
+                delete_framebuffer(&framebuffer);
+            }
+            if used {
+                target.framebuffer = Some(create_framebuffer(target.desc.format, size.0, size.1));
+                target.size = size;
+            }
+        }
+    }
+
+    /// Binds the pass's target with the viewport set to its size, clears it, and binds each input's texture to its unit.
+    pub fn begin_pass(&self, pass: P) -> PassInputs {
+        let Some(desc) = self.pass_desc(pass) else {
+            warn!("render graph has no pass {:?}", pass);
+            return PassInputs::default();
+        };
+
+        let (framebuffer_id, (width, height)) = match desc.output {
+            None => (0, self.viewport),
+            Some(name) => match self.target(name).and_then(|t| t.framebuffer.as_ref().map(|f| (f.framebuffer_id, t.size))) {
+                Some(output) => output,
+                None => {
+                    warn!("render graph pass {:?} draws to '{}', which hasn't been made", pass, name);
+                    (0, self.viewport)
+                }
+            },
+        };
+
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, framebuffer_id);
+            gl::Viewport(0, 0, width as GLsizei, height as GLsizei);
+            match desc.clear {
+                Clear::None => {}
+                Clear::Depth => gl::Clear(gl::DEPTH_BUFFER_BIT),
+                Clear::ColorAndDepth(color) => {
+                    gl::ClearColor(color.x, color.y, color.z, color.w);
+                    gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+                }
+            }
+        }
+
+        let inputs = self.input_units(pass);
+        for (name, unit) in inputs.units.iter() {
+            let texture_id = self.texture_id(name).unwrap_or(0);
+            unsafe {
+                gl::ActiveTexture(gl::TEXTURE0 + *unit as u32);
+                gl::BindTexture(gl::TEXTURE_2D, texture_id);
+            }
+        }
+        inputs
+    }
+
+    /// The texture of a target that has been made.
+    pub fn texture_id(&self, name: &str) -> Option<u32> {
+        self.target(name)?.framebuffer.as_ref().map(|f| f.texture_id)
+    }
+
+    /// The units the pass's inputs go on, in the order it lists them.
+    pub fn input_units(&self, pass: P) -> PassInputs {
+        let units = self
+            .pass_desc(pass)
+            .map(|desc| desc.inputs.iter().zip(FIRST_INPUT_TEXTURE_UNIT..).map(|(name, unit)| (*name, unit)).collect())
+            .unwrap_or_default();
+        PassInputs { units }
+    }
+
+    /// Mistakes in the declaration: targets that aren't declared, and inputs no earlier enabled pass draws.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+        let mut drawn: Vec<&str> = vec![];
+
+        for (index, desc) in self.passes.iter().enumerate() {
+            if self.passes[..index].iter().any(|p| p.pass == desc.pass) {
+                problems.push(format!("pass {:?} is declared more than once", desc.pass));
+            }
+
+            for name in desc.inputs.iter().chain(desc.output.iter()) {
+                if self.target(name).is_none() {
+                    problems.push(format!("pass {:?} uses '{}', which isn't a target", desc.pass, name));
+                }
+            }
+
+            if !desc.enabled {
+                continue;
+            }
+
+            for name in desc.inputs.iter() {
+                if desc.output == Some(*name) {
+                    problems.push(format!("pass {:?} reads '{}' while drawing to it", desc.pass, name));
+                } else if !drawn.contains(name) {
+                    problems.push(format!("pass {:?} reads '{}' before any pass draws it", desc.pass, name));
+                }
+            }
+            drawn.extend(desc.output.iter());
+        }
+
+        problems
+    }
+
+    fn warn_problems(&self) {
+        for problem in self.problems() {
+            warn!("render graph: {}", problem);
+        }
+    }
+
+    fn pass_desc(&self, pass: P) -> Option<&PassDesc<P>> {
+        self.passes.iter().find(|p| p.pass == pass)
+    }
+
+    fn target(&self, name: &str) -> Option<&Target> {
+        self.targets.iter().find(|t| t.desc.name == name)
+    }
+
+    fn is_target_used(&self, name: &str) -> bool {
+        self.passes
+            .iter()
+            .filter(|p| p.enabled)
+            .any(|p| p.output == Some(name) || p.inputs.contains(&name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::framebuffers::{DepthBuffer, Edge};
+
+    const COLOR: TargetFormat = TargetFormat::Color {
+        edge: Edge::Clamp,
+        depth: DepthBuffer::None,
+    };
+
+    fn bloom(blur_enabled: bool) -> RenderGraph<&'static str> {
+        RenderGraph::new(
+            vec![
+                TargetDesc {
+                    name: "shadow",
+                    format: TargetFormat::Depth,
+                    size: TargetSize::Fixed(1024, 1024),
+                },
+                TargetDesc {
+                    name: "scene",
+                    format: COLOR,
+                    size: TargetSize::Viewport { divisor: 1 },
+                },
+                TargetDesc {
+                    name: "blur",
+                    format: COLOR,
+                    size: TargetSize::Viewport { divisor: 2 },
+                },
+            ],
+            vec![
+                PassDesc::new("shadow", &[], Some("shadow"), Clear::Depth),
+                PassDesc::new("scene", &["shadow"], Some("scene"), Clear::ColorAndDepth(Vec4::ZERO)),
+                PassDesc::new("blur", &["scene"], Some("blur"), Clear::None).enabled(blur_enabled),
+                PassDesc::new("merge", &["scene", "blur"], None, Clear::None).enabled(blur_enabled),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_passes_in_declared_order() {
+        let mut graph = bloom(true);
+        assert_eq!(graph.passes(), ["shadow", "scene", "blur", "merge"]);
+        assert!(graph.problems().is_empty(), "{:?}", graph.problems());
+
+        graph.set_enabled("blur", false);
+        assert_eq!(graph.passes(), ["shadow", "scene", "merge"]);
+        assert!(!graph.is_enabled("blur"));
+        assert_eq!(graph.problems().len(), 1);
+        assert!(graph.problems()[0].contains("\"merge\" reads 'blur'"), "{:?}", graph.problems());
+
+        graph.set_enabled("merge", false);
+        assert!(graph.problems().is_empty(), "{:?}", graph.problems());
+        assert!(bloom(false).problems().is_empty());
+    }
+
+    #[test]
+    fn test_declaration_problems() {
+        let graph = RenderGraph::new(
+            vec![TargetDesc {
+                name: "scene",
+                format: COLOR,
+                size: TargetSize::Viewport { divisor: 1 },
+            }],
+            vec![
+                PassDesc::new("early", &["scene"], None, Clear::None),
+                PassDesc::new("scene", &["scene"], Some("scene"), Clear::None),
+                PassDesc::new("typo", &["secne"], None, Clear::None),
+            ],
+        );
+        let problems = graph.problems();
+        assert_eq!(problems.len(), 4, "{:?}", problems);
+        assert!(problems[0].contains("\"early\" reads 'scene' before"));
+        assert!(problems[1].contains("while drawing to it"));
+        assert!(problems[2].contains("isn't a target"));
+        assert!(problems[3].contains("\"typo\" reads 'secne' before"));
+    }
+
+    #[test]
+    fn test_target_sizes_and_input_units() {
+        assert_eq!(TargetSize::Fixed(1024, 512).resolve(1500, 1000), (1024, 512));
+        assert_eq!(TargetSize::Viewport { divisor: 1 }.resolve(1500, 1000), (1500, 1000));
+        assert_eq!(TargetSize::Viewport { divisor: 2 }.resolve(1501, 1000), (750, 500));
+        assert_eq!(TargetSize::Viewport { divisor: 0 }.resolve(0, 3), (1, 3));
+
+        let graph = bloom(true);
+        let merge = graph.input_units("merge");
+        assert_eq!(merge.unit("scene"), FIRST_INPUT_TEXTURE_UNIT);
+        assert_eq!(merge.unit("blur"), FIRST_INPUT_TEXTURE_UNIT + 1);
+        assert_eq!(graph.input_units("scene").unit("shadow"), FIRST_INPUT_TEXTURE_UNIT);
+
+        // nothing is made before the first resize
+        assert_eq!(graph.texture_id("scene"), None);
+    }
+}
@@ -0,0 +1,44 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Seeded random numbers for the simulation. Networked peers share the seed so
+/// enemy spawns and bullet spread come out the same on every machine.
+pub struct SimRng {
+    state: u64,
+}
+
+impl SimRng {
+    pub const fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    /// One of many independent sequences drawn from the same seed, such as one per enemy, so things updated in
+    /// parallel get the same numbers whatever order they run in.
+    pub fn stream(seed: u64, stream: u64) -> Self {
+        let mut mixer = Self::new(seed ^ stream.wrapping_mul(0xD1B5_4A32_D192_ED03));
+        Self::new(mixer.next_u64())
+    }
+
+    pub fn from_time() -> Self {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos() as u64).unwrap_or_default();
+        Self::new(nanos)
+    }
+
+    // splitmix64
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Range 0..1
+    pub fn rand_float(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    /// Range -1..1
+    pub fn random_clamped(&mut self) -> f32 {
+        self.rand_float().mul_add(2.0, -1.0)
+    }
+}
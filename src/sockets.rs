@@ -0,0 +1,122 @@
+use crate::model_definition::{SocketDefinition, SocketParent};
+use glam::{Mat4, Vec3};
+use small_gl_core::hash_map::HashMap;
+use small_gl_core::model::Model;
+use std::rc::Rc;
+
+//
+// Named attachment points on a model, like the muzzle of its gun or a hand, found in world space from the
+// model's current pose. Effects and props ask for a socket by name instead of knowing the model's bones
+// and meshes, which live in the model's definition file.
+//
+
+#[derive(Debug, Clone, Copy)]
+enum Attachment {
+    Bone(usize),
+    Mesh(usize),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Socket {
+    attachment: Attachment,
+    offset: Vec3,
+}
+
+pub struct Sockets {
+    sockets: HashMap<Rc<str>, Socket>,
+}
+
+impl Sockets {
+    /// Finds each socket's bone or mesh in the model. Sockets on parts the model doesn't have are left out with a warning.
+    pub fn new(model: &Model, definitions: &[SocketDefinition]) -> Self {
+        let mut sockets = HashMap::new();
+
+        for definition in definitions.iter() {
+            let attachment = match definition.parent {
+                SocketParent::Bone => find_bone(model, &definition.parent_name).map(Attachment::Bone),
+                SocketParent::Mesh => find_mesh(model, &definition.parent_name).map(Attachment::Mesh),
+            };
+            let Some(attachment) = attachment else {
+                warn!(
+                    "socket '{}' is on {:?} '{}', which the model doesn't have",
+                    definition.name, definition.parent, definition.parent_name
+                );
+                continue;
+            };
+            sockets.insert(
+                definition.name.clone(),
+                Socket {
+                    attachment,
+                    offset: definition.offset,
+                },
+            );
+        }
+
+        Self { sockets }
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.sockets.contains_key(name)
+    }
+
+    /// The socket in world space on `model` drawn with `model_transform`, as the model was last posed.
+    /// `None` when the model has no such socket.
+    pub fn transform(&self, model: &Model, name: &str, model_transform: &Mat4) -> Option<Mat4> {
+        let socket = self.sockets.get(name)?;
+
+        let animator = model.animator.borrow();
+        let parent_transform = match socket.attachment {
+            Attachment::Bone(index) => *animator.final_bone_matrices.borrow().get(index)?,
+            Attachment::Mesh(index) => *animator.final_node_matrices.borrow().get(index)?,
+        };
+
+        Some(posed_socket(model_transform, &parent_transform, socket.offset))
+    }
+
+    pub fn position(&self, model: &Model, name: &str, model_transform: &Mat4) -> Option<Vec3> {
+        self.transform(model, name, model_transform)
+            .map(|transform| transform.transform_point3(Vec3::ZERO))
+    }
+}
+
+// `parent_transform` takes the socket's parent from where the offset is given to where it is posed. For a bone that is
+// from the bind pose, so a bone socket's offset is a bind pose position in model space. For a mesh it is from the
+// mesh's own space.
+fn posed_socket(model_transform: &Mat4, parent_transform: &Mat4, offset: Vec3) -> Mat4 {
+    *model_transform * *parent_transform * Mat4::from_translation(offset)
+}
+
+/// Index of the bone's matrix in the model's final bone matrices.
+pub fn find_bone(model: &Model, name: &str) -> Option<usize> {
+    let animator = model.animator.borrow();
+    let bone_data_map = animator.bone_data_map.borrow();
+    bone_data_map.get(name).map(|bone| bone.bone_index as usize)
+}
+
+/// Index of the mesh's matrix in the model's final node matrices.
+pub fn find_mesh(model: &Model, name: &str) -> Option<usize> {
+    model.meshes.iter().find(|m| m.name.as_str() == name).map(|mesh| mesh.id as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::{vec3, Quat};
+
+    #[test]
+    fn test_bone_socket_follows_its_bone() {
+        // a head socket a little above the neck joint in the bind pose
+        let joint = vec3(0.0, 150.0, 0.0);
+        let offset = vec3(0.0, 165.0, 0.0);
+        let model_transform = Mat4::from_translation(vec3(5.0, 0.0, -2.0)) * Mat4::from_scale(Vec3::splat(0.01));
+
+        let position = |bone_matrix: &Mat4| posed_socket(&model_transform, bone_matrix, offset).transform_point3(Vec3::ZERO);
+
+        // in the bind pose the bone's final matrix is the identity, and the socket is where it was given
+        assert!(position(&Mat4::IDENTITY).abs_diff_eq(vec3(5.0, 1.65, -2.0), 1e-5));
+
+        // the bone tipping forward a quarter turn about its joint carries the socket round with it
+        let tipped = Mat4::from_translation(joint) * Mat4::from_quat(Quat::from_rotation_x(std::f32::consts::FRAC_PI_2)) * Mat4::from_translation(-joint);
+        assert!(position(&tipped).abs_diff_eq(vec3(5.0, 1.5, -1.85), 1e-5), "{}", position(&tipped));
+    }
+}
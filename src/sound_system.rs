@@ -1,5 +1,6 @@
 use log::warn;
-use rodio::{Decoder, OutputStream, OutputStreamHandle, Sink};
+use rodio::source::Buffered;
+use rodio::{Decoder, OutputStream, OutputStreamHandle, Sink, Source};
 use std::fs::File;
 use std::io::{BufReader, Cursor, Read};
 use std::sync::Arc;
@@ -62,14 +63,39 @@ impl AudioSource {
 
         Self { bytes: bytes.into() }
     }
+
+    /// Reads and decodes a sound the game can do without, once, so playing it can't fail. A missing or undecodable
+    /// file is only a warning.
+    fn load_decoded(filename: &str) -> Option<DecodedSound> {
+        let bytes: Arc<[u8]> = match std::fs::read(filename) {
+            Ok(bytes) => bytes.into(),
+            Err(e) => {
+                warn!("could not load sound {}: {}", filename, e);
+                return None;
+            }
+        };
+        match Decoder::new(Cursor::new(bytes)) {
+            Ok(decoder) => Some(decoder.buffered()),
+            Err(e) => {
+                warn!("could not decode sound {}: {}", filename, e);
+                None
+            }
+        }
+    }
 }
 
+/// A decoded sound, its samples are kept after the first play and shared by the clones.
+type DecodedSound = Buffered<Decoder<Cursor<Arc<[u8]>>>>;
+
 pub struct SoundSystem {
     audio_output: AudioOutput,
     bullet_sink: Sink,
     explosion_sink: Sink,
+    aoe_explosion_sink: Sink,
+    footstep_sink: Sink,
     player_shooting_source: AudioSource,
     enemy_destroyed_source: AudioSource,
+    footstep_source: Option<DecodedSound>,
 }
 
 impl SoundSystem {
@@ -77,19 +103,27 @@ impl SoundSystem {
         let audio_output = AudioOutput::default();
         let bullet_sink = Sink::try_new(audio_output.stream_handle.as_ref().unwrap()).unwrap();
         let explosion_sink = Sink::try_new(audio_output.stream_handle.as_ref().unwrap()).unwrap();
+        let aoe_explosion_sink = Sink::try_new(audio_output.stream_handle.as_ref().unwrap()).unwrap();
+        let footstep_sink = Sink::try_new(audio_output.stream_handle.as_ref().unwrap()).unwrap();
 
         bullet_sink.set_speed(1.5);
         explosion_sink.set_speed(2.0);
+        aoe_explosion_sink.set_speed(0.8); // slower for a deeper boom
+        footstep_sink.set_volume(0.5);
 
         let player_shooting_source = AudioSource::new("assets/Audio/Player_SFX/player_shooting_one.wav");
         let enemy_destroyed_source = AudioSource::new("assets/Audio/Enemy_SFX/enemy_Spider_DestroyedExplosion.wav");
+        let footstep_source = AudioSource::load_decoded("assets/Audio/Player_SFX/player_footstep.wav");
 
         Self {
             audio_output,
             bullet_sink,
             explosion_sink,
+            aoe_explosion_sink,
+            footstep_sink,
             player_shooting_source,
             enemy_destroyed_source,
+            footstep_source,
         }
     }
 
@@ -108,4 +142,21 @@ impl SoundSystem {
         self.explosion_sink.append(source);
         self.explosion_sink.play();
     }
+
+    pub fn play_explosion(&self) {
+        let data = self.enemy_destroyed_source.bytes.clone();
+        let source = Decoder::new(Cursor::new(data)).unwrap();
+        self.aoe_explosion_sink.clear();
+        self.aoe_explosion_sink.append(source);
+        self.aoe_explosion_sink.play();
+    }
+
+    pub fn play_footstep(&self) {
+        let Some(footstep_source) = self.footstep_source.as_ref() else {
+            return;
+        };
+        self.footstep_sink.clear();
+        self.footstep_sink.append(footstep_source.clone());
+        self.footstep_sink.play();
+    }
 }
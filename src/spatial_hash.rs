@@ -0,0 +1,158 @@
+use glam::Vec3;
+
+//
+// Uniform grid over the floor (x/z) for finding what is near a point without checking everything.
+// Rebuilt each tick, so entries are plain indices into whatever list was inserted, such as `State::enemies`.
+// The cells are stored densely over the occupied range, as one list of entries sorted by cell, so a query is a few
+// slice reads and a rebuild reuses the last tick's buffers.
+//
+
+// Past this many cells the grid coarsens, so a stray far away position can't make it huge.
+const MAX_CELLS: usize = 1 << 16;
+
+pub struct SpatialHash {
+    cell_size: f32,
+    // of the cells in use, doubled from `cell_size` while the occupied range needs more than MAX_CELLS
+    inverse_cell_size: f32,
+    // the lowest x and z inserted, the corner of cell (0, 0)
+    origin: (f32, f32),
+    width: i32,
+    height: i32,
+    // entries of cell i are entries[cell_starts[i]..cell_starts[i + 1]], cells row by row in z
+    cell_starts: Vec<usize>,
+    entries: Vec<usize>,
+    entry_cells: Vec<usize>,
+}
+
+impl SpatialHash {
+    pub fn new(cell_size: f32) -> Self {
+        Self {
+            cell_size,
+            inverse_cell_size: 1.0 / cell_size,
+            origin: (0.0, 0.0),
+            width: 0,
+            height: 0,
+            cell_starts: vec![],
+            entries: vec![],
+            entry_cells: vec![],
+        }
+    }
+
+    /// Empties the grid, keeping the allocations for the next rebuild.
+    pub fn clear(&mut self) {
+        self.width = 0;
+        self.height = 0;
+        self.cell_starts.clear();
+        self.entries.clear();
+        self.entry_cells.clear();
+    }
+
+    /// Clears and refills the grid, each position is inserted under its index.
+    pub fn rebuild(&mut self, positions: impl Iterator<Item = Vec3> + Clone) {
+        self.clear();
+
+        let (min, max) = positions.clone().fold((Vec3::MAX, Vec3::MIN), |(min, max), p| (min.min(p), max.max(p)));
+        if min.x > max.x {
+            return;
+        }
+
+        let mut cell_size = self.cell_size;
+        while ((max.x - min.x) / cell_size + 1.0) * ((max.z - min.z) / cell_size + 1.0) > MAX_CELLS as f32 {
+            cell_size *= 2.0;
+        }
+        self.inverse_cell_size = 1.0 / cell_size;
+        self.origin = (min.x, min.z);
+        self.width = ((max.x - min.x) * self.inverse_cell_size) as i32 + 1;
+        self.height = ((max.z - min.z) * self.inverse_cell_size) as i32 + 1;
+
+        // counting sort of the entries by cell: count, add up to each cell's end, then fill each cell from its end
+        // down, which leaves the starts behind and each cell's indices ascending
+        self.cell_starts.resize((self.width * self.height) as usize + 1, 0);
+        for position in positions {
+            let cell = self.cell_index(self.column(position.x), self.row(position.z));
+            self.entry_cells.push(cell);
+            self.cell_starts[cell] += 1;
+        }
+        for i in 1..self.cell_starts.len() {
+            self.cell_starts[i] += self.cell_starts[i - 1];
+        }
+
+        self.entries.resize(self.entry_cells.len(), 0);
+        for (index, &cell) in self.entry_cells.iter().enumerate().rev() {
+            self.cell_starts[cell] -= 1;
+            self.entries[self.cell_starts[cell]] = index;
+        }
+    }
+
+    /// Fills `found` with the indices in the cells within `radius` of the position, in ascending order.
+    /// These are candidates, callers still do the exact test.
+    pub fn query(&self, position: Vec3, radius: f32, found: &mut Vec<usize>) {
+        self.query_box(position - radius, position + radius, found);
+    }
+
+    /// Like `query`, for the cells overlapping a box on the floor. Only x and z are used.
+    pub fn query_box(&self, min: Vec3, max: Vec3, found: &mut Vec<usize>) {
+        found.clear();
+
+        // also empty when the box is off the grid's low side, where the casts round its far edge up to zero
+        if max.x < self.origin.0 || max.z < self.origin.1 {
+            return;
+        }
+        let (x_min, x_max) = (self.column(min.x), self.column(max.x).min(self.width - 1));
+        let (z_min, z_max) = (self.row(min.z), self.row(max.z).min(self.height - 1));
+
+        let mut rows_found = 0;
+        for z in z_min..=z_max {
+            if x_max < x_min {
+                break;
+            }
+            // a row's cells are next to each other, so its entries are one slice
+            let entries = &self.entries[self.cell_starts[self.cell_index(x_min, z)]..self.cell_starts[self.cell_index(x_max, z) + 1]];
+            if !entries.is_empty() {
+                found.extend_from_slice(entries);
+                rows_found += 1;
+            }
+        }
+
+        // each index is in one cell, sorting keeps hits in the same order as a plain loop over the list
+        if rows_found > 1 {
+            found.sort_unstable();
+        }
+    }
+
+    // Cell coordinates, clamped to zero below the grid. The casts round toward zero and saturate, so no floor is needed.
+    fn column(&self, x: f32) -> i32 {
+        ((x - self.origin.0) * self.inverse_cell_size).max(0.0) as i32
+    }
+
+    fn row(&self, z: f32) -> i32 {
+        ((z - self.origin.1) * self.inverse_cell_size).max(0.0) as i32
+    }
+
+    fn cell_index(&self, x: i32, z: i32) -> usize {
+        (z * self.width + x) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_query_finds_nearby_only() {
+        use super::SpatialHash;
+        use glam::vec3;
+
+        let mut grid = SpatialHash::new(1.0);
+        grid.rebuild([vec3(0.5, 0.0, 0.5), vec3(-0.2, 0.0, 0.1), vec3(5.0, 0.0, 5.0), vec3(1.4, 0.0, -0.3)].into_iter());
+
+        let mut found = vec![];
+        grid.query(vec3(0.0, 0.0, 0.0), 0.5, &mut found);
+        assert_eq!(found, vec![0, 1]);
+
+        grid.query(vec3(0.0, 0.0, 0.0), 1.5, &mut found);
+        assert_eq!(found, vec![0, 1, 3]);
+
+        grid.rebuild([vec3(5.0, 0.0, 5.0)].into_iter());
+        grid.query(vec3(0.0, 0.0, 0.0), 1.5, &mut found);
+        assert!(found.is_empty());
+    }
+}
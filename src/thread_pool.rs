@@ -0,0 +1,209 @@
+use std::ops::Range;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{channel, SendError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+//
+// Spreads slice work across worker threads. Work is split into contiguous chunks and the results are put
+// back together in index order, so the outcome is the same for any number of workers, including one.
+// The workers are started once and wait for jobs, so a call only pays for handing the work over. Each call
+// waits for all of its jobs before returning, which lets the work borrow the game state. Calls with too
+// little work to be worth handing over run on the calling thread.
+//
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+pub struct ThreadPool {
+    num_workers: usize,
+    jobs: Option<Sender<Job>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl ThreadPool {
+    pub fn new(num_workers: usize) -> Self {
+        let num_workers = num_workers.max(1);
+        let (jobs, receiver) = channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        // the calling thread works too, so it needs one less
+        let workers = (1..num_workers)
+            .map(|index| {
+                let receiver = Arc::clone(&receiver);
+                thread::Builder::new()
+                    .name(format!("worker {}", index))
+                    .spawn(move || loop {
+                        let job = receiver.lock().unwrap().recv();
+                        match job {
+                            Ok(job) => job(),
+                            // the pool was dropped
+                            Err(_) => break,
+                        }
+                    })
+                    .expect("Failed to start a worker thread")
+            })
+            .collect();
+
+        Self {
+            num_workers,
+            jobs: Some(jobs),
+            workers,
+        }
+    }
+
+    pub fn num_workers(&self) -> usize {
+        self.num_workers
+    }
+
+    /// Chunk length that shares `len` items across the workers, but not below `min_chunk_len`.
+    pub fn chunk_len(&self, len: usize, min_chunk_len: usize) -> usize {
+        len.div_ceil(self.num_workers).max(min_chunk_len).max(1)
+    }
+
+    /// Runs each task, spread across the workers.
+    pub fn for_each<T: Send>(&self, tasks: Vec<T>, f: impl Fn(T) + Sync) {
+        if self.num_workers == 1 || tasks.len() <= 1 {
+            tasks.into_iter().for_each(f);
+            return;
+        }
+
+        let f = &f;
+        self.run(
+            tasks
+                .into_iter()
+                .map(|task| Box::new(move || f(task)) as Box<dyn FnOnce() + Send + '_>)
+                .collect(),
+        );
+    }
+
+    /// Calls `f` on contiguous ranges covering `0..len` and joins the results in range order.
+    pub fn map_chunks<R: Send>(&self, len: usize, min_chunk_len: usize, f: impl Fn(Range<usize>) -> Vec<R> + Sync) -> Vec<R> {
+        let chunk_len = self.chunk_len(len, min_chunk_len);
+        let ranges: Vec<Range<usize>> = (0..len).step_by(chunk_len).map(|start| start..(start + chunk_len).min(len)).collect();
+
+        if self.num_workers == 1 || ranges.len() <= 1 {
+            return ranges.into_iter().flat_map(f).collect();
+        }
+
+        let f = &f;
+        let mut results: Vec<Vec<R>> = ranges.iter().map(|_| vec![]).collect();
+        self.run(
+            ranges
+                .into_iter()
+                .zip(results.iter_mut())
+                .map(|(range, result)| Box::new(move || *result = f(range)) as Box<dyn FnOnce() + Send + '_>)
+                .collect(),
+        );
+
+        let mut joined = Vec::with_capacity(results.iter().map(Vec::len).sum());
+        for result in results.iter_mut() {
+            joined.append(result);
+        }
+        joined
+    }
+
+    // Hands all but the last task to the workers, runs the last one here, then waits for the rest. A panic in any
+    // task is passed on to the caller once they have all finished.
+    fn run<'a>(&self, mut tasks: Vec<Box<dyn FnOnce() + Send + 'a>>) {
+        let Some(last) = tasks.pop() else {
+            return;
+        };
+
+        let (done, finished) = channel();
+        let handed_out = tasks.len();
+
+        for task in tasks {
+            let done = done.clone();
+            let job: Box<dyn FnOnce() + Send + 'a> = Box::new(move || {
+                let _ = done.send(panic::catch_unwind(AssertUnwindSafe(task)));
+            });
+            // SAFETY: the job may borrow from the caller for 'a. This function doesn't return until every job it
+            // handed out has reported back, panics included, so nothing the job borrows goes away while it runs.
+            let job: Job = unsafe { std::mem::transmute::<Box<dyn FnOnce() + Send + 'a>, Job>(job) };
+
+            match &self.jobs {
+                Some(jobs) => {
+                    if let Err(SendError(job)) = jobs.send(job) {
+                        job();
+                    }
+                }
+                None => job(),
+            }
+        }
+
+        let mut outcome = panic::catch_unwind(AssertUnwindSafe(last));
+        for _ in 0..handed_out {
+            let result = finished.recv().expect("A job finished without reporting back");
+            outcome = outcome.and(result);
+        }
+
+        if let Err(payload) = outcome {
+            panic::resume_unwind(payload);
+        }
+    }
+}
+
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        // closing the channel lets the workers finish
+        self.jobs = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_results_match_single_worker() {
+        let work = |range: Range<usize>| range.map(|i| (i as f32).sqrt().sin()).collect::<Vec<f32>>();
+
+        let single = ThreadPool::new(1).map_chunks(10_000, 100, work);
+        for workers in [2, 3, 4, 7] {
+            assert_eq!(ThreadPool::new(workers).map_chunks(10_000, 100, work), single);
+        }
+    }
+
+    #[test]
+    fn test_for_each_runs_every_task() {
+        let seen = Mutex::new(vec![]);
+        ThreadPool::new(4).for_each((0..10).collect(), |i| seen.lock().unwrap().push(i));
+
+        let mut seen = seen.into_inner().unwrap();
+        seen.sort_unstable();
+        assert_eq!(seen, (0..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_workers_are_reused() {
+        let pool = ThreadPool::new(4);
+        let threads = Mutex::new(HashSet::new());
+
+        for _ in 0..20 {
+            pool.for_each((0..8).collect(), |_: i32| {
+                threads.lock().unwrap().insert(thread::current().id());
+            });
+        }
+
+        // the three workers and the calling thread, however many calls were made
+        let threads = threads.into_inner().unwrap();
+        assert!(threads.len() <= 4, "{} threads ran the work", threads.len());
+    }
+
+    #[test]
+    fn test_a_panicking_task_reaches_the_caller() {
+        let pool = ThreadPool::new(3);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            pool.for_each((0..6).collect(), |i: i32| assert!(i != 2, "task {} failed", i));
+        }));
+        assert!(result.is_err());
+
+        // and the pool still works afterwards
+        assert_eq!(pool.map_chunks(10, 1, |range| range.collect::<Vec<_>>()), (0..10).collect::<Vec<_>>());
+    }
+}